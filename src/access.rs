@@ -0,0 +1,213 @@
+use crate::tree::TreeItemRefCell;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::rc::Rc;
+
+/// A user for `--as-user`, with the ids of all their groups.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct User {
+    pub(crate) name: String,
+    uid: u32,
+    /// The primary group first, then the supplementary ones
+    groups: Vec<u32>,
+}
+
+impl User {
+    /// Looks up the user `name` in `/etc/passwd` and their groups in `/etc/group`. Users from
+    /// directory services like LDAP aren't listed there and can't be looked up.
+    pub(crate) fn lookup(name: &str) -> io::Result<User> {
+        let passwd = fs::read_to_string("/etc/passwd")?;
+        let group = fs::read_to_string("/etc/group").unwrap_or_default();
+        User::parse(name, &passwd, &group)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no user '{}' in /etc/passwd", name)))
+    }
+
+    /// Finds `name` in lines like `bob:x:1001:1001:Bob:/home/bob:/bin/sh` of `passwd`, and the
+    /// groups listing it in lines like `staff:x:50:alice,bob` of `group`.
+    fn parse(name: &str, passwd: &str, group: &str) -> Option<User> {
+        let (uid, gid) = passwd.lines().find_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            (fields.len() > 3 && fields[0] == name).then(|| Some((fields[2].parse().ok()?, fields[3].parse().ok()?)))?
+        })?;
+        let mut groups = vec![gid];
+        for line in group.lines() {
+            let fields: Vec<&str> = line.split(':').collect();
+            if fields.len() > 3 && fields[3].split(',').any(|member| member.trim() == name) {
+                groups.extend(fields[2].parse::<u32>().ok().filter(|gid| !groups.contains(gid)));
+            }
+        }
+        Some(User { name: name.to_string(), uid, groups })
+    }
+
+    /// Returns the read (4), write (2) and execute (1) bits of `mode` that apply to this user for
+    /// an entry owned by `uid` and `gid`. The superuser may read and enter everything.
+    fn permission_bits(&self, mode: u32, uid: u32, gid: u32) -> u32 {
+        if self.uid == 0 {
+            0o7
+        } else if uid == self.uid {
+            (mode >> 6) & 0o7
+        } else if self.groups.contains(&gid) {
+            (mode >> 3) & 0o7
+        } else {
+            mode & 0o7
+        }
+    }
+
+    /// Returns what this user can't do with an entry, e.g. `read` a file or `enter` a directory.
+    fn denial(&self, (mode, uid, gid): (u32, u32, u32), is_dir: bool) -> Option<&'static str> {
+        let bits = self.permission_bits(mode, uid, gid);
+        match (is_dir, bits & 0o4 != 0, bits & 0o1 != 0) {
+            (true, _, false) => Some("enter"),
+            (true, false, true) => Some("list"),
+            (false, false, _) => Some("read"),
+            _ => None,
+        }
+    }
+}
+
+///
+/// Fails unless ftree runs as root on Unix, which `--as-user` needs: other users only see the
+/// entries they can read themselves, so the tree would miss what `user` can access beyond them,
+/// and other platforms don't have Unix permissions.
+///
+pub(crate) fn check_superuser() -> io::Result<()> {
+    if cfg!(not(unix)) {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "--as-user needs Unix permissions"));
+    }
+    require_superuser(effective_uid()?)
+}
+
+fn require_superuser(euid: u32) -> io::Result<()> {
+    match euid {
+        0 => Ok(()),
+        _ => Err(io::Error::new(io::ErrorKind::PermissionDenied, "--as-user needs to run as root, e.g. with sudo")),
+    }
+}
+
+/// Returns the effective user id of this process, as printed by `id -u`.
+fn effective_uid() -> io::Result<u32> {
+    let output = Command::new("id").arg("-u").output()?;
+    let uid = String::from_utf8_lossy(&output.stdout).trim().parse().ok();
+    uid.filter(|_| output.status.success()).ok_or_else(|| io::Error::other("can't read the user id from id -u"))
+}
+
+///
+/// Annotates the entries below and including `root` that `user` can't access, judged by the
+/// permission bits, the owner and the group, e.g. `secrets/ [bob can't enter]`,
+/// `app.log [bob can't read]` or `uploads/ [bob can't list]`. Entries inside a directory the
+/// user can't enter aren't annotated, as the directory already explains it. If a directory
+/// above `root` blocks the way, only `root` is annotated with `[bob can't reach]`.
+///
+/// ACLs and capabilities aren't taken into account. Does nothing on platforms without Unix
+/// permissions.
+///
+pub(crate) fn annotate_access(root: &Rc<TreeItemRefCell>, user: &User) {
+    let path = root.borrow().path();
+    let ancestors = fs::canonicalize(&path).ok().map(|canonical| canonical.ancestors().skip(1).map(Path::to_path_buf).collect::<Vec<_>>());
+    let blocked = ancestors.unwrap_or_default().iter().any(|dir| owner_mode(dir).is_some_and(|stat| user.denial(stat, true) == Some("enter")));
+    if blocked {
+        root.borrow_mut().annotations.push(format!("[{} can't reach]", user.name));
+        return;
+    }
+    annotate_item(root, user);
+}
+
+fn annotate_item(item: &Rc<TreeItemRefCell>, user: &User) {
+    let (path, is_dir) = (item.borrow().path(), item.borrow().is_dir);
+    let denial = owner_mode(&path).and_then(|stat| user.denial(stat, is_dir));
+    if let Some(denial) = denial {
+        item.borrow_mut().annotations.push(format!("[{} can't {}]", user.name, denial));
+    }
+    if denial == Some("enter") {
+        return;
+    }
+    for child in &item.borrow().children {
+        annotate_item(child, user);
+    }
+}
+
+/// Returns the mode, owner and group of the entry at `path`, following symbolic links.
+#[cfg(unix)]
+fn owner_mode(path: &Path) -> Option<(u32, u32, u32)> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|meta| (meta.mode(), meta.uid(), meta.gid()))
+}
+
+#[cfg(not(unix))]
+fn owner_mode(_path: &Path) -> Option<(u32, u32, u32)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWD: &str = "root:x:0:0:root:/root:/bin/bash\nbob:x:1001:1001:Bob:/home/bob:/bin/sh\n";
+    const GROUP: &str = "root:x:0:\nbob:x:1001:\nstaff:x:50:alice, bob\ndocker:x:999:alice\n";
+
+    #[test]
+    fn parse_user_with_groups() {
+        let bob = User::parse("bob", PASSWD, GROUP).unwrap();
+
+        assert_eq!(bob, User { name: "bob".to_string(), uid: 1001, groups: vec![1001, 50] });
+        assert_eq!(User::parse("carol", PASSWD, GROUP), None);
+    }
+
+    #[test]
+    fn as_user_requires_root() {
+        assert!(require_superuser(0).is_ok());
+        assert_eq!(require_superuser(1000).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn denial_by_owner_group_and_others() {
+        let bob = User::parse("bob", PASSWD, GROUP).unwrap();
+        let root = User::parse("root", PASSWD, GROUP).unwrap();
+
+        assert_eq!(bob.denial((0o600, 1001, 0), false), None);
+        assert_eq!(bob.denial((0o640, 0, 50), false), None);
+        assert_eq!(bob.denial((0o640, 0, 999), false), Some("read"));
+        assert_eq!(bob.denial((0o750, 0, 0), true), Some("enter"));
+        assert_eq!(bob.denial((0o751, 0, 0), true), Some("list"));
+        assert_eq!(root.denial((0o000, 1001, 1001), true), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn annotate_access_stops_at_closed_directories() {
+        use crate::tree::TreeItem;
+        use std::fs::{File, Permissions};
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+        use tempfile::TempDir;
+
+        // Prepare: an unrelated user without any groups sees the permissions for others
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        fs::set_permissions(temp_path, Permissions::from_mode(0o755)).unwrap();
+        fs::create_dir(temp_path.join("closed")).unwrap();
+        File::create(temp_path.join("closed/inner.txt")).unwrap();
+        File::create(temp_path.join("private.txt")).unwrap();
+        File::create(temp_path.join("public.txt")).unwrap();
+        fs::set_permissions(temp_path.join("closed"), Permissions::from_mode(0o700)).unwrap();
+        fs::set_permissions(temp_path.join("private.txt"), Permissions::from_mode(0o600)).unwrap();
+        fs::set_permissions(temp_path.join("public.txt"), Permissions::from_mode(0o644)).unwrap();
+        let owner = fs::metadata(temp_path).unwrap().uid();
+        let guest = User { name: "guest".to_string(), uid: owner + 1, groups: Vec::new() };
+        let root = TreeItem::new_top_level(temp_path.to_str().unwrap().to_string(), true);
+        let closed = TreeItem::new(&root, "closed".to_string(), true);
+        let inner = TreeItem::new(&closed, "inner.txt".to_string(), false);
+        let private = TreeItem::new(&root, "private.txt".to_string(), false);
+        let public = TreeItem::new(&root, "public.txt".to_string(), false);
+
+        // Call
+        annotate_access(&root, &guest);
+
+        // Verify
+        assert_eq!(closed.borrow().annotations, vec!["[guest can't enter]"]);
+        assert!(inner.borrow().annotations.is_empty());
+        assert_eq!(private.borrow().annotations, vec!["[guest can't read]"]);
+        assert!(public.borrow().annotations.is_empty());
+    }
+}
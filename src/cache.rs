@@ -0,0 +1,527 @@
+use crate::diagnostics::Diagnostics;
+use crate::fs_utils::{is_entry_excluded, read_entries, Entry, TraverseOptions};
+use crate::hash::{sha256, to_hex};
+use crate::tree::json::push_str_value;
+use crate::tree::{Meta, SpecialFile, TreeItem, TreeItemRefCell};
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const HEADER: &str = "ftree-cache v8";
+
+/// Modification time of a directory as seconds and nanoseconds since the Unix epoch.
+type Mtime = (u64, u32);
+
+struct CachedDir {
+    mtime: Mtime,
+    entries: Vec<Entry>,
+}
+
+/// Directory listings from a previous run, keyed by their path relative to the scanned root.
+///
+/// A directory's mtime changes whenever an entry is added, removed or renamed in it, so a
+/// cached listing can be reused as long as the mtime is unchanged. Editing a file in place
+/// doesn't change it, so the sizes and times of the files in a reused listing may be stale. Listings are stored
+/// unfiltered, so changing filters between runs doesn't invalidate the cache.
+pub(crate) struct Cache {
+    root: PathBuf,
+    previous: HashMap<String, CachedDir>,
+    current: HashMap<String, CachedDir>,
+}
+
+impl Cache {
+    pub(crate) fn new(root: &str) -> Self {
+        Self { root: PathBuf::from(root), previous: HashMap::new(), current: HashMap::new() }
+    }
+
+    /// Loads the cache for `root` from `file`. A missing or unreadable file results in an
+    /// empty cache, so the first run simply scans everything.
+    pub(crate) fn load(root: &str, file: &Path) -> Self {
+        let mut cache = Self::new(root);
+        if let Ok(content) = fs::read_to_string(file) {
+            cache.previous = parse(&content).unwrap_or_default();
+        }
+        cache
+    }
+
+    /// Writes all directories read during this run to `file`. Directories that weren't
+    /// visited (because they were deleted, filtered or beyond the depth limit) are dropped.
+    pub(crate) fn save(&self, file: &Path) -> io::Result<()> {
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut keys: Vec<&String> = self.current.keys().collect();
+        keys.sort();
+
+        let mut content = format!("{}\n", HEADER);
+        for key in keys {
+            let dir = &self.current[key];
+            content.push_str(&format!("D {} {} {}\n", dir.mtime.0, dir.mtime.1, escape(key)));
+            for entry in &dir.entries {
+                let meta = &entry.meta;
+                let mut kind = String::from(if entry.is_dir { "d" } else { "f" });
+                if meta.is_symlink {
+                    kind.push('l');
+                }
+                if meta.is_executable {
+                    kind.push('x');
+                }
+                if meta.is_symdir {
+                    kind.push('L');
+                }
+                kind.extend(meta.special.map(|special| match special {
+                    SpecialFile::Socket => 's',
+                    SpecialFile::Fifo => 'p',
+                    SpecialFile::BlockDevice => 'b',
+                    SpecialFile::CharDevice => 'c',
+                }));
+                content.push_str(&format!(
+                    "E {} {} {} {} {} {} {}\n",
+                    kind,
+                    meta.size,
+                    meta.allocated.map_or("-".to_string(), |allocated| allocated.to_string()),
+                    encode_time(meta.modified),
+                    encode_time(meta.created),
+                    encode_time(meta.accessed),
+                    escape(&entry.name)
+                ));
+            }
+        }
+
+        // Write to a temporary file first, so concurrent runs never read a partial cache
+        let tmp_file = file.with_extension("tmp");
+        fs::write(&tmp_file, content)?;
+        fs::rename(&tmp_file, file)
+    }
+
+    /// Returns the entries of the directory at `path`, reading them from disk only if the
+    /// directory changed since the cached listing was taken. Directories that can't be read
+    /// aren't cached, so the error is reported again on the next run.
+    pub(crate) fn read_entries(&mut self, path: &str, diagnostics: &Diagnostics) -> io::Result<Vec<Entry>> {
+        let key = self.key(path);
+        let mtime = read_mtime(path);
+
+        let entries = match (self.previous.remove(&key), mtime) {
+            (Some(cached), Some(mtime)) if cached.mtime == mtime => cached.entries,
+            _ => read_entries(path, diagnostics)?,
+        };
+
+        if let Some(mtime) = mtime {
+            self.current.insert(key, CachedDir { mtime, entries: entries.clone() });
+        }
+        Ok(entries)
+    }
+
+    fn key(&self, path: &str) -> String {
+        let rel = Path::new(path).strip_prefix(&self.root).unwrap_or(Path::new(path));
+        rel.to_string_lossy().replace('\\', "/")
+    }
+}
+
+/// How `--diff-cache` shows the changes since the last run.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum DiffFormat {
+    /// Annotations like `[new]` in the tree
+    Tree,
+    /// A JSON array of `{"path", "change", "old_size", "new_size"}` objects instead of the tree, `[]` if nothing changed
+    Json,
+}
+
+/// How an entry changed since the last run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ChangeKind {
+    New,
+    Removed,
+    Resized,
+}
+
+impl ChangeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ChangeKind::New => "new",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Resized => "resized",
+        }
+    }
+}
+
+/// An entry that changed since the last run, with its size before and after, if it existed.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Change {
+    pub(crate) path: PathBuf,
+    pub(crate) is_dir: bool,
+    pub(crate) kind: ChangeKind,
+    pub(crate) old_size: Option<u64>,
+    pub(crate) new_size: Option<u64>,
+}
+
+impl Change {
+    fn name(&self) -> String {
+        self.path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default()
+    }
+
+    /// Returns the annotation of the change in the tree, e.g. `[resized 1 → 3 B]`.
+    fn annotation(&self) -> String {
+        match self.kind {
+            ChangeKind::Resized => format!("[resized {} → {} B]", self.old_size.unwrap_or(0), self.new_size.unwrap_or(0)),
+            kind => format!("[{}]", kind.as_str()),
+        }
+    }
+}
+
+/// Returns the changes of the entries below `item` in `current` compared to `previous`, in the
+/// order of the tree. Only directories listed in both caches are compared, as there is nothing
+/// to compare against otherwise.
+pub(crate) fn find_changes(item: &Rc<TreeItemRefCell>, previous: &Cache, current: &Cache, options: &TraverseOptions) -> Vec<Change> {
+    let mut changes = dir_changes(item, previous, current, options);
+    for child in item.borrow().children.iter().filter(|c| c.borrow().is_dir) {
+        changes.extend(find_changes(child, previous, current, options));
+    }
+    changes
+}
+
+/// Annotates the entries of `item` that are `[new]`, `[removed]` or `[resized]` in `current`
+/// compared to `previous`, adding nodes for removed entries.
+pub(crate) fn annotate_changes(item: &Rc<TreeItemRefCell>, previous: &Cache, current: &Cache, options: &TraverseOptions) {
+    for change in dir_changes(item, previous, current, options) {
+        if change.kind == ChangeKind::Removed {
+            let removed_node = TreeItem::new(item, change.name(), change.is_dir);
+            removed_node.borrow_mut().annotations.push(change.annotation());
+        } else if let Some(child) = item.borrow().children.iter().find(|c| c.borrow().text == change.name()) {
+            child.borrow_mut().annotations.push(change.annotation());
+        }
+    }
+
+    let children: Vec<_> = item.borrow().children.iter().map(Rc::clone).collect();
+    for child in children.iter().filter(|c| c.borrow().is_dir) {
+        annotate_changes(child, previous, current, options);
+    }
+}
+
+/// Returns the changes of the entries directly in `item`, removed ones last.
+fn dir_changes(item: &Rc<TreeItemRefCell>, previous: &Cache, current: &Cache, options: &TraverseOptions) -> Vec<Change> {
+    let path = item.borrow().path();
+    let path_str = path.to_str().unwrap();
+    let key = current.key(path_str);
+    let (Some(old), Some(new)) = (previous.previous.get(&key), current.current.get(&key)) else {
+        return Vec::new();
+    };
+
+    let mut changes = Vec::new();
+    for child in &item.borrow().children {
+        let child = child.borrow();
+        let old_entry = old.entries.iter().find(|e| e.name == child.text);
+        let new_entry = new.entries.iter().find(|e| e.name == child.text);
+        let (kind, old_size) = match (old_entry, new_entry) {
+            (None, _) => (ChangeKind::New, None),
+            (Some(old_entry), Some(new_entry)) if old_entry.meta.size != new_entry.meta.size => (ChangeKind::Resized, Some(old_entry.meta.size)),
+            _ => continue,
+        };
+        let new_size = new_entry.map(|entry| entry.meta.size);
+        changes.push(Change { path: path.join(&child.text), is_dir: child.is_dir, kind, old_size, new_size });
+    }
+
+    let removed = old.entries.iter().filter(|e| !new.entries.iter().any(|n| n.name == e.name));
+    for entry in removed {
+        if !is_entry_excluded(path_str, entry, options) {
+            let kind = ChangeKind::Removed;
+            changes.push(Change { path: path.join(&entry.name), is_dir: entry.is_dir, kind, old_size: Some(entry.meta.size), new_size: None });
+        }
+    }
+    changes
+}
+
+/// Renders `changes` as a JSON array, e.g.
+/// `[{"path":"./dist/app.js","change":"resized","old_size":1024,"new_size":2048}]`.
+/// Sizes are `null` for entries that didn't exist before or don't exist anymore.
+pub(crate) fn to_changes_json(changes: &[Change]) -> String {
+    let size_value = |size: Option<u64>| size.map_or("null".to_string(), |size| size.to_string());
+    let mut out = String::from("[");
+    for (i, change) in changes.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str("{\"path\":");
+        push_str_value(&mut out, &change.path.to_string_lossy().replace('\\', "/"));
+        out.push_str(",\"change\":");
+        push_str_value(&mut out, change.kind.as_str());
+        out.push_str(&format!(",\"old_size\":{},\"new_size\":{}}}", size_value(change.old_size), size_value(change.new_size)));
+    }
+    out.push(']');
+    out
+}
+
+/// Returns the file the cache for `root` is stored in, e.g. `~/.cache/ftree/<hash>`.
+/// Every root has its own cache file, keyed by its canonical path, see `cache_key`.
+pub(crate) fn cache_file(root: &Path) -> Option<PathBuf> {
+    let cache_dir = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+
+    let canonical_root = fs::canonicalize(root).ok()?;
+    Some(cache_dir.join("ftree").join(cache_key(&canonical_root)))
+}
+
+/// Returns the first 16 hex digits of the SHA-256 of `canonical_root`, which stay the same
+/// across builds and Rust versions, unlike `DefaultHasher`.
+fn cache_key(canonical_root: &Path) -> String {
+    to_hex(&sha256(canonical_root.as_os_str().as_encoded_bytes())[..8])
+}
+
+fn read_mtime(path: &str) -> Option<Mtime> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    let since_epoch = modified.duration_since(UNIX_EPOCH).ok()?;
+    Some((since_epoch.as_secs(), since_epoch.subsec_nanos()))
+}
+
+fn parse(content: &str) -> Option<HashMap<String, CachedDir>> {
+    let mut lines = content.lines();
+    if lines.next()? != HEADER {
+        return None;
+    }
+
+    let mut dirs = HashMap::new();
+    let mut current: Option<(String, CachedDir)> = None;
+    for line in lines {
+        let (kind, rest) = line.split_once(' ')?;
+        match kind {
+            "D" => {
+                let mut parts = rest.splitn(3, ' ');
+                let secs = parts.next()?.parse().ok()?;
+                let nanos = parts.next()?.parse().ok()?;
+                let key = unescape(parts.next()?);
+                if let Some((key, dir)) = current.replace((key, CachedDir { mtime: (secs, nanos), entries: Vec::new() })) {
+                    dirs.insert(key, dir);
+                }
+            }
+            "E" => {
+                let mut parts = rest.splitn(7, ' ');
+                let kind = parts.next()?;
+                let is_dir = kind.starts_with('d');
+                let meta = Meta {
+                    is_symlink: kind.contains('l'),
+                    is_executable: kind.contains('x'),
+                    is_symdir: kind.contains('L'),
+                    special: [('s', SpecialFile::Socket), ('p', SpecialFile::Fifo), ('b', SpecialFile::BlockDevice), ('c', SpecialFile::CharDevice)]
+                        .into_iter()
+                        .find_map(|(flag, special)| kind.contains(flag).then_some(special)),
+                    size: parts.next()?.parse().ok()?,
+                    allocated: match parts.next()? {
+                        "-" => None,
+                        allocated => Some(allocated.parse().ok()?),
+                    },
+                    modified: decode_time(parts.next()?)?,
+                    created: decode_time(parts.next()?)?,
+                    accessed: decode_time(parts.next()?)?,
+                };
+                let name = unescape(parts.next()?);
+                let (_, dir) = current.as_mut()?;
+                dir.entries.push(Entry { name, is_dir, meta });
+            }
+            _ => return None,
+        }
+    }
+    if let Some((key, dir)) = current {
+        dirs.insert(key, dir);
+    }
+    Some(dirs)
+}
+
+/// Encodes `time` as `<secs>.<nanos>` since the Unix epoch, or `-` if it's unknown.
+fn encode_time(time: Option<SystemTime>) -> String {
+    match time.and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+        Some(since_epoch) => format!("{}.{:09}", since_epoch.as_secs(), since_epoch.subsec_nanos()),
+        None => "-".to_string(),
+    }
+}
+
+/// Inverse of `encode_time`. Returns `None` if `value` is malformed.
+fn decode_time(value: &str) -> Option<Option<SystemTime>> {
+    if value == "-" {
+        return Some(None);
+    }
+    let (secs, nanos) = value.split_once('.')?;
+    let since_epoch = Duration::new(secs.parse().ok()?, nanos.parse().ok()?);
+    Some(Some(UNIX_EPOCH + since_epoch))
+}
+
+/// Escapes backslashes and newlines, so every path fits on a single line.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => result.push('\n'),
+                Some(other) => result.push(other),
+                None => result.push('\\'),
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_utils::traverse_fs_cached;
+    use std::fs::File;
+    use tempfile::TempDir;
+
+    #[test]
+    fn cache_key_is_stable() {
+        assert_eq!(cache_key(Path::new("/srv/data")), "96b53e47e3fc7c29");
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("root");
+        fs::create_dir_all(root.join("dir1")).unwrap();
+        fs::write(root.join("dir1/line\nbreak.txt"), "content").unwrap();
+        let root_str = root.to_str().unwrap();
+        let cache_file = temp_dir.path().join("cache");
+
+        // Call
+        let mut cache = Cache::new(root_str);
+        let item = TreeItem::new_top_level(root_str.to_string(), true);
+        traverse_fs_cached(root_str, &item, &TraverseOptions::default(), &mut cache);
+        cache.save(&cache_file).unwrap();
+        let loaded = Cache::load(root_str, &cache_file);
+
+        // Verify
+        assert_eq!(loaded.previous.len(), 2);
+        assert_eq!(loaded.previous[""].entries, cache.current[""].entries);
+        assert_eq!(loaded.previous["dir1"].entries, cache.current["dir1"].entries);
+        let file = &loaded.previous["dir1"].entries[0];
+        assert_eq!(file.name, "line\nbreak.txt");
+        assert!(!file.is_dir);
+        assert_eq!(file.meta.size, 7);
+        assert!(file.meta.modified.is_some());
+    }
+
+    #[test]
+    fn read_entries_reuses_unchanged_directories() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        File::create(temp_dir.path().join("real.txt")).unwrap();
+        let root_str = temp_dir.path().to_str().unwrap();
+        let ghost = vec![Entry { name: "ghost.txt".to_string(), is_dir: false, meta: Meta::default() }];
+
+        // Call & verify: a cached listing with the current mtime is returned as is
+        let mut cache = Cache::new(root_str);
+        let mtime = read_mtime(root_str).unwrap();
+        cache.previous.insert(String::new(), CachedDir { mtime, entries: ghost.clone() });
+        assert_eq!(cache.read_entries(root_str, &Diagnostics::default()).unwrap(), ghost);
+
+        // Call & verify: a cached listing with an outdated mtime is read again
+        let mut cache = Cache::new(root_str);
+        cache.previous.insert(String::new(), CachedDir { mtime: (0, 0), entries: ghost });
+        let entries = cache.read_entries(root_str, &Diagnostics::default()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "real.txt");
+    }
+
+    #[test]
+    fn annotate_changes_marks_new_removed_and_resized() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let root_str = temp_dir.path().to_str().unwrap();
+        fs::write(temp_dir.path().join("kept.txt"), "a").unwrap();
+        fs::write(temp_dir.path().join("resized.txt"), "a").unwrap();
+        fs::write(temp_dir.path().join("removed.txt"), "a").unwrap();
+        let options = TraverseOptions::default();
+        let mut previous = Cache::new(root_str);
+        traverse_fs_cached(root_str, &TreeItem::new_top_level(root_str.to_string(), true), &options, &mut previous);
+        previous.previous = std::mem::take(&mut previous.current);
+
+        fs::write(temp_dir.path().join("resized.txt"), "abc").unwrap();
+        fs::remove_file(temp_dir.path().join("removed.txt")).unwrap();
+        fs::write(temp_dir.path().join("new.txt"), "a").unwrap();
+
+        // Call
+        let mut current = Cache::new(root_str);
+        let root = TreeItem::new_top_level(root_str.to_string(), true);
+        traverse_fs_cached(root_str, &root, &options, &mut current);
+        annotate_changes(&root, &previous, &current, &options);
+
+        // Verify
+        let mut rows: Vec<String> = root.borrow().children.iter().map(|c| c.borrow().to_string()).collect();
+        rows.sort();
+        assert_eq!(rows, vec!["kept.txt", "new.txt [new]", "removed.txt [removed]", "resized.txt [resized 1 → 3 B]"]);
+    }
+
+    #[test]
+    fn find_changes_as_json() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let root_str = temp_dir.path().to_str().unwrap();
+        fs::create_dir(temp_dir.path().join("dist")).unwrap();
+        fs::write(temp_dir.path().join("dist/app.js"), "a").unwrap();
+        fs::write(temp_dir.path().join("old.txt"), "ab").unwrap();
+        let options = TraverseOptions::default();
+        let mut previous = Cache::new(root_str);
+        traverse_fs_cached(root_str, &TreeItem::new_top_level(root_str.to_string(), true), &options, &mut previous);
+        previous.previous = std::mem::take(&mut previous.current);
+
+        fs::write(temp_dir.path().join("dist/app.js"), "abc").unwrap();
+        fs::remove_file(temp_dir.path().join("old.txt")).unwrap();
+
+        // Call
+        let mut current = Cache::new(root_str);
+        let root = TreeItem::new_top_level(root_str.to_string(), true);
+        traverse_fs_cached(root_str, &root, &options, &mut current);
+        let changes = find_changes(&root, &previous, &current, &options);
+
+        // Verify
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].path, temp_dir.path().join("old.txt"));
+        assert_eq!((changes[0].kind, changes[0].old_size, changes[0].new_size), (ChangeKind::Removed, Some(2), None));
+        assert_eq!((changes[1].kind, changes[1].old_size, changes[1].new_size), (ChangeKind::Resized, Some(1), Some(3)));
+        let json = to_changes_json(&changes[1..]);
+        let expected = format!(r#"[{{"path":"{}/dist/app.js","change":"resized","old_size":1,"new_size":3}}]"#, root_str);
+        assert_eq!(json, expected);
+        assert_eq!(to_changes_json(&[]), "[]");
+    }
+
+    #[test]
+    fn load_ignores_unknown_format() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_file = temp_dir.path().join("cache");
+        fs::write(&cache_file, "something else\nD 1 2 x\n").unwrap();
+
+        let cache = Cache::load("root", &cache_file);
+
+        assert!(cache.previous.is_empty());
+    }
+
+    #[test]
+    fn encode_time_round_trip() {
+        let time = UNIX_EPOCH + Duration::new(1711893900, 5);
+        assert_eq!(encode_time(Some(time)), "1711893900.000000005");
+        assert_eq!(decode_time(&encode_time(Some(time))), Some(Some(time)));
+        assert_eq!(decode_time("-"), Some(None));
+        assert_eq!(decode_time("x"), None);
+    }
+
+    #[test]
+    fn escape_round_trip() {
+        let value = "a\\b\nc";
+        assert_eq!(escape(value), "a\\\\b\\nc");
+        assert_eq!(unescape(&escape(value)), value);
+    }
+}
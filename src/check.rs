@@ -0,0 +1,231 @@
+use crate::tree::TreeItemRefCell;
+use glob::{MatchOptions, Pattern};
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+/// `*` and `?` don't match `/`, only `**` crosses directories.
+const MATCH_OPTIONS: MatchOptions = MatchOptions { case_sensitive: true, require_literal_separator: true, require_literal_leading_dot: false };
+
+/// A glob over paths relative to the checked directory, e.g. `src/` or `**/.env`.
+/// A trailing `/` restricts the rule to directories.
+#[derive(Debug)]
+pub(crate) struct Rule {
+    text: String,
+    pattern: Pattern,
+    dirs_only: bool,
+}
+
+impl Rule {
+    fn parse(text: &str) -> Result<Self, String> {
+        let (glob, dirs_only) = match text.strip_suffix('/') {
+            Some(stripped) => (stripped, true),
+            None => (text, false),
+        };
+        let pattern = Pattern::new(glob).map_err(|err| format!("invalid pattern '{}': {}", text, err))?;
+        Ok(Self { text: text.to_string(), pattern, dirs_only })
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        (is_dir || !self.dirs_only) && self.pattern.matches_with(path, MATCH_OPTIONS)
+    }
+}
+
+///
+/// Expected layout of a directory, read from a TOML file like:
+///
+/// ```toml
+/// # Paths that must exist
+/// required = ["src/", "Cargo.toml"]
+/// # Paths that must not exist
+/// forbidden = ["**/.env", "**/*.log"]
+/// ```
+///
+/// Only this subset of TOML is supported: comments and top level keys with arrays of strings.
+///
+#[derive(Debug, Default)]
+pub(crate) struct Spec {
+    pub(crate) required: Vec<Rule>,
+    pub(crate) forbidden: Vec<Rule>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Violation {
+    /// No entry matches a required rule
+    Missing(String),
+    /// The entry at the path matches the forbidden rule
+    Forbidden { path: String, rule: String },
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::Missing(rule) => write!(f, "missing: {}", rule),
+            Violation::Forbidden { path, rule } => write!(f, "forbidden: {} (matches {})", path, rule),
+        }
+    }
+}
+
+/// Parses a spec, see `Spec`.
+pub(crate) fn parse_spec(text: &str) -> Result<Spec, String> {
+    let mut spec = Spec::default();
+    let mut lines = text.lines().enumerate();
+
+    while let Some((i, line)) = lines.next() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or_else(|| format!("line {}: expected `key = [...]`", i + 1))?;
+
+        // Arrays may span multiple lines
+        let mut value = value.trim().to_string();
+        while !value.ends_with(']') {
+            let (_, next) = lines.next().ok_or_else(|| format!("line {}: unterminated array", i + 1))?;
+            value.push_str(strip_comment(next).trim());
+        }
+        let rules = parse_string_array(&value)
+            .map_err(|err| format!("line {}: {}", i + 1, err))?
+            .iter()
+            .map(|text| Rule::parse(text))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match key.trim() {
+            "required" => spec.required.extend(rules),
+            "forbidden" => spec.forbidden.extend(rules),
+            other => return Err(format!("line {}: unknown key '{}', expected required or forbidden", i + 1, other)),
+        }
+    }
+    Ok(spec)
+}
+
+/// Removes a `#` comment, unless it's inside a string.
+pub(crate) fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Parses `["a", "b"]`, allowing a trailing comma.
+pub(crate) fn parse_string_array(value: &str) -> Result<Vec<String>, String> {
+    let inner = value
+        .strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']'))
+        .ok_or_else(|| format!("expected an array of strings, got '{}'", value))?;
+
+    let mut strings = Vec::new();
+    let mut chars = inner.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace() || *c == ',').is_some() {}
+        match chars.next() {
+            None => return Ok(strings),
+            Some('"') => {}
+            Some(c) => return Err(format!("unexpected '{}', expected a string", c)),
+        }
+
+        let mut string = String::new();
+        loop {
+            match chars.next() {
+                None => return Err("unterminated string".to_string()),
+                Some('"') => break,
+                Some('\\') => match chars.next() {
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    other => return Err(format!("unsupported escape '\\{}'", other.unwrap_or(' '))),
+                },
+                Some(c) => string.push(c),
+            }
+        }
+        strings.push(string);
+
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_some_and(|c| *c != ',') {
+            return Err("expected ',' between strings".to_string());
+        }
+    }
+}
+
+/// Returns all violations of `spec` in the tree below `root`, in tree order after missing paths.
+pub(crate) fn check(root: &Rc<TreeItemRefCell>, spec: &Spec) -> Vec<Violation> {
+    let mut entries = Vec::new();
+    collect_relative_paths(root, "", &mut entries);
+
+    let mut violations: Vec<Violation> = spec
+        .required
+        .iter()
+        .filter(|rule| !entries.iter().any(|(path, is_dir)| rule.matches(path, *is_dir)))
+        .map(|rule| Violation::Missing(rule.text.clone()))
+        .collect();
+
+    for (path, is_dir) in &entries {
+        if let Some(rule) = spec.forbidden.iter().find(|rule| rule.matches(path, *is_dir)) {
+            violations.push(Violation::Forbidden { path: path.clone(), rule: rule.text.clone() });
+        }
+    }
+    violations
+}
+
+/// Collects the paths below `item` relative to it, separated by `/`, with whether they are directories.
+pub(crate) fn collect_relative_paths(item: &Rc<TreeItemRefCell>, prefix: &str, entries: &mut Vec<(String, bool)>) {
+    for child in &item.borrow().children {
+        let path = format!("{}{}", prefix, child.borrow().text);
+        let is_dir = child.borrow().is_dir;
+        entries.push((path.clone(), is_dir));
+        if is_dir {
+            collect_relative_paths(child, &format!("{}/", path), entries);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::TreeItem;
+
+    #[test]
+    fn parse_spec_reads_arrays_and_comments() {
+        let text = "# layout\nrequired = [\"src/\", \"Cargo.toml\"] # must exist\nforbidden = [\n  \"**/.env\",\n  \"#tmp\",\n]\n";
+
+        let spec = parse_spec(text).unwrap();
+
+        let required: Vec<_> = spec.required.iter().map(|rule| rule.text.as_str()).collect();
+        let forbidden: Vec<_> = spec.forbidden.iter().map(|rule| rule.text.as_str()).collect();
+        assert_eq!(required, vec!["src/", "Cargo.toml"]);
+        assert_eq!(forbidden, vec!["**/.env", "#tmp"]);
+    }
+
+    #[test]
+    fn parse_spec_rejects_invalid_input() {
+        assert!(parse_spec("optional = [\"a\"]").is_err());
+        assert!(parse_spec("required = \"a\"").is_err());
+        assert!(parse_spec("required = [\"a\" \"b\"]").is_err());
+        assert!(parse_spec("required = [\"a\"").is_err());
+    }
+
+    #[test]
+    fn check_reports_missing_and_forbidden_paths() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let config = TreeItem::new(&root, "config".to_string(), true);
+        TreeItem::new(&config, ".env".to_string(), false);
+        TreeItem::new(&root, "src".to_string(), false);
+        let spec = parse_spec("required = [\"src/\", \"config/\"]\nforbidden = [\"**/.env\"]").unwrap();
+
+        let violations = check(&root, &spec);
+
+        let expected = vec![
+            Violation::Missing("src/".to_string()),
+            Violation::Forbidden { path: "config/.env".to_string(), rule: "**/.env".to_string() },
+        ];
+        assert_eq!(violations, expected);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::diagnostics::Diagnostics;
+use crate::tree::TreeItemRefCell;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::rc::Rc;
+
+/// Extents of a file as reported by `filefrag -v`, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Extents {
+    /// Bytes in extents shared with other files, e.g. by reflinks on Btrfs or XFS
+    shared: u64,
+    total: u64,
+}
+
+///
+/// Annotates the files below `item` whose data is shared with other files, as created by
+/// `cp --reflink` or deduplication on Btrfs and XFS: `[clone]` if all of it is shared, e.g.
+/// `[clone 40%]` if some. Their size is counted once per file by `-s` and `--stats`, but only
+/// once on disk.
+///
+/// Uses `filefrag -v` from e2fsprogs, which reads the extents with the FIEMAP ioctl of Linux.
+/// Files on file systems without it aren't annotated. If `filefrag` is missing, that's recorded
+/// in `diagnostics` once.
+///
+pub(crate) fn annotate_clones(item: &Rc<TreeItemRefCell>, diagnostics: &Diagnostics) {
+    if let Err(err) = annotate_below(item) {
+        let message = match err.kind() {
+            io::ErrorKind::NotFound => "--clones needs filefrag (e2fsprogs), which isn't installed".to_string(),
+            _ => format!("can't run filefrag: {}", err),
+        };
+        diagnostics.record(&item.borrow().path().to_string_lossy(), "clones", message);
+    }
+}
+
+/// Annotates the clones below `item`, failing only if `filefrag` can't be run.
+fn annotate_below(item: &Rc<TreeItemRefCell>) -> io::Result<()> {
+    for child in &item.borrow().children {
+        if child.borrow().is_dir {
+            annotate_below(child)?;
+            continue;
+        }
+        if child.borrow().meta.is_symlink || child.borrow().meta.special.is_some() {
+            continue;
+        }
+        let annotation = extents(&child.borrow().path())?.and_then(clone_annotation);
+        child.borrow_mut().annotations.extend(annotation);
+    }
+    Ok(())
+}
+
+/// Returns the extents of the file at `path`, or `None` if the file system doesn't report them.
+fn extents(path: &Path) -> io::Result<Option<Extents>> {
+    let output = Command::new("filefrag").arg("-v").arg("--").arg(path).output()?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(parse_filefrag(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Returns the annotation of a file with `extents`, if some of them are shared.
+fn clone_annotation(extents: Extents) -> Option<String> {
+    if extents.shared == 0 || extents.total == 0 {
+        return None;
+    }
+    match extents.shared * 100 / extents.total {
+        100.. => Some("[clone]".to_string()),
+        share => Some(format!("[clone {}%]", share.max(1))),
+    }
+}
+
+///
+/// Parses the output of `filefrag -v` for a single file:
+///
+/// ```text
+/// Filesystem type is: 9123683e
+/// File size of a.img is 1048576 (256 blocks of 4096 bytes)
+///  ext:     logical_offset:        physical_offset: length:   expected: flags:
+///    0:        0..     255:    3400960..   3401215:    256:             last,shared,eof
+/// a.img: 1 extent found
+/// ```
+///
+/// Lengths are in blocks of the size in the header. Files without extents have a total of 0.
+///
+fn parse_filefrag(output: &str) -> Option<Extents> {
+    let header = output.lines().find(|line| line.starts_with("File size of "))?;
+    let (_, blocks) = header.rsplit_once(" (")?;
+    let block_size: u64 = blocks.strip_suffix(" bytes)")?.rsplit_once(" of ")?.1.parse().ok()?;
+
+    let mut extents = Extents { shared: 0, total: 0 };
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split(':').map(str::trim).collect();
+        // Extent rows start with their index and have the length in the fourth field
+        if fields.len() < 5 || fields[0].parse::<u64>().is_err() {
+            continue;
+        }
+        let Ok(length) = fields[3].parse::<u64>() else { continue };
+        let bytes = length * block_size;
+        extents.total += bytes;
+        if fields.last().is_some_and(|flags| flags.split(',').any(|flag| flag == "shared")) {
+            extents.shared += bytes;
+        }
+    }
+    Some(extents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_filefrag_counts_shared_extents() {
+        let output = "Filesystem type is: 9123683e\n\
+                      File size of disk (copy).img is 1048576 (256 blocks of 4096 bytes)\n \
+                      ext:     logical_offset:        physical_offset: length:   expected: flags:\n   \
+                      0:        0..     101:    3400960..   3401061:    102:             shared\n   \
+                      1:      102..     255:    3500000..   3500153:    154:    3401062: last,eof\n\
+                      disk (copy).img: 2 extents found\n";
+
+        let extents = parse_filefrag(output).unwrap();
+
+        assert_eq!(extents, Extents { shared: 102 * 4096, total: 256 * 4096 });
+        assert_eq!(clone_annotation(extents), Some("[clone 39%]".to_string()));
+        assert_eq!(clone_annotation(Extents { shared: 4096, total: 4096 }), Some("[clone]".to_string()));
+        assert_eq!(clone_annotation(Extents { shared: 0, total: 4096 }), None);
+        let empty = "Filesystem type is: ef53\nFile size of empty is 0 (0 blocks of 4096 bytes)\nempty: 0 extents found\n";
+        assert_eq!(parse_filefrag(empty), Some(Extents { shared: 0, total: 0 }));
+        assert_eq!(parse_filefrag("filefrag: Operation not supported"), None);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::tree::TreeItemRefCell;
+use glob::{MatchOptions, Pattern};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Where a repository may keep its CODEOWNERS file, in the order GitHub looks for it.
+const LOCATIONS: [&str; 4] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS", ".gitlab/CODEOWNERS"];
+
+/// Annotation of entries whose rule lists no owners.
+const UNOWNED_ANNOTATION: &str = "[unowned]";
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions { case_sensitive: true, require_literal_separator: true, require_literal_leading_dot: false };
+
+/// A line of a CODEOWNERS file, e.g. `/src/auth/ @team-identity`.
+#[derive(Debug)]
+struct Rule {
+    pattern: Pattern,
+    owners: Vec<String>,
+    /// Only matches directories and what's inside them (`/` suffix)
+    dirs_only: bool,
+    /// Only matches the entries directly inside a directory (`/*` suffix), not deeper ones
+    direct_only: bool,
+    /// Matches the path relative to the root instead of a name at any depth. True for
+    /// patterns with a `/` other than at the end, as in gitignore.
+    anchored: bool,
+}
+
+impl Rule {
+    /// Returns whether the rule matches the entry at `rel_path` or one of the directories it's in.
+    fn matches(&self, rel_path: &str, is_dir: bool) -> bool {
+        let components: Vec<&str> = rel_path.split('/').collect();
+        (1..=components.len()).any(|end| {
+            let is_ancestor = end < components.len();
+            if (is_ancestor && self.direct_only) || (self.dirs_only && !is_ancestor && !is_dir) {
+                return false;
+            }
+            let candidate = if self.anchored { components[..end].join("/") } else { components[end - 1].to_string() };
+            self.pattern.matches_with(&candidate, MATCH_OPTIONS)
+        })
+    }
+}
+
+///
+/// The owners of the paths of a repository, read from a CODEOWNERS file:
+///
+/// ```text
+/// # Default owners
+/// *              @org/everyone
+/// /src/auth/     @team-identity @alice
+/// *.md           @team-docs
+/// /vendor/       # no owners
+/// ```
+///
+/// The patterns have gitignore syntax, except that `!` isn't supported and a pattern ending in
+/// `/*` only matches the entries directly inside a directory. As on GitHub, the last matching
+/// rule wins.
+///
+#[derive(Debug)]
+pub(crate) struct CodeOwners {
+    rules: Vec<Rule>,
+}
+
+impl CodeOwners {
+    /// Returns the CODEOWNERS file of the repository at `root`, if it has one.
+    pub(crate) fn find(root: &Path) -> Option<PathBuf> {
+        LOCATIONS.iter().map(|location| root.join(location)).find(|file| file.is_file())
+    }
+
+    pub(crate) fn load(file: &Path) -> io::Result<CodeOwners> {
+        let text = fs::read_to_string(file)?;
+        CodeOwners::parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub(crate) fn parse(text: &str) -> Result<CodeOwners, String> {
+        let mut rules = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let mut words = line.split_whitespace().take_while(|word| !word.starts_with('#'));
+            let Some(line_pattern) = words.next() else {
+                continue;
+            };
+            let owners = words.map(str::to_string).collect();
+
+            let (direct_only, glob) = match line_pattern.strip_suffix("/*") {
+                Some(rest) => (true, format!("{}/*", rest)),
+                None => (false, line_pattern.to_string()),
+            };
+            let (dirs_only, glob) = match glob.strip_suffix('/') {
+                Some(rest) => (true, rest.to_string()),
+                None => (false, glob),
+            };
+            let anchored = glob.contains('/');
+            let glob = glob.strip_prefix('/').unwrap_or(&glob);
+            let pattern = Pattern::new(glob).map_err(|err| format!("line {}: invalid pattern '{}': {}", i + 1, line_pattern, err))?;
+            rules.push(Rule { pattern, owners, dirs_only, direct_only, anchored });
+        }
+        Ok(CodeOwners { rules })
+    }
+
+    /// Returns the owners of the entry at `rel_path` (relative to the root, with `/` separators),
+    /// or `None` if no rule matches it. The owners are empty if the matching rule lists none.
+    pub(crate) fn owners(&self, rel_path: &str, is_dir: bool) -> Option<&[String]> {
+        self.rules.iter().rev().find(|rule| rule.matches(rel_path, is_dir)).map(|rule| rule.owners.as_slice())
+    }
+}
+
+/// Annotates the entries below `root` whose owners differ from the ones of their directory with
+/// the owners, e.g. `auth/ @team-identity`, or with `[unowned]` if a rule without owners matches.
+/// Entries that no rule matches aren't annotated.
+pub(crate) fn annotate_owners(root: &Rc<TreeItemRefCell>, owners: &CodeOwners) {
+    annotate_below(root, "", None, owners);
+}
+
+fn annotate_below(item: &Rc<TreeItemRefCell>, rel_path: &str, parent_owners: Option<&[String]>, owners: &CodeOwners) {
+    for child in &item.borrow().children {
+        let (child_path, is_dir) = {
+            let child_ref = child.borrow();
+            let child_path = if rel_path.is_empty() { child_ref.text.clone() } else { format!("{}/{}", rel_path, child_ref.text) };
+            (child_path, child_ref.is_dir)
+        };
+        let child_owners = owners.owners(&child_path, is_dir);
+        match child_owners {
+            Some(list) if child_owners != parent_owners => {
+                let annotation = if list.is_empty() { UNOWNED_ANNOTATION.to_string() } else { list.join(" ") };
+                child.borrow_mut().annotations.push(annotation);
+            }
+            _ => {}
+        }
+        if is_dir {
+            annotate_below(child, &child_path, child_owners, owners);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::TreeItem;
+    use tempfile::TempDir;
+
+    const CODEOWNERS: &str = "# Default owners\n\
+        *            @org/everyone\n\
+        /src/auth/   @team-identity @alice  # login and sessions\n\
+        *.md         @team-docs\n\
+        docs/*       @writers\n\
+        /vendor/\n";
+
+    #[test]
+    fn owners_of_the_last_matching_rule() {
+        let owners = CodeOwners::parse(CODEOWNERS).unwrap();
+
+        assert_eq!(owners.owners("Cargo.toml", false), Some(&["@org/everyone".to_string()][..]));
+        assert_eq!(owners.owners("src/auth", true), Some(&["@team-identity".to_string(), "@alice".to_string()][..]));
+        assert_eq!(owners.owners("src/auth/session/token.rs", false).unwrap()[0], "@team-identity");
+        assert_eq!(owners.owners("src/auth/README.md", false).unwrap(), ["@team-docs"]);
+        assert_eq!(owners.owners("docs/intro.txt", false).unwrap(), ["@writers"]);
+        assert_eq!(owners.owners("docs/api/index.txt", false).unwrap(), ["@org/everyone"]);
+        assert_eq!(owners.owners("vendor/lib.rs", false), Some(&[][..]));
+        assert_eq!(CodeOwners::parse("/src/ @a").unwrap().owners("lib/src/main.rs", false), None);
+        assert!(CodeOwners::parse("ok @a\n[unclosed @b").unwrap_err().starts_with("line 2:"));
+    }
+
+    #[test]
+    fn annotate_owners_where_they_change() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let src = TreeItem::new(&root, "src".to_string(), true);
+        let auth = TreeItem::new(&src, "auth".to_string(), true);
+        let login = TreeItem::new(&auth, "login.rs".to_string(), false);
+        let main = TreeItem::new(&src, "main.rs".to_string(), false);
+        let vendor = TreeItem::new(&root, "vendor".to_string(), true);
+
+        annotate_owners(&root, &CodeOwners::parse(CODEOWNERS).unwrap());
+
+        assert_eq!(src.borrow().annotations, vec!["@org/everyone"]);
+        assert_eq!(auth.borrow().annotations, vec!["@team-identity @alice"]);
+        assert!(login.borrow().annotations.is_empty());
+        assert!(main.borrow().annotations.is_empty());
+        assert_eq!(vendor.borrow().annotations, vec![UNOWNED_ANNOTATION]);
+    }
+
+    #[test]
+    fn find_prefers_the_github_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        assert_eq!(CodeOwners::find(temp_dir.path()), None);
+
+        fs::write(temp_dir.path().join("CODEOWNERS"), "* @a\n").unwrap();
+        fs::create_dir(temp_dir.path().join(".github")).unwrap();
+        fs::write(temp_dir.path().join(".github/CODEOWNERS"), "* @b\n").unwrap();
+
+        assert_eq!(CodeOwners::find(temp_dir.path()), Some(temp_dir.path().join(".github/CODEOWNERS")));
+    }
+}
@@ -0,0 +1,320 @@
+use crate::tree::{prune_tree, TreeItemRefCell};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::thread;
+
+/// Number of bytes at the start of a file that are checked for NUL bytes to detect binary files.
+const BINARY_SAMPLE_SIZE: u64 = 8 * 1024;
+
+/// Number of bytes at the start of a file that are searched for the first line of `--first-line`.
+const FIRST_LINE_SAMPLE_SIZE: u64 = 1024;
+
+/// Maximum number of characters of a first line, longer ones are cut off with `…`.
+const FIRST_LINE_MAX_CHARS: usize = 60;
+
+/// Number of bytes at the start of a README that are searched for its heading, past badges and
+/// front matter.
+const README_SAMPLE_SIZE: u64 = 4 * 1024;
+
+/// Extensions of the READMEs of `--peek-readme`, in the order they're preferred. Names are
+/// matched case-insensitively.
+const README_EXTENSIONS: [&str; 6] = ["md", "markdown", "", "txt", "rst", "adoc"];
+
+/// Returns whether the file at `path` looks binary, i.e. has a NUL byte in its first 8 KiB.
+/// That's the same heuristic git and grep use.
+pub(crate) fn is_binary(path: &Path) -> io::Result<bool> {
+    let mut sample = Vec::new();
+    File::open(path)?.take(BINARY_SAMPLE_SIZE).read_to_end(&mut sample)?;
+    Ok(sample.contains(&0))
+}
+
+/// Returns the number of lines in the file at `path` that contain `pattern`, or `None` if the
+/// file is binary or can't be read.
+pub(crate) fn count_matches(path: &Path, pattern: &str) -> Option<usize> {
+    if is_binary(path).ok()? {
+        return None;
+    }
+
+    let pattern = pattern.as_bytes();
+    let mut count = 0;
+    for line in BufReader::new(File::open(path).ok()?).split(b'\n') {
+        let line = line.ok()?;
+        if pattern.is_empty() || line.windows(pattern.len()).any(|window| window == pattern) {
+            count += 1;
+        }
+    }
+    Some(count)
+}
+
+///
+/// Returns the first non-blank line of the file at `path`, e.g. a shebang like
+/// `#!/usr/bin/env bash` or the docstring of a module, with surrounding whitespace trimmed and cut
+/// off after 60 characters. Only the first KiB is read. UTF-16 files are recognized by their
+/// byte order mark, other files are read as UTF-8, where invalid bytes are replaced.
+///
+/// Returns `None` for binary files, files without a non-blank line in the first KiB and files
+/// that can't be read.
+///
+pub(crate) fn first_line(path: &Path) -> Option<String> {
+    let mut sample = Vec::new();
+    File::open(path).ok()?.take(FIRST_LINE_SAMPLE_SIZE).read_to_end(&mut sample).ok()?;
+    let text = decode_sample(&sample)?;
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty())?;
+    Some(shorten(line))
+}
+
+/// Replaces control characters in `line` and cuts it off after `FIRST_LINE_MAX_CHARS`.
+fn shorten(line: &str) -> String {
+    let line = line.replace(|c: char| c.is_control(), " ");
+    if line.chars().count() <= FIRST_LINE_MAX_CHARS {
+        return line;
+    }
+    let cut: String = line.chars().take(FIRST_LINE_MAX_CHARS - 1).collect();
+    format!("{}…", cut.trim_end())
+}
+
+/// Returns the README of the directory at `path`, e.g. `README.md` or `readme.txt`, preferring
+/// the extensions in the order of `README_EXTENSIONS`.
+fn find_readme(path: &Path) -> Option<PathBuf> {
+    let rank = |name: &str| {
+        let name = name.to_lowercase();
+        let extension = name.strip_prefix("readme").and_then(|rest| if rest.is_empty() { Some("") } else { rest.strip_prefix('.') })?;
+        README_EXTENSIONS.iter().position(|known| *known == extension)
+    };
+    fs::read_dir(path)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .filter_map(|entry| Some((rank(entry.file_name().to_str()?)?, entry.path())))
+        .min()
+        .map(|(_, path)| path)
+}
+
+///
+/// Returns a short description of a directory from its README at `path`: the first heading, or
+/// the first line of text if there's none before it. Markdown headings like `# Parser` and
+/// underlined ones like in reStructuredText are recognized. Front matter, HTML, images and badges
+/// are skipped, and only the first 4 KiB are read.
+///
+pub(crate) fn readme_summary(path: &Path) -> Option<String> {
+    let mut sample = Vec::new();
+    File::open(path).ok()?.take(README_SAMPLE_SIZE).read_to_end(&mut sample).ok()?;
+    let text = decode_sample(&sample)?;
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    let underline = |line: &str| line.len() >= 3 && line.chars().all(|c| "=-~^*#".contains(c));
+
+    let mut i = 0;
+    // YAML front matter of static site generators
+    if lines.first() == Some(&"---") {
+        i = lines.iter().skip(1).position(|line| *line == "---").map_or(0, |end| end + 2);
+    }
+    for line in lines.iter().skip(i) {
+        if line.is_empty() || underline(line) || line.starts_with('<') || line.starts_with("![") || line.starts_with("[![") {
+            continue;
+        }
+        if let Some(heading) = line.strip_prefix('#').map(|heading| heading.trim_start_matches('#')) {
+            let heading = heading.trim_end_matches('#').trim();
+            if heading.is_empty() {
+                continue;
+            }
+            return Some(shorten(heading));
+        }
+        // An underlined heading, or the first line of text
+        return Some(shorten(line));
+    }
+    None
+}
+
+/// Annotates `root` and the directories below it that have a README with its summary (see
+/// `readme_summary`) in quotes, e.g. `parser/ "Parses the spec files"`, for an annotated map of
+/// a project.
+pub(crate) fn annotate_readmes(root: &Rc<TreeItemRefCell>) {
+    let summary = find_readme(&root.borrow().path()).and_then(|readme| readme_summary(&readme));
+    root.borrow_mut().annotations.extend(summary.map(|summary| format!("\"{}\"", summary)));
+    for child in &root.borrow().children {
+        if child.borrow().is_dir {
+            annotate_readmes(child);
+        }
+    }
+}
+
+/// Decodes the start of a file by its byte order mark, or as UTF-8 without one. Returns `None`
+/// if it looks binary. The last line may be cut off, also within a character.
+fn decode_sample(sample: &[u8]) -> Option<String> {
+    let utf16 = |bytes: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+        let units = bytes.chunks_exact(2).map(|pair| from_bytes([pair[0], pair[1]]));
+        char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect::<String>()
+    };
+    let text = match sample {
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8_lossy(rest).to_string(),
+        _ if sample.contains(&0) => return None,
+        _ => String::from_utf8_lossy(sample).to_string(),
+    };
+    (!text.contains('\0')).then_some(text)
+}
+
+/// Annotates the files below `root` with their first line (see `first_line`) in quotes, e.g.
+/// `deploy.sh "#!/usr/bin/env bash"`, for a quick sense of what each file is.
+pub(crate) fn annotate_first_lines(root: &Rc<TreeItemRefCell>) {
+    for child in &root.borrow().children {
+        if child.borrow().is_dir {
+            annotate_first_lines(child);
+        } else {
+            let line = first_line(&child.borrow().path());
+            child.borrow_mut().annotations.extend(line.map(|line| format!("\"{}\"", line)));
+        }
+    }
+}
+
+/// Removes all files that don't contain `pattern` from the tree, as well as directories
+/// that end up without any matching file. Matching files are annotated with their number of
+/// matching lines, e.g. `parser.rs (4 matches)`.
+///
+/// The files are searched on all available cores.
+pub(crate) fn filter_by_content(root: &Rc<TreeItemRefCell>, pattern: &str) {
+    let mut paths = Vec::new();
+    collect_file_paths(root, &mut paths);
+    let matches = count_matches_parallel(paths, pattern);
+
+    prune_tree(root, &mut |item| match matches.get(&item.path()) {
+        Some(&count) if count > 0 => {
+            let unit = if count == 1 { "match" } else { "matches" };
+            item.annotations.push(format!("({} {})", count, unit));
+            true
+        }
+        _ => false,
+    });
+}
+
+fn collect_file_paths(item: &Rc<TreeItemRefCell>, paths: &mut Vec<PathBuf>) {
+    for child in &item.borrow().children {
+        if child.borrow().is_dir {
+            collect_file_paths(child, paths);
+        } else {
+            paths.push(child.borrow().path());
+        }
+    }
+}
+
+fn count_matches_parallel(paths: Vec<PathBuf>, pattern: &str) -> HashMap<PathBuf, usize> {
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = paths.len().div_ceil(workers).max(1);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .filter_map(|path| count_matches(path, pattern).map(|count| (path.clone(), count)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_utils::{traverse_fs, TraverseOptions};
+    use crate::tree::TreeItem;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn count_matches_counts_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let text = temp_dir.path().join("text.txt");
+        let binary = temp_dir.path().join("binary.bin");
+        fs::write(&text, "TODO one\nnothing\nTODO two TODO\n").unwrap();
+        fs::write(&binary, b"TODO\0").unwrap();
+
+        assert_eq!(count_matches(&text, "TODO"), Some(2));
+        assert_eq!(count_matches(&text, "FIXME"), Some(0));
+        assert_eq!(count_matches(&binary, "TODO"), None);
+        assert!(is_binary(&binary).unwrap());
+        assert!(!is_binary(&text).unwrap());
+    }
+
+    #[test]
+    fn readme_summary_prefers_headings() {
+        let temp_dir = TempDir::new().unwrap();
+        let write = |name: &str, content: &str| {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            path
+        };
+
+        let badges = "---\ntitle: Parser\n---\n<p align=\"center\"><img src=\"logo.png\"></p>\n[![CI](ci.svg)](ci)\n\n## Parser ##\nText\n";
+        assert_eq!(readme_summary(&write("badges.md", badges)), Some("Parser".to_string()));
+        assert_eq!(readme_summary(&write("setext.rst", "Spec reader\n===========\n\nReads specs.\n")), Some("Spec reader".to_string()));
+        assert_eq!(readme_summary(&write("plain", "\nScripts for the release.\nMore.\n")), Some("Scripts for the release.".to_string()));
+        assert_eq!(readme_summary(&write("empty.md", "#\n\n")), None);
+
+        let root = TreeItem::new_top_level(temp_dir.path().to_str().unwrap().to_string(), true);
+        let docs = TreeItem::new(&root, "docs".to_string(), true);
+        fs::create_dir(temp_dir.path().join("docs")).unwrap();
+        write("docs/readme.txt", "Old notes\n");
+        write("docs/Readme.md", "# Developer guide\n");
+        annotate_readmes(&root);
+        assert_eq!(docs.borrow().annotations, vec!["\"Developer guide\""]);
+        assert!(root.borrow().annotations.is_empty());
+    }
+
+    #[test]
+    fn first_line_skips_blank_lines_and_truncates() {
+        let temp_dir = TempDir::new().unwrap();
+        let write = |name: &str, content: &[u8]| {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            path
+        };
+
+        assert_eq!(first_line(&write("run.sh", b"#!/usr/bin/env bash\nset -e\n")), Some("#!/usr/bin/env bash".to_string()));
+        assert_eq!(first_line(&write("mod.py", b"\n\n  \"\"\"Parses the config.\"\"\"  \r\n")), Some("\"\"\"Parses the config.\"\"\"".to_string()));
+        assert_eq!(first_line(&write("bom.txt", b"\xEF\xBB\xBFname = demo\n")), Some("name = demo".to_string()));
+        assert_eq!(first_line(&write("utf16.txt", b"\xFF\xFEh\0i\0\n\0")), Some("hi".to_string()));
+        assert_eq!(first_line(&write("long.txt", "é".repeat(100).as_bytes())), Some(format!("{}…", "é".repeat(59))));
+        assert_eq!(first_line(&write("binary.bin", b"ELF\0\x01")), None);
+        assert_eq!(first_line(&write("blank.txt", b" \n\t\n")), None);
+
+        let root = TreeItem::new_top_level(temp_dir.path().to_str().unwrap().to_string(), true);
+        let script = TreeItem::new(&root, "run.sh".to_string(), false);
+        annotate_first_lines(&root);
+        assert_eq!(script.borrow().to_string(), "run.sh \"#!/usr/bin/env bash\"");
+    }
+
+    #[test]
+    fn filter_by_content_prunes_tree() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        fs::create_dir_all(temp_path.join("src/nested")).unwrap();
+        fs::create_dir(temp_path.join("docs")).unwrap();
+        fs::write(temp_path.join("src/parser.rs"), "// TODO\n// TODO\n").unwrap();
+        fs::write(temp_path.join("src/nested/done.rs"), "fn main() {}\n").unwrap();
+        fs::write(temp_path.join("docs/readme.md"), "no todos\n").unwrap();
+        let root = TreeItem::new_top_level(temp_path.to_str().unwrap().to_string(), true);
+        traverse_fs(temp_path.to_str().unwrap(), &root, &TraverseOptions::default());
+
+        // Call
+        filter_by_content(&root, "TODO");
+
+        // Verify
+        let root_ref = root.borrow();
+        assert_eq!(root_ref.children.len(), 1);
+        let src = root_ref.children[0].borrow();
+        assert_eq!(src.text, "src");
+        assert_eq!(src.children.len(), 1);
+        assert_eq!(src.children[0].borrow().to_string(), "parser.rs (2 matches)");
+        assert!(src.children[0].borrow().is_last);
+    }
+}
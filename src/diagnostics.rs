@@ -0,0 +1,106 @@
+use crate::tree::json::push_str_value;
+use clap::ValueEnum;
+use std::cell::RefCell;
+use std::io;
+
+/// How `--errors` reports the problems that occurred while reading the tree.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum ErrorFormat {
+    /// One line per problem
+    Text,
+    /// A JSON array of `{"path", "kind", "message"}` objects, `[]` if there were none
+    Json,
+}
+
+/// A problem that prevented reading part of the tree.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Diagnostic {
+    pub(crate) path: String,
+    /// Machine readable category, e.g. `permission_denied` or `broken_link`
+    pub(crate) kind: &'static str,
+    pub(crate) message: String,
+}
+
+/// Collects the problems that occur during a traversal, so it can continue past them and
+/// report all of them at the end.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Diagnostics {
+    problems: RefCell<Vec<Diagnostic>>,
+}
+
+impl Diagnostics {
+    pub(crate) fn record(&self, path: &str, kind: &'static str, message: String) {
+        self.problems.borrow_mut().push(Diagnostic { path: path.to_string(), kind, message });
+    }
+
+    pub(crate) fn record_io(&self, path: &str, err: &io::Error) {
+        let kind = match err.kind() {
+            io::ErrorKind::PermissionDenied => "permission_denied",
+            io::ErrorKind::NotFound => "not_found",
+            _ => "io",
+        };
+        self.record(path, kind, err.to_string());
+    }
+
+    /// Returns the problems recorded so far without clearing them.
+    pub(crate) fn problems(&self) -> Vec<Diagnostic> {
+        self.problems.borrow().clone()
+    }
+
+    /// Returns the problems recorded so far and clears them.
+    pub(crate) fn take(&self) -> Vec<Diagnostic> {
+        self.problems.take()
+    }
+}
+
+/// Renders `problems` in `format`, e.g. `Error reading ./secret: Permission denied (os error 13)`
+/// or `[{"path":"./secret","kind":"permission_denied","message":"..."}]`.
+pub(crate) fn format_diagnostics(problems: &[Diagnostic], format: ErrorFormat) -> String {
+    match format {
+        ErrorFormat::Text => problems
+            .iter()
+            .map(|problem| format!("Error reading {}: {}", problem.path, problem.message))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        ErrorFormat::Json => {
+            let mut out = String::from("[");
+            for (i, problem) in problems.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str("{\"path\":");
+                push_str_value(&mut out, &problem.path);
+                out.push_str(",\"kind\":");
+                push_str_value(&mut out, problem.kind);
+                out.push_str(",\"message\":");
+                push_str_value(&mut out, &problem.message);
+                out.push('}');
+            }
+            out.push(']');
+            out
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_and_format() {
+        let diagnostics = Diagnostics::default();
+        diagnostics.record_io("./secret", &io::Error::from(io::ErrorKind::PermissionDenied));
+        diagnostics.record("./link", "broken_link", "target doesn't exist".to_string());
+
+        let problems = diagnostics.take();
+
+        assert!(diagnostics.take().is_empty());
+        assert_eq!(problems[0].kind, "permission_denied");
+        assert_eq!(
+            format_diagnostics(&problems[1..], ErrorFormat::Json),
+            r#"[{"path":"./link","kind":"broken_link","message":"target doesn't exist"}]"#
+        );
+        assert_eq!(format_diagnostics(&problems[1..], ErrorFormat::Text), "Error reading ./link: target doesn't exist");
+        assert_eq!(format_diagnostics(&[], ErrorFormat::Json), "[]");
+    }
+}
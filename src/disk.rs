@@ -0,0 +1,82 @@
+use crate::size_utils::format_human;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Space of the file system a path is on, as reported by `df`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DiskSpace {
+    pub(crate) filesystem: String,
+    pub(crate) mount: String,
+    total: u64,
+    used: u64,
+    available: u64,
+    /// Share of the space usable by unprivileged users that's used, in percent
+    capacity: u8,
+}
+
+impl Display for DiskSpace {
+    /// Formats the space like `/dev/sda1 on /: 120.5 GiB total, 80.1 GiB used, 40.4 GiB free (67% used)`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} on {}: {} total, {} used, {} free ({}% used)",
+            self.filesystem,
+            self.mount,
+            format_human(self.total),
+            format_human(self.used),
+            format_human(self.available),
+            self.capacity
+        )
+    }
+}
+
+/// Returns the space of the file system `path` is on, with `df -P -k`. Fails where there is no
+/// POSIX `df`, e.g. on Windows.
+pub(crate) fn disk_space(path: &Path) -> io::Result<DiskSpace> {
+    let output = Command::new("df").args(["-P", "-k", "--"]).arg(path).output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(format!("df failed: {}", message)));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_df(&stdout).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("unexpected output of df: {}", stdout.trim())))
+}
+
+/// Parses the output of `df -P -k`, a header followed by a line like
+/// `/dev/sda1  125829120  83990528  42354688  67%  /`. Names with spaces are supported.
+fn parse_df(output: &str) -> Option<DiskSpace> {
+    let fields: Vec<&str> = output.lines().nth(1)?.split_whitespace().collect();
+    // The numbers follow the file system name and precede the mount point
+    let start = (1..fields.len().saturating_sub(4)).find(|&i| {
+        fields[i..i + 3].iter().all(|field| field.parse::<u64>().is_ok()) && fields[i + 3].ends_with('%')
+    })?;
+    let kib = |i: usize| fields[i].parse::<u64>().ok().map(|blocks| blocks * 1024);
+    Some(DiskSpace {
+        filesystem: fields[..start].join(" "),
+        mount: fields[start + 4..].join(" "),
+        total: kib(start)?,
+        used: kib(start + 1)?,
+        available: kib(start + 2)?,
+        capacity: fields[start + 3].trim_end_matches('%').parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_df_output() {
+        let output = "Filesystem     1024-blocks     Used Available Capacity Mounted on\n\
+                      /dev/sda1        125829120 83990528  42354688      67% /\n";
+
+        let space = parse_df(output).unwrap();
+
+        assert_eq!(space.to_string(), "/dev/sda1 on /: 120.0 GiB total, 80.1 GiB used, 40.4 GiB free (67% used)");
+        let space = parse_df("header\nmy disk 100 40 60 40% /mnt/my disk\n").unwrap();
+        assert_eq!((space.filesystem.as_str(), space.mount.as_str(), space.used), ("my disk", "/mnt/my disk", 40 * 1024));
+        assert_eq!(parse_df("header\n"), None);
+    }
+}
@@ -0,0 +1,266 @@
+use crate::diagnostics::Diagnostics;
+use crate::i18n;
+use crate::tree::TreeItemRefCell;
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Placeholder for the path of the file in the commands of `--exec-label` and `--exec`, like in `find -exec`.
+const PLACEHOLDER: &str = "{}";
+
+/// How often a running command is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+///
+/// Runs `command` for each file below `root` and annotates the file with its output, e.g.
+/// `wc -l < {}` gives `main.rs 120`. Lines of the output are joined with spaces, empty
+/// output adds no annotation.
+///
+/// `{}` is replaced by the path, which is passed to the shell as an argument rather than pasted
+/// into the command, so names with spaces or quotes are safe. Without `{}`, the path is appended.
+/// The commands run on all available cores. Commands that fail or take longer than `timeout`
+/// are killed and recorded in `diagnostics`.
+///
+pub(crate) fn annotate_labels(root: &Rc<TreeItemRefCell>, command: &str, timeout: Duration, diagnostics: &Diagnostics) {
+    let mut files = Vec::new();
+    collect_files(root, &mut files);
+    let paths: Vec<PathBuf> = files.iter().map(|file| file.borrow().path()).collect();
+
+    let results = run_parallel(&paths, command, timeout);
+    for ((file, path), result) in files.iter().zip(&paths).zip(results) {
+        match result {
+            Ok(label) if label.is_empty() => {}
+            Ok(label) => file.borrow_mut().annotations.push(label),
+            Err(message) => diagnostics.record(&path.to_string_lossy(), "exec_label", message),
+        }
+    }
+}
+
+/// What `run_on_files` did with the files, e.g. `12 commands ran, 1 failed, 2 skipped`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct ExecSummary {
+    pub(crate) ran: usize,
+    pub(crate) failed: usize,
+    /// Files that weren't confirmed
+    pub(crate) skipped: usize,
+    /// Commands listed by a dry run
+    pub(crate) listed: usize,
+}
+
+impl Display for ExecSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.listed {
+            0 => {}
+            1 => return write!(f, "{}", i18n::tr!("Dry run: {} command not run", self.listed)),
+            _ => return write!(f, "{}", i18n::tr!("Dry run: {} commands not run", self.listed)),
+        }
+        match self.ran {
+            1 => write!(f, "{}", i18n::tr!("{} command ran, {} failed", self.ran, self.failed))?,
+            _ => write!(f, "{}", i18n::tr!("{} commands ran, {} failed", self.ran, self.failed))?,
+        }
+        if self.skipped > 0 {
+            write!(f, "{}", i18n::tr!(", {} skipped", self.skipped))?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Runs `command` for each file below `root` in the order of the tree, e.g. `chmod 644 {}` for
+/// the files the filters kept, with `{}` replaced like in `annotate_labels`. The commands run one
+/// after the other and write to the terminal directly, which `out` is flushed to before each one.
+///
+/// With `dry_run`, the commands are written to `out` instead, with the path quoted for the
+/// shell, e.g. `chmod 644 'notes 1.txt'`. Otherwise `confirm` is asked before each command,
+/// which is skipped if it declines. Failing commands don't stop the others.
+///
+pub(crate) fn run_on_files(
+    root: &Rc<TreeItemRefCell>,
+    command: &str,
+    dry_run: bool,
+    confirm: &mut dyn FnMut(&str) -> bool,
+    out: &mut impl Write,
+) -> io::Result<ExecSummary> {
+    let mut files = Vec::new();
+    collect_files(root, &mut files);
+    let mut summary = ExecSummary::default();
+    for file in files {
+        let path = file.borrow().path();
+        let shown = display_command(command, &path);
+        if dry_run {
+            writeln!(out, "{}", shown)?;
+            summary.listed += 1;
+            continue;
+        }
+        if !confirm(&shown) {
+            summary.skipped += 1;
+            continue;
+        }
+        out.flush()?;
+        summary.ran += 1;
+        let error = match shell_command(command, &path).stdin(Stdio::null()).status() {
+            Ok(status) if status.success() => continue,
+            Ok(status) => format!("the command failed with {}", status),
+            Err(err) => format!("failed to run the command: {}", err),
+        };
+        eprintln!("{}", i18n::tr!("Error running {}: {}", shown, error));
+        summary.failed += 1;
+    }
+    Ok(summary)
+}
+
+/// Returns `command` as it runs for the file at `path`, with the path quoted for a POSIX shell.
+fn display_command(command: &str, path: &Path) -> String {
+    let quoted = format!("'{}'", path.display().to_string().replace('\'', "'\\''"));
+    if command.contains(PLACEHOLDER) { command.replace(PLACEHOLDER, &quoted) } else { format!("{} {}", command, quoted) }
+}
+
+fn collect_files(item: &Rc<TreeItemRefCell>, files: &mut Vec<Rc<TreeItemRefCell>>) {
+    for child in &item.borrow().children {
+        if child.borrow().is_dir {
+            collect_files(child, files);
+        } else {
+            files.push(Rc::clone(child));
+        }
+    }
+}
+
+/// Runs `command` for each of `paths`, returning the results in the same order.
+fn run_parallel(paths: &[PathBuf], command: &str, timeout: Duration) -> Vec<Result<String, String>> {
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = paths.len().div_ceil(workers).max(1);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(|path| run_label(command, path, timeout)).collect::<Vec<_>>()))
+            .collect();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+/// Runs `command` for the file at `path` and returns its output on one line.
+fn run_label(command: &str, path: &Path, timeout: Duration) -> Result<String, String> {
+    let mut child = shell_command(command, path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| format!("failed to run the command: {}", err))?;
+
+    // Read the output while waiting, so a command with a lot of output doesn't block on a full pipe
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|err| err.to_string())? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("the command timed out after {:?}", timeout));
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    if !status.success() {
+        return Err(format!("the command failed with {}", status));
+    }
+
+    let output = reader.join().unwrap().map_err(|err| err.to_string())?;
+    let output = String::from_utf8_lossy(&output);
+    Ok(output.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join(" "))
+}
+
+#[cfg(unix)]
+fn shell_command(command: &str, path: &Path) -> Command {
+    let script = if command.contains(PLACEHOLDER) { command.replace(PLACEHOLDER, "\"$1\"") } else { format!("{} \"$1\"", command) };
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(script).arg("sh").arg(path);
+    shell
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str, path: &Path) -> Command {
+    let quoted = format!("\"{}\"", path.display());
+    let script = if command.contains(PLACEHOLDER) { command.replace(PLACEHOLDER, &quoted) } else { format!("{} {}", command, quoted) };
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(script);
+    shell
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::tree::TreeItem;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn annotate_labels_with_command_output() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().to_str().unwrap();
+        fs::write(temp_dir.path().join("two lines.txt"), "a\nb\n").unwrap();
+        fs::write(temp_dir.path().join("empty.txt"), "").unwrap();
+        let root = TreeItem::new_top_level(path.to_string(), true);
+        let two_lines = TreeItem::new(&root, "two lines.txt".to_string(), false);
+        let empty = TreeItem::new(&root, "empty.txt".to_string(), false);
+        let diagnostics = Diagnostics::default();
+
+        annotate_labels(&root, "wc -l < {}", Duration::from_secs(10), &diagnostics);
+
+        assert_eq!(two_lines.borrow().annotations, vec!["2"]);
+        assert_eq!(empty.borrow().annotations, vec!["0"]);
+        assert!(diagnostics.take().is_empty());
+    }
+
+    #[test]
+    fn run_on_files_runs_confirmed_commands() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().to_str().unwrap();
+        fs::write(temp_dir.path().join("it's.txt"), "").unwrap();
+        fs::write(temp_dir.path().join("skip.txt"), "").unwrap();
+        let root = TreeItem::new_top_level(path.to_string(), true);
+        TreeItem::new(&root, "it's.txt".to_string(), false);
+        TreeItem::new(&root, "skip.txt".to_string(), false);
+        let mut out = Vec::new();
+
+        // Call: a dry run lists the commands, then only the confirmed one runs
+        let dry_run = run_on_files(&root, "mv {} {}.bak", true, &mut |_| true, &mut out).unwrap();
+        let mut asked = Vec::new();
+        let mut confirm = |shown: &str| {
+            asked.push(shown.to_string());
+            !shown.contains("skip")
+        };
+        let summary = run_on_files(&root, "mv {} {}.bak", false, &mut confirm, &mut Vec::new()).unwrap();
+
+        // Verify
+        let quoted = format!("'{}/it'\\''s.txt'", path);
+        assert_eq!(String::from_utf8(out).unwrap().lines().next().unwrap(), format!("mv {} {}.bak", quoted, quoted));
+        assert_eq!(dry_run.to_string(), "Dry run: 2 commands not run");
+        assert_eq!(asked.len(), 2);
+        assert_eq!(summary, ExecSummary { ran: 1, failed: 0, skipped: 1, listed: 0 });
+        assert_eq!(summary.to_string(), "1 command ran, 0 failed, 1 skipped");
+        assert!(temp_dir.path().join("it's.txt.bak").exists());
+        assert!(temp_dir.path().join("skip.txt").exists());
+    }
+
+    #[test]
+    fn run_label_reports_failures_and_timeouts() {
+        let path = Path::new("a 'quoted' name");
+
+        assert_eq!(run_label("printf '%s\\n' {} ok", path, Duration::from_secs(10)), Ok("a 'quoted' name ok".to_string()));
+        assert_eq!(run_label("echo", path, Duration::from_secs(10)), Ok("a 'quoted' name".to_string()));
+        assert!(run_label("exit 3 #", path, Duration::from_secs(10)).unwrap_err().contains("failed"));
+        assert!(run_label("sleep 5 #", path, Duration::from_millis(50)).unwrap_err().contains("timed out"));
+    }
+}
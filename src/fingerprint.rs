@@ -0,0 +1,66 @@
+use crate::check::collect_relative_paths;
+use crate::hash::{sha256, to_hex};
+use crate::tree::TreeItemRefCell;
+use std::rc::Rc;
+
+///
+/// Returns the canonical form of the layout below `root` that `fingerprint` hashes: the paths
+/// relative to `root`, separated by `/`, directories with a trailing `/`, sorted by their bytes,
+/// each followed by a newline:
+///
+/// ```text
+/// Cargo.toml
+/// src/
+/// src/main.rs
+/// ```
+///
+/// Only names and kinds count, so sizes, times, annotations and the name of `root` don't change it.
+///
+pub(crate) fn canonical_str(root: &Rc<TreeItemRefCell>) -> String {
+    let mut entries = Vec::new();
+    collect_relative_paths(root, "", &mut entries);
+    let mut paths: Vec<String> = entries.into_iter().map(|(path, is_dir)| if is_dir { format!("{}/", path) } else { path }).collect();
+    paths.sort();
+    paths.into_iter().map(|path| format!("{}\n", path)).collect()
+}
+
+/// Returns the SHA-256 of `canonical_str` in hex, which changes with the layout below `root`.
+pub(crate) fn fingerprint(root: &Rc<TreeItemRefCell>) -> String {
+    to_hex(&sha256(canonical_str(root).as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_utils::{traverse_fs, TraverseOptions};
+    use crate::tree::TreeItem;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn fingerprint_follows_the_layout_only() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("src")).unwrap();
+        fs::write(temp_dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(temp_dir.path().join("Cargo.toml"), "").unwrap();
+        let read = || {
+            let root = TreeItem::new_top_level(".".to_string(), true);
+            traverse_fs(temp_dir.path().to_str().unwrap(), &root, &TraverseOptions::default());
+            root
+        };
+
+        // Call
+        let before = fingerprint(&read());
+        fs::write(temp_dir.path().join("Cargo.toml"), "[package]").unwrap();
+        let after_edit = fingerprint(&read());
+        fs::rename(temp_dir.path().join("src/main.rs"), temp_dir.path().join("src/lib.rs")).unwrap();
+        let after_rename = fingerprint(&read());
+
+        // Verify
+        assert_eq!(canonical_str(&read()), "Cargo.toml\nsrc/\nsrc/lib.rs\n");
+        assert_eq!(before, after_edit);
+        assert_ne!(before, after_rename);
+        assert_eq!(after_rename, to_hex(&sha256(b"Cargo.toml\nsrc/\nsrc/lib.rs\n")));
+    }
+}
@@ -1,7 +1,262 @@
-use crate::tree::{TreeItem, TreeItemRefCell};
+use crate::cache::Cache;
+use crate::content::is_binary;
+use crate::diagnostics::Diagnostics;
+use crate::ignore::{IgnoreRules, IGNORE_ENV_VAR};
+use crate::i18n;
+use crate::logging;
+use crate::names::NameFindings;
+use crate::size_utils::format_count;
+use crate::throttle::Throttle;
+use crate::time_utils::TimeKind;
+use clap::ValueEnum;
+use crate::tree::{Meta, SpecialFile, TreeItem, TreeItemRefCell};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::env;
+use std::fmt::{Display, Formatter};
 use std::fs;
+use std::io;
 use std::rc::Rc;
-use std::path::Path;
+use std::sync::Arc;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Annotation of directories that aren't read because of `TraverseOptions::collapse`.
+pub(crate) const COLLAPSED_ANNOTATION: &str = "[collapsed]";
+
+/// Options that control which entries `traverse_fs` visits.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct TraverseOptions {
+    /// Skip the `.git` folder and entries excluded by `.gitignore` files, see `gitignore_stack`.
+    pub(crate) gitignore: bool,
+    /// The `.gitignore` files read so far.
+    pub(crate) gitignores: GitignoreCache,
+    /// Maximum depth to descend to, where the children of the top level item have depth 1.
+    /// `None` traverses the whole tree.
+    pub(crate) max_depth: Option<usize>,
+    /// Limits the rate of directory reads and metadata lookups.
+    pub(crate) throttle: Option<Throttle>,
+    /// Only show files created within this duration before now.
+    pub(crate) created_within: Option<Duration>,
+    /// Only show files created before this time.
+    pub(crate) created_before: Option<SystemTime>,
+    /// Only show files accessed within this duration before now.
+    pub(crate) accessed_within: Option<Duration>,
+    /// Skip binary files (see `content::is_binary`).
+    pub(crate) text_only: bool,
+    /// List directories before files, keeping the order of `sort` within each group.
+    pub(crate) dirs_first: bool,
+    /// Order the entries of a directory by the first key, ties by the next one, and so on.
+    /// Remaining ties, and all entries if empty, keep the order of the file system.
+    pub(crate) sort: Vec<SortKey>,
+    /// Names of directories that are listed, but not read, e.g. `target`.
+    pub(crate) collapse: Vec<String>,
+    /// Read the symbolic links to directories matching these rules like directories, e.g.
+    /// `current -> releases/v1.2`. Other links to directories are listed, but never followed.
+    pub(crate) expand_links: Option<IgnoreRules>,
+    /// Count the entries hidden by the filters in each directory (see `TreeItem::hidden`).
+    pub(crate) count_hidden: bool,
+    /// Annotate the directories at `max_depth` with the number of entries below them, see `count_below`.
+    pub(crate) count_below: bool,
+    /// Collects the problems that occur during the traversal, e.g. unreadable directories.
+    pub(crate) diagnostics: Diagnostics,
+    /// Collects the skipped entries with the reason, if set.
+    pub(crate) skipped: Option<SkippedLog>,
+    /// Collects the problems with names found after the traversal, e.g. by `--detect-case-conflicts`.
+    pub(crate) name_findings: NameFindings,
+    /// Skip entries matching these rules, e.g. from `--exclude-from`.
+    pub(crate) exclude_from: Option<IgnoreRules>,
+    /// Skip entries matching these rules, from `FTREE_IGNORE`, unless `exclude_from` re-includes them.
+    pub(crate) env_ignore: Option<IgnoreRules>,
+    /// Only keep entries matching these rules, with their ancestors and descendants, e.g. from
+    /// `--include-from`. Unlike the other filters, it's applied to the whole tree after the
+    /// traversal (see `tree::retain_listed`), as directories have to be read to find the
+    /// entries below them.
+    pub(crate) include_from: Option<IgnoreRules>,
+    /// The top level paths being traversed. Anchored rules like `/docs` match relative to them.
+    pub(crate) roots: Vec<String>,
+}
+
+impl TraverseOptions {
+    /// Returns `path` relative to the longest of `roots` it's below, with `/` separators, or
+    /// `path` itself if it isn't below any.
+    fn relative_path<'a>(&self, path: &'a str) -> &'a str {
+        self.root_of(path).map_or(path, |(_, relative)| relative)
+    }
+
+    /// Returns the longest of `roots` that `path` is or is below, with `path` relative to it,
+    /// which is empty for the root itself.
+    fn root_of<'a>(&self, path: &'a str) -> Option<(&str, &'a str)> {
+        self.roots
+            .iter()
+            .filter_map(|root| {
+                let rest = path.strip_prefix(root.as_str())?;
+                let relative = if rest.is_empty() { rest } else { rest.strip_prefix(['/', '\\'])? };
+                Some((root.as_str(), relative))
+            })
+            .min_by_key(|(_, relative)| relative.len())
+    }
+}
+
+/// Entries skipped during a traversal with the reason, for `--report-skipped`.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct SkippedLog {
+    entries: RefCell<Vec<(String, Exclusion)>>,
+    /// Number of entries removed by the filters applied after the traversal, e.g. `--include-from`
+    removed: RefCell<Vec<(&'static str, usize)>>,
+}
+
+impl SkippedLog {
+    fn record(&self, path: String, reason: Exclusion) {
+        self.entries.borrow_mut().push((path, reason));
+    }
+
+    /// Returns the entries recorded so far and clears them.
+    pub(crate) fn take(&self) -> Vec<(String, Exclusion)> {
+        self.entries.take()
+    }
+
+    /// Records that `filter` removed `count` entries from the tree after the traversal.
+    pub(crate) fn record_removed(&self, filter: &'static str, count: usize) {
+        self.removed.borrow_mut().push((filter, count));
+    }
+
+    /// Returns the number of entries hidden by each filter (see `Exclusion::filter`) and clears
+    /// them. A directory hidden during the traversal counts once, as its entries aren't read.
+    pub(crate) fn take_filter_counts(&self) -> HashMap<&'static str, usize> {
+        let mut counts = HashMap::new();
+        for (_, exclusion) in self.take() {
+            *counts.entry(exclusion.filter()).or_default() += 1;
+        }
+        for (filter, count) in self.removed.take() {
+            *counts.entry(filter).or_default() += count;
+        }
+        counts
+    }
+}
+
+/// The `.gitignore` files and repository roots found so far, by absolute directory, so each
+/// directory is only looked at once per run.
+#[derive(Default, Clone)]
+pub(crate) struct GitignoreCache {
+    dirs: RefCell<HashMap<PathBuf, GitignoreDir>>,
+    /// The real paths of the roots, where they differ from the absolute ones, see `real_root`
+    real_roots: RefCell<HashMap<String, Option<PathBuf>>>,
+}
+
+#[derive(Clone)]
+struct GitignoreDir {
+    rules: Option<Arc<IgnoreRules>>,
+    is_repo_root: bool,
+}
+
+impl std::fmt::Debug for GitignoreCache {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GitignoreCache({} directories)", self.dirs.borrow().len())
+    }
+}
+
+impl GitignoreCache {
+    /// Returns the rules of the `.gitignore` file in `dir`, shown as `source`, and whether `dir` is the
+    /// root of a git repository. Invalid files are recorded in `diagnostics` and skipped.
+    fn get(&self, dir: &Path, source: &Path, diagnostics: &Diagnostics) -> GitignoreDir {
+        if let Some(cached) = self.dirs.borrow().get(dir) {
+            return cached.clone();
+        }
+        let file = dir.join(".gitignore");
+        let rules = if file.is_file() {
+            match IgnoreRules::load(&file) {
+                Ok(rules) => {
+                    logging::debug!("using {}", source.display());
+                    Some(Arc::new(rules))
+                }
+                Err(err) => {
+                    diagnostics.record(&source.to_string_lossy(), "gitignore", err.to_string());
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let cached = GitignoreDir { rules, is_repo_root: dir.join(".git").exists() };
+        self.dirs.borrow_mut().insert(dir.to_path_buf(), cached.clone());
+        cached
+    }
+
+    /// Returns the path of `root` without symbolic links if it differs from the absolute one,
+    /// e.g. `/srv/app/releases/v2` for `/srv/app/current`.
+    fn real_root(&self, root: &str) -> Option<PathBuf> {
+        if let Some(cached) = self.real_roots.borrow().get(root) {
+            return cached.clone();
+        }
+        let real = fs::canonicalize(root).ok().filter(|real| *real != absolute_path(Path::new(root)));
+        self.real_roots.borrow_mut().insert(root.to_string(), real.clone());
+        real
+    }
+}
+
+/// The `.gitignore` rules that apply to the entries of a directory, innermost first, each with
+/// the path of the directory relative to the one of the file and the file as shown to the user.
+type GitignoreStack = Vec<(Arc<IgnoreRules>, String, PathBuf)>;
+
+///
+/// Returns the `.gitignore` files that apply to the entries of the directory at `path`, like git:
+/// the one in `path` and the ones in its parents up to the root of the repository (the first
+/// directory with a `.git`), also above the traversed directory. Each file's patterns are
+/// anchored at its own directory, and deeper files take precedence.
+///
+/// If the traversed directory is a symbolic link, e.g. `/srv/app/current`, the files above it
+/// are looked up above its target, as git does.
+///
+/// Files below the traversed directory are shown like `path`, the ones above it as absolute paths.
+///
+fn gitignore_stack(path: &str, options: &TraverseOptions) -> GitignoreStack {
+    if !options.gitignore {
+        return Vec::new();
+    }
+    let real = options.root_of(path).and_then(|(root, relative)| Some((options.gitignores.real_root(root)?, relative)));
+    let (absolute, levels_below_root) = match real {
+        Some((real_root, "")) => (real_root, Some(0)),
+        Some((real_root, relative)) => (real_root.join(relative), Some(Path::new(relative).components().count())),
+        None => (absolute_path(Path::new(path)), None),
+    };
+    let mut shown = Some(PathBuf::from(path));
+    let mut stack = Vec::new();
+    for (level, dir) in absolute.ancestors().enumerate() {
+        let shown_dir = shown.clone().unwrap_or_else(|| dir.to_path_buf());
+        let source = shown_dir.join(".gitignore");
+        let cached = options.gitignores.get(dir, &source, &options.diagnostics);
+        if let Some(rules) = cached.rules {
+            let prefix = absolute.strip_prefix(dir).unwrap_or(Path::new("")).to_string_lossy().replace('\\', "/");
+            stack.push((rules, prefix, source));
+        }
+        if cached.is_repo_root {
+            break;
+        }
+        // Go up lexically while the path as given has components left, e.g. `./src` to `.`, and
+        // doesn't leave a root that's a symbolic link, whose parent isn't the one of its target
+        shown = shown.filter(|_| levels_below_root.is_none_or(|levels| level < levels));
+        shown = shown.filter(|dir| matches!(dir.components().next_back(), Some(Component::Normal(_)))).map(|dir| match dir.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        });
+    }
+    stack
+}
+
+/// Returns the `.gitignore` file of `stack` that excludes `entry`, if any.
+fn gitignore_exclusion(stack: &GitignoreStack, entry: &Entry) -> Option<PathBuf> {
+    for (rules, prefix, source) in stack {
+        let rel_path = if prefix.is_empty() { entry.name.clone() } else { format!("{}/{}", prefix, entry.name) };
+        match rules.verdict(&rel_path, entry.is_dir) {
+            Some(true) => return Some(source.clone()),
+            Some(false) => return None,
+            None => {}
+        }
+    }
+    None
+}
 
 /// Recursively reads a directory and builds a tree structure.
 ///
@@ -13,63 +268,608 @@ use std::path::Path;
 ///
 /// * `path` - The path to the directory to be read.
 /// * `item` - The tree node to read the children for.
+/// * `options` - Controls which entries are visited.
 ///
 /// # Examples
 ///
 /// ```
 /// let root = TreeItem::new_top_level("/home/user", true);
-/// read_dir_rec("/home/user", &root);
+/// traverse_fs("/home/user", &root, &TraverseOptions::default());
 /// ```
-pub(crate) fn traverse_fs(path: &str, item: &Rc<TreeItemRefCell>, git: bool) {
+pub(crate) fn traverse_fs(path: &str, item: &Rc<TreeItemRefCell>, options: &TraverseOptions) {
+    traverse_dir(path, item, options, 1, None, &mut Vec::new());
+}
 
-    let git_ignore_path = Path::new(path).join(".gitignore");
-    let ignore_matcher = if git {
-        if git_ignore_path.exists() {
-            Some(gitignore::File::new(&git_ignore_path).unwrap())
-        } else {
-            None
+/// Returns the top level item for `path`: a directory to traverse, or a file that is listed on
+/// its own, e.g. for `ftree Cargo.toml`. Paths that can't be read are treated as directories,
+/// so the traversal reports the error. A symbolic link is shown with the metadata of its
+/// target, unless it's broken, but keeps its name.
+pub(crate) fn top_level_item(path: &str) -> Rc<TreeItemRefCell> {
+    match fs::metadata(path).or_else(|_| fs::symlink_metadata(path)) {
+        Ok(metadata) if !Path::new(path).is_dir() => {
+            let entry = Entry::from_metadata(path.to_string(), &metadata);
+            let item = TreeItem::new_top_level(entry.name, false);
+            item.borrow_mut().meta = entry.meta;
+            item
         }
-    } else {
-        None
+        _ => TreeItem::new_top_level(path.to_string(), true),
+    }
+}
+
+/// Same as `traverse_fs`, but takes directory listings from `cache` where they are still
+/// up to date, and updates the cache with all directories that had to be read.
+pub(crate) fn traverse_fs_cached(path: &str, item: &Rc<TreeItemRefCell>, options: &TraverseOptions, cache: &mut Cache) {
+    traverse_dir(path, item, options, 1, Some(cache), &mut Vec::new());
+}
+
+/// `links` are the canonical targets of the expanded links along `path`, to detect cycles.
+fn traverse_dir(
+    path: &str,
+    item: &Rc<TreeItemRefCell>,
+    options: &TraverseOptions,
+    depth: usize,
+    mut cache: Option<&mut Cache>,
+    links: &mut Vec<PathBuf>,
+) {
+    let (entries, hidden) = visible_entries(path, options, cache.as_deref_mut());
+    if options.count_hidden {
+        item.borrow_mut().hidden = hidden;
+    }
+    for mut entry in entries {
+        let link_target = expanded_link(path, &entry, options, links);
+        entry.is_dir |= link_target.is_some();
+        let child_node = TreeItem::new(item, entry.name.clone(), entry.is_dir);
+        child_node.borrow_mut().meta = entry.meta.clone();
+        if is_collapsed(&entry, options) {
+            child_node.borrow_mut().annotations.push(COLLAPSED_ANNOTATION.to_string());
+        }
+        if let Some(target) = &link_target {
+            if let Ok(shown) = fs::read_link(format!("{}/{}", path, entry.name)) {
+                child_node.borrow_mut().annotations.push(format!("-> {}", shown.display()));
+            }
+            links.push(target.clone());
+        }
+
+        // If it's a directory, recursively traverse it
+        if descends_into(path, &entry, options, depth) {
+            let new_path = format!("{}/{}", path, entry.name);
+            traverse_dir(&new_path, &child_node, options, depth + 1, cache.as_deref_mut(), links);
+        } else if options.count_below && entry.is_dir && !is_collapsed(&entry, options) {
+            let count = count_below(&format!("{}/{}", path, entry.name), options, cache.as_deref_mut());
+            if count > 0 {
+                let annotation = match count {
+                    1 => i18n::tr!("… ({} item below)", format_count(count)),
+                    _ => i18n::tr!("… ({} items below)", format_count(count)),
+                };
+                child_node.borrow_mut().annotations.push(annotation);
+            }
+        }
+        if link_target.is_some() {
+            links.pop();
+        }
+    }
+}
+
+/// Counts the entries below the directory at `path` that the filters in `options` keep, at any
+/// depth, without building nodes for them. Collapsed directories count, but their entries don't,
+/// and symbolic links aren't followed.
+fn count_below(path: &str, options: &TraverseOptions, mut cache: Option<&mut Cache>) -> usize {
+    let (entries, _) = visible_entries(path, options, cache.as_deref_mut());
+    let mut count = entries.len();
+    for entry in entries.iter().filter(|entry| entry.is_dir && !is_collapsed(entry, options)) {
+        count += count_below(&format!("{}/{}", path, entry.name), options, cache.as_deref_mut());
+    }
+    count
+}
+
+/// Returns the canonical target of `entry` of the directory at `path` if it's a symbolic link to
+/// a directory that `options.expand_links` selects. Links that would lead back into a directory
+/// that's being read, like `parent -> ..`, aren't expanded and are recorded as a problem.
+fn expanded_link(path: &str, entry: &Entry, options: &TraverseOptions, links: &[PathBuf]) -> Option<PathBuf> {
+    let rules = options.expand_links.as_ref().filter(|_| entry.meta.is_symdir)?;
+    let link_path = format!("{}/{}", path, entry.name);
+    if !rules.matches(&options.relative_path(&link_path).replace('\\', "/"), true) {
+        return None;
+    }
+    let target = fs::canonicalize(&link_path).ok()?;
+    let is_cycle = fs::canonicalize(path).is_ok_and(|dir| dir.starts_with(&target)) || links.contains(&target);
+    if is_cycle {
+        options.diagnostics.record(&link_path, "link_cycle", format!("not expanded, {} is already being read", target.display()));
+        return None;
+    }
+    Some(target)
+}
+
+/// Reads the entries of the directory at `path` (from `cache` if given) and drops the ones
+/// hidden by the filters in `options`. Returns the remaining entries and the number of dropped ones.
+pub(crate) fn visible_entries(path: &str, options: &TraverseOptions, cache: Option<&mut Cache>) -> (Vec<Entry>, usize) {
+    let gitignores = gitignore_stack(path, options);
+
+    if let Some(throttle) = &options.throttle {
+        throttle.acquire(1);
+    }
+    let entries = match cache {
+        Some(cache) => cache.read_entries(path, &options.diagnostics),
+        None => read_entries(path, &options.diagnostics),
     };
+    let entries = entries.unwrap_or_else(|err| {
+        options.diagnostics.record_io(path, &err);
+        Vec::new()
+    });
+    if let Some(throttle) = &options.throttle {
+        // Reading the entries looked up the metadata of each of them
+        throttle.acquire(entries.len() as u32);
+    }
 
-    match fs::read_dir(path) {
-        Ok(dir) => {
-            let dir_entries: Vec<_> = dir.collect::<Result<_, _>>().expect("Unable to read files");
-            for dir_entry in dir_entries.into_iter() {
-                let is_dir = dir_entry.metadata().expect("Unable to read metadata").is_dir();
-                let file_name = dir_entry.file_name();
-                let file_name_str = file_name.to_str().expect("Unable to read the file name");
-                let full_path = Path::new(path).join(&file_name);
-
-                // If git functionality is enabled, skip .git folder and check .gitignore
-                if git {
-                    // Skip .git folder
-                    if file_name_str == ".git" {
-                        continue;
-                    }
-
-                    // Check if the file is ignored by .gitignore
-                    if let Some(ref matcher) = ignore_matcher {
-                        if matcher.is_excluded(&full_path).unwrap() {
-                            continue;
-                        }
-                    }
+    let total = entries.len();
+    let mut entries: Vec<Entry> = entries
+        .into_iter()
+        .filter(|entry| match exclusion(path, entry, options, &gitignores) {
+            Some(reason) => {
+                logging::debug!("excluded {}/{}: {}", path, entry.name, reason);
+                if let Some(skipped) = &options.skipped {
+                    skipped.record(format!("{}/{}", path, entry.name), reason);
                 }
+                false
+            }
+            None => true,
+        })
+        .collect();
+    if !options.sort.is_empty() {
+        entries.sort_by(|a, b| options.sort.iter().fold(Ordering::Equal, |order, key| order.then_with(|| key.compare(a, b))));
+    }
+    if options.dirs_first {
+        entries.sort_by_key(|entry| !entry.is_dir);
+    }
+    let hidden = total - entries.len();
+    (entries, hidden)
+}
 
-                let child_node = TreeItem::new(item, file_name_str.to_string(), is_dir);
-                
-                // If it's a directory, recursively traverse it
-                if is_dir {
-                    let new_path = format!("{}/{}", path, file_name_str);
-                    traverse_fs(&new_path, &child_node, git);
-                }
+/// Returns whether the children of `entry` of the directory at `path` are read, where `entry`
+/// has `depth`. Records directories that aren't read in `options.skipped`.
+pub(crate) fn descends_into(path: &str, entry: &Entry, options: &TraverseOptions, depth: usize) -> bool {
+    if !entry.is_dir {
+        return false;
+    }
+    let reason = match options.max_depth {
+        Some(max) if depth >= max => Exclusion::MaxDepth(max),
+        _ if is_collapsed(entry, options) => Exclusion::Collapsed,
+        _ => return true,
+    };
+    if let Some(skipped) = &options.skipped {
+        skipped.record(format!("{}/{}/…", path, entry.name), reason);
+    }
+    false
+}
+
+/// Returns whether `entry` is a directory that is listed, but not read (see `TraverseOptions::collapse`).
+pub(crate) fn is_collapsed(entry: &Entry, options: &TraverseOptions) -> bool {
+    entry.is_dir && options.collapse.contains(&entry.name)
+}
+
+/// Returns whether `entry` of the directory at `path` is hidden by the filters in `options`.
+pub(crate) fn is_entry_excluded(path: &str, entry: &Entry, options: &TraverseOptions) -> bool {
+    is_excluded(path, entry, options, &gitignore_stack(path, options))
+}
+
+/// The filter that hides an entry.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Exclusion {
+    /// The `.git` folder, with `--gitignore`
+    GitFolder,
+    /// A pattern in the given `.gitignore` file
+    Gitignore(PathBuf),
+    /// A pattern in the given `--exclude-from` file, or in `FTREE_IGNORE`
+    ExcludeFrom(PathBuf),
+    CreatedWithin,
+    CreatedBefore,
+    AccessedWithin,
+    /// A binary or unreadable file, with `--text-only`
+    Binary,
+    /// Deeper than the maximum depth, which is given
+    MaxDepth(usize),
+    /// Inside a directory that is listed, but not read
+    Collapsed,
+}
+
+impl Display for Exclusion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Exclusion::GitFolder => write!(f, "the .git folder is hidden by --gitignore"),
+            Exclusion::Gitignore(file) => write!(f, "ignored by {}", file.display()),
+            Exclusion::ExcludeFrom(file) => write!(f, "excluded by {}", file.display()),
+            Exclusion::CreatedWithin => write!(f, "not created within the --created-within duration"),
+            Exclusion::CreatedBefore => write!(f, "not created before the --created-before date"),
+            Exclusion::AccessedWithin => write!(f, "not accessed within the --accessed-within duration"),
+            Exclusion::Binary => write!(f, "binary file hidden by --text-only"),
+            Exclusion::MaxDepth(max) => write!(f, "deeper than the maximum depth of {}", max),
+            Exclusion::Collapsed => write!(f, "the directory is collapsed, its entries aren't read"),
+        }
+    }
+}
+
+impl Exclusion {
+    /// Returns the option or source of the filter, to group exclusions by, e.g. `--gitignore`.
+    pub(crate) fn filter(&self) -> &'static str {
+        match self {
+            Exclusion::GitFolder | Exclusion::Gitignore(_) => "--gitignore",
+            Exclusion::ExcludeFrom(source) if source == Path::new(IGNORE_ENV_VAR) => IGNORE_ENV_VAR,
+            Exclusion::ExcludeFrom(_) => "--exclude-from",
+            Exclusion::CreatedWithin => "--created-within",
+            Exclusion::CreatedBefore => "--created-before",
+            Exclusion::AccessedWithin => "--accessed-within",
+            Exclusion::Binary => "--text-only",
+            Exclusion::MaxDepth(_) => "depth limit",
+            Exclusion::Collapsed => "collapsed directories",
+        }
+    }
+}
+
+/// Returns the filters that `options` enable, in the order they're applied, named like
+/// `Exclusion::filter`. `--include-from` is applied after the traversal.
+pub(crate) fn active_filters(options: &TraverseOptions) -> Vec<&'static str> {
+    [
+        (options.gitignore, "--gitignore"),
+        (options.exclude_from.is_some(), "--exclude-from"),
+        (options.env_ignore.is_some(), IGNORE_ENV_VAR),
+        (options.created_within.is_some(), "--created-within"),
+        (options.created_before.is_some(), "--created-before"),
+        (options.accessed_within.is_some(), "--accessed-within"),
+        (options.text_only, "--text-only"),
+        (options.max_depth.is_some(), "depth limit"),
+        (!options.collapse.is_empty(), "collapsed directories"),
+        (options.include_from.is_some(), "--include-from"),
+    ]
+    .into_iter()
+    .filter_map(|(active, filter)| active.then_some(filter))
+    .collect()
+}
+
+fn is_excluded(path: &str, entry: &Entry, options: &TraverseOptions, gitignores: &GitignoreStack) -> bool {
+    match exclusion(path, entry, options, gitignores) {
+        Some(exclusion) => {
+            logging::debug!("excluded {}/{}: {}", path, entry.name, exclusion);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns the first filter in `options` that hides `entry` of the directory at `path`.
+fn exclusion(path: &str, entry: &Entry, options: &TraverseOptions, gitignores: &GitignoreStack) -> Option<Exclusion> {
+    // If git functionality is enabled, skip .git folder and check .gitignore
+    if options.gitignore {
+        // Skip .git folder
+        if entry.name == ".git" {
+            return Some(Exclusion::GitFolder);
+        }
+
+        // Check if the file is ignored by a .gitignore
+        if let Some(source) = gitignore_exclusion(gitignores, entry) {
+            return Some(Exclusion::Gitignore(source));
+        }
+    }
+
+    if options.exclude_from.is_some() || options.env_ignore.is_some() {
+        let full_path = format!("{}/{}", path, entry.name);
+        let rel_path = options.relative_path(&full_path).replace('\\', "/");
+        // FTREE_IGNORE has the lowest precedence, so --exclude-from can re-include entries
+        let exclude_from = options.exclude_from.as_ref().and_then(|rules| Some((rules, rules.verdict(&rel_path, entry.is_dir)?)));
+        let env_ignore = options.env_ignore.as_ref().and_then(|rules| Some((rules, rules.verdict(&rel_path, entry.is_dir)?)));
+        if let Some((rules, true)) = exclude_from.or(env_ignore) {
+            return Some(Exclusion::ExcludeFrom(rules.source.clone()));
+        }
+    }
+
+    // Time filters only apply to files, otherwise they'd hide whole directories
+    if !entry.is_dir {
+        let created = TimeKind::Created.of(&entry.meta);
+        let accessed = TimeKind::Accessed.of(&entry.meta);
+        if !is_within(created, options.created_within) {
+            return Some(Exclusion::CreatedWithin);
+        }
+        if created.zip(options.created_before).is_some_and(|(created, before)| created >= before) {
+            return Some(Exclusion::CreatedBefore);
+        }
+        if !is_within(accessed, options.accessed_within) {
+            return Some(Exclusion::AccessedWithin);
+        }
+
+        // Files that can't be read can't be shown as text either
+        if options.text_only && is_binary(&Path::new(path).join(&entry.name)).unwrap_or(true) {
+            return Some(Exclusion::Binary);
+        }
+    }
+    None
+}
+
+/// Returns whether `time` lies within `duration` before now. Unknown times and durations always match.
+fn is_within(time: Option<SystemTime>, duration: Option<Duration>) -> bool {
+    let earliest = duration.and_then(|duration| SystemTime::now().checked_sub(duration));
+    match (time, earliest) {
+        (Some(time), Some(earliest)) => time >= earliest,
+        _ => true,
+    }
+}
+
+/// What to order the entries of a directory by, see `TraverseOptions::sort`.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum SortKey {
+    /// Name, in byte order
+    Name,
+    /// Extension (the part after the last `.`), in byte order. Names without one come first
+    #[value(alias = "ext")]
+    Extension,
+    /// Size, largest first
+    Size,
+    /// Time of the last modification, newest first
+    #[value(alias = "mtime")]
+    Modified,
+    /// Time of creation, newest first
+    #[value(alias = "btime")]
+    Created,
+    /// Time of the last access, newest first
+    #[value(alias = "atime")]
+    Accessed,
+}
+
+impl SortKey {
+    fn compare(&self, a: &Entry, b: &Entry) -> Ordering {
+        let extension = |entry: &Entry| Path::new(&entry.name).extension().map(|ext| ext.to_string_lossy().to_string());
+        match self {
+            SortKey::Name => a.name.cmp(&b.name),
+            SortKey::Extension => extension(a).cmp(&extension(b)),
+            SortKey::Size => b.meta.size.cmp(&a.meta.size),
+            SortKey::Modified => TimeKind::Modified.of(&b.meta).cmp(&TimeKind::Modified.of(&a.meta)),
+            SortKey::Created => TimeKind::Created.of(&b.meta).cmp(&TimeKind::Created.of(&a.meta)),
+            SortKey::Accessed => TimeKind::Accessed.of(&b.meta).cmp(&TimeKind::Accessed.of(&a.meta)),
+        }
+    }
+}
+
+/// A directory entry with the information needed to build a `TreeItem`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Entry {
+    pub(crate) name: String,
+    pub(crate) is_dir: bool,
+    pub(crate) meta: Meta,
+}
+
+/// Turns the root path as given on the command line into the path to traverse and display:
+/// expands a leading `~` to the home directory (for shells that don't), drops `.` components
+/// and trailing separators, and resolves it to an absolute path without symbolic links if
+/// `canonical` is set.
+pub(crate) fn resolve_root(path: &Path, canonical: bool) -> io::Result<PathBuf> {
+    let mut components = path.components().peekable();
+    let mut resolved = match components.peek() {
+        Some(Component::Normal(first)) if *first == "~" => {
+            components.next();
+            home_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "the home directory is unknown"))?
+        }
+        _ => PathBuf::new(),
+    };
+    resolved.extend(components.filter(|component| *component != Component::CurDir));
+    if resolved.as_os_str().is_empty() {
+        resolved.push(".");
+    }
+
+    if !canonical {
+        return Ok(resolved);
+    }
+    let resolved = fs::canonicalize(resolved)?;
+    // Windows returns extended-length paths like `\\?\C:\proj`, show them the usual way
+    match resolved.to_str().and_then(|p| p.strip_prefix(r"\\?\")) {
+        Some(stripped) if !stripped.starts_with("UNC") => Ok(PathBuf::from(stripped)),
+        _ => Ok(resolved),
+    }
+}
+
+/// Returns `path` as an absolute path without `.` and `..` components. Unlike
+/// `fs::canonicalize`, doesn't resolve symbolic links or require the path to exist.
+pub(crate) fn absolute_path(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut normalized = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
             }
+            other => normalized.push(other),
         }
+    }
+    normalized
+}
+
+/// Returns `path` relative to `base`, both from `absolute_path`, with a `..` for each component
+/// of `base` that `path` isn't below, e.g. `../lib/util.rs`. Returns `path` itself if they
+/// share no component, e.g. on different Windows drives.
+pub(crate) fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    let path_components: Vec<_> = path.components().collect();
+    let base_components: Vec<_> = base.components().collect();
+    let common = path_components.iter().zip(&base_components).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return path.to_path_buf();
+    }
+    let mut relative: PathBuf = base_components[common..].iter().map(|_| Component::ParentDir).collect();
+    relative.extend(&path_components[common..]);
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+    relative
+}
+
+/// Expands `path` to the paths matching it if it's a glob like `packages/*/src`, for shells that
+/// don't expand globs (or quoted ones). Returns `path` itself if it exists, isn't a glob or
+/// doesn't match anything, so the traversal reports it as missing.
+pub(crate) fn expand_roots(path: &Path) -> Vec<PathBuf> {
+    let is_glob = path.to_str().is_some_and(|p| p.contains(['*', '?', '[']));
+    if !is_glob || path.exists() {
+        return vec![path.to_path_buf()];
+    }
+    let matches: Vec<PathBuf> = match glob::glob(path.to_str().unwrap()) {
+        Ok(paths) => paths.filter_map(Result::ok).collect(),
         Err(err) => {
-            panic!("Error reading files in {}: {}", path, err)
+            logging::debug!("{} isn't a valid glob: {}", path.display(), err);
+            Vec::new()
+        }
+    };
+    if matches.is_empty() {
+        vec![path.to_path_buf()]
+    } else {
+        matches
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")).map(PathBuf::from)
+}
+
+/// Reads the entries of the directory at `path`, in the order returned by the file system.
+/// Fails if the directory can't be read. Entries that can't be read are skipped and recorded
+/// in `diagnostics`, as are symbolic links whose target doesn't exist.
+pub(crate) fn read_entries(path: &str, diagnostics: &Diagnostics) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for dir_entry in fs::read_dir(path)? {
+        let dir_entry = match dir_entry {
+            Ok(dir_entry) => dir_entry,
+            Err(err) => {
+                diagnostics.record_io(path, &err);
+                continue;
+            }
+        };
+        let entry_path = format!("{}/{}", path, dir_entry.file_name().to_string_lossy());
+        let metadata = match dir_entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                diagnostics.record_io(&entry_path, &err);
+                continue;
+            }
+        };
+        let Ok(name) = dir_entry.file_name().into_string() else {
+            diagnostics.record(&entry_path, "invalid_name", "file name is not valid UTF-8".to_string());
+            continue;
+        };
+        let mut entry = Entry::from_metadata(name, &metadata);
+        if metadata.is_symlink() {
+            match fs::metadata(dir_entry.path()) {
+                Ok(target) => entry.meta.is_symdir = target.is_dir(),
+                Err(_) => diagnostics.record(&entry_path, "broken_link", "target of the symbolic link doesn't exist".to_string()),
+            }
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+impl Entry {
+    pub(crate) fn from_metadata(name: String, metadata: &fs::Metadata) -> Self {
+        let is_dir = metadata.is_dir();
+        let meta = Meta {
+            size: if is_dir { 0 } else { metadata.len() },
+            allocated: allocated_bytes(metadata),
+            modified: metadata.modified().ok(),
+            created: metadata.created().ok(),
+            accessed: metadata.accessed().ok(),
+            is_symlink: metadata.is_symlink(),
+            is_executable: !is_dir && is_executable(metadata),
+            is_symdir: false,
+            special: special_file(metadata),
+        };
+        Entry { name, is_dir, meta }
+    }
+}
+
+#[cfg(unix)]
+fn allocated_bytes(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    // Blocks are counted in units of 512 bytes, whatever the block size of the file system
+    Some(metadata.blocks() * 512)
+}
+
+#[cfg(not(unix))]
+fn allocated_bytes(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn special_file(metadata: &fs::Metadata) -> Option<SpecialFile> {
+    use std::os::unix::fs::FileTypeExt;
+    let file_type = metadata.file_type();
+    [
+        (file_type.is_socket(), SpecialFile::Socket),
+        (file_type.is_fifo(), SpecialFile::Fifo),
+        (file_type.is_block_device(), SpecialFile::BlockDevice),
+        (file_type.is_char_device(), SpecialFile::CharDevice),
+    ]
+    .into_iter()
+    .find_map(|(is_kind, kind)| is_kind.then_some(kind))
+}
+
+#[cfg(not(unix))]
+fn special_file(_metadata: &fs::Metadata) -> Option<SpecialFile> {
+    None
+}
+
+/// Whether the filters show an entry, see `explain`.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Verdict {
+    Included,
+    /// The entry at `path`, the explained path or one of its ancestors, is hidden.
+    Excluded { path: String, reason: Exclusion },
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Verdict::Included => write!(f, "included"),
+            Verdict::Excluded { path, reason } => write!(f, "excluded: {} ({})", path, reason),
+        }
+    }
+}
+
+///
+/// Runs the filters in `options` against `target` and reports the first one that hides it,
+/// checking each directory on the way from `root` down to `target`, as the traversal would.
+///
+/// Fails if `target` doesn't exist or isn't below `root`.
+///
+pub(crate) fn explain(root: &str, target: &Path, options: &TraverseOptions) -> io::Result<Verdict> {
+    let target_abs = target.canonicalize()?;
+    let rel_path = target_abs
+        .strip_prefix(Path::new(root).canonicalize()?)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not below {}", target.display(), root)))?;
+
+    let mut dir = root.to_string();
+    let components: Vec<_> = rel_path.iter().map(|name| name.to_string_lossy().to_string()).collect();
+    for (i, name) in components.iter().enumerate() {
+        let depth = i + 1;
+        let path = format!("{}/{}", dir, name);
+        let entry = Entry::from_metadata(name.clone(), &fs::symlink_metadata(&path)?);
+
+        if let Some(reason) = exclusion(&dir, &entry, options, &gitignore_stack(&dir, options)) {
+            return Ok(Verdict::Excluded { path, reason });
         }
+        if options.max_depth.is_some_and(|max| depth > max) {
+            return Ok(Verdict::Excluded { path, reason: Exclusion::MaxDepth(depth - 1) });
+        }
+        if depth < components.len() && is_collapsed(&entry, options) {
+            return Ok(Verdict::Excluded { path, reason: Exclusion::Collapsed });
+        }
+        dir = path;
     }
+    Ok(Verdict::Included)
 }
 
 #[cfg(test)]
@@ -92,7 +892,7 @@ mod tests {
 
         // Call
         let root = TreeItem::new_top_level(temp_path.to_str().unwrap().to_string(), true);
-        traverse_fs(temp_path.to_str().unwrap(), &root, false);
+        traverse_fs(temp_path.to_str().unwrap(), &root, &TraverseOptions::default());
 
         // Verify
         let root_ref = root.borrow();
@@ -100,7 +900,7 @@ mod tests {
 
         // Sort children by name for consistent ordering in tests
         let mut children: Vec<_> = root_ref.children.iter()
-            .map(|c| Rc::clone(c))
+            .map(Rc::clone)
             .collect();
         children.sort_by(|a, b| a.borrow().text.cmp(&b.borrow().text));
 
@@ -125,6 +925,72 @@ mod tests {
         assert_eq!(file1.children.len(), 0);
     }
 
+    #[test]
+    fn test_traverse_fs_stacks_gitignores() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path().join("repo");
+        for dir in [".git", "build", "sub/build", "sub/deep/cache"] {
+            fs::create_dir_all(repo.join(dir)).unwrap();
+        }
+        for file in ["debug.log", "sub/debug.log", "sub/keep.log", "sub/deep/keep.log"] {
+            File::create(repo.join(file)).unwrap();
+        }
+        fs::write(repo.join(".gitignore"), "/build\n*.log\nsub/deep/cache/\n").unwrap();
+        fs::write(repo.join("sub/.gitignore"), "!keep.log\n").unwrap();
+        // Outside the repository, so it doesn't apply
+        fs::write(temp_dir.path().join(".gitignore"), "sub\n").unwrap();
+
+        // Call: traverse a subdirectory, the .gitignore of the repository root applies to it too
+        let sub = repo.join("sub");
+        let path = sub.to_str().unwrap();
+        let root = TreeItem::new_top_level(path.to_string(), true);
+        let options = TraverseOptions { gitignore: true, skipped: Some(SkippedLog::default()), ..Default::default() };
+        traverse_fs(path, &root, &options);
+
+        // Verify
+        let mut paths = Vec::new();
+        collect_paths(&root, &sub, &mut paths);
+        paths.sort();
+        let expected: Vec<PathBuf> = [".gitignore", "build", "deep", "deep/keep.log", "keep.log"].iter().map(PathBuf::from).collect();
+        assert_eq!(paths, expected);
+        let mut skipped = options.skipped.unwrap().take();
+        skipped.sort_by(|a, b| a.0.cmp(&b.0));
+        let root_gitignore = absolute_path(&repo).join(".gitignore");
+        let expected = vec![
+            (format!("{}/debug.log", path), Exclusion::Gitignore(root_gitignore.clone())),
+            (format!("{}/deep/cache", path), Exclusion::Gitignore(root_gitignore)),
+        ];
+        assert_eq!(skipped, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_traverse_fs_through_linked_root() {
+        // Prepare: `current` outside the repository links to a subdirectory of it
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path().join("repo");
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::create_dir_all(repo.join("releases/v2")).unwrap();
+        File::create(repo.join("releases/v2/app.log")).unwrap();
+        File::create(repo.join("releases/v2/app.bin")).unwrap();
+        fs::write(repo.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(temp_dir.path().join(".gitignore"), "*.bin\n").unwrap();
+        let current = temp_dir.path().join("current");
+        std::os::unix::fs::symlink(repo.join("releases/v2"), &current).unwrap();
+
+        // Call
+        let path = current.to_str().unwrap();
+        let root = TreeItem::new_top_level(path.to_string(), true);
+        let options = TraverseOptions { gitignore: true, roots: vec![path.to_string()], ..Default::default() };
+        traverse_fs(path, &root, &options);
+
+        // Verify: the repository's .gitignore applies, the one next to the link doesn't
+        let names: Vec<String> = root.borrow().children.iter().map(|child| child.borrow().text.clone()).collect();
+        assert_eq!(names, vec!["app.bin"]);
+        assert_eq!(root.borrow().text, path);
+    }
+
     #[test]
     fn test_traverse_fs_with_git() {
         // Prepare
@@ -141,7 +1007,8 @@ mod tests {
 
         // Call
         let root = TreeItem::new_top_level(temp_path.to_str().unwrap().to_string(), true);
-        traverse_fs(temp_path.to_str().unwrap(), &root, true);
+        let options = TraverseOptions { gitignore: true, ..Default::default() };
+        traverse_fs(temp_path.to_str().unwrap(), &root, &options);
 
         // Verify
         let root_ref = root.borrow();
@@ -149,7 +1016,7 @@ mod tests {
 
         // Sort children by name for consistent ordering in tests
         let mut children: Vec<_> = root_ref.children.iter()
-            .map(|c| Rc::clone(c))
+            .map(Rc::clone)
             .collect();
         children.sort_by(|a, b| a.borrow().text.cmp(&b.borrow().text));
 
@@ -179,4 +1046,457 @@ mod tests {
         assert!(!file1.is_dir);
         assert_eq!(file1.children.len(), 0);
     }
+
+    #[test]
+    fn test_traverse_fs_with_max_depth() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        fs::create_dir_all(temp_path.join("dir1/nested")).unwrap();
+        File::create(temp_path.join("dir1/file2.txt")).unwrap().write_all(b"content").unwrap();
+
+        // Call
+        let root = TreeItem::new_top_level(temp_path.to_str().unwrap().to_string(), true);
+        let options = TraverseOptions { max_depth: Some(1), ..Default::default() };
+        traverse_fs(temp_path.to_str().unwrap(), &root, &options);
+
+        // Verify
+        let root_ref = root.borrow();
+        assert_eq!(root_ref.children.len(), 1);
+        let dir1 = root_ref.children[0].borrow();
+        assert_eq!(dir1.text, "dir1");
+        assert!(dir1.is_dir);
+        assert!(dir1.children.is_empty());
+    }
+
+    #[test]
+    fn test_traverse_fs_counts_below_max_depth() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        fs::create_dir_all(temp_path.join("dir1/nested/deeper")).unwrap();
+        File::create(temp_path.join("dir1/file.txt")).unwrap();
+        File::create(temp_path.join("dir1/nested/deeper/skip.log")).unwrap();
+        fs::create_dir(temp_path.join("dir2")).unwrap();
+        File::create(temp_path.join("dir2/only.txt")).unwrap();
+        fs::create_dir(temp_path.join("empty")).unwrap();
+        let excludes = IgnoreRules::parse(Path::new("excludes.txt"), "*.log").unwrap();
+
+        // Call
+        let root = TreeItem::new_top_level(temp_path.to_str().unwrap().to_string(), true);
+        let options = TraverseOptions { max_depth: Some(1), count_below: true, exclude_from: Some(excludes), ..Default::default() };
+        traverse_fs(temp_path.to_str().unwrap(), &root, &options);
+
+        // Verify
+        let root_ref = root.borrow();
+        let annotations: Vec<(String, Vec<String>)> =
+            root_ref.children.iter().map(|child| (child.borrow().text.clone(), child.borrow().annotations.clone())).collect();
+        assert!(annotations.contains(&("dir1".to_string(), vec!["… (3 items below)".to_string()])));
+        assert!(annotations.contains(&("dir2".to_string(), vec!["… (1 item below)".to_string()])));
+        assert!(annotations.contains(&("empty".to_string(), Vec::new())));
+        assert!(root_ref.children.iter().all(|child| child.borrow().children.is_empty()));
+    }
+
+    #[test]
+    fn test_traverse_fs_with_created_filter() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        fs::create_dir(temp_path.join("dir1")).unwrap();
+        File::create(temp_path.join("file1.txt")).unwrap();
+
+        // Call
+        let root = TreeItem::new_top_level(temp_path.to_str().unwrap().to_string(), true);
+        let options = TraverseOptions { created_before: Some(SystemTime::UNIX_EPOCH), ..Default::default() };
+        traverse_fs(temp_path.to_str().unwrap(), &root, &options);
+
+        // Verify: the file is too new, the directory is kept regardless of its time
+        let root_ref = root.borrow();
+        assert_eq!(root_ref.children.len(), 1);
+        assert_eq!(root_ref.children[0].borrow().text, "dir1");
+    }
+
+    #[test]
+    fn test_traverse_fs_text_only() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        fs::create_dir(temp_path.join("dir1")).unwrap();
+        File::create(temp_path.join("text.txt")).unwrap().write_all(b"content").unwrap();
+        File::create(temp_path.join("image.png")).unwrap().write_all(b"\x89PNG\0\0").unwrap();
+
+        // Call
+        let root = TreeItem::new_top_level(temp_path.to_str().unwrap().to_string(), true);
+        let options = TraverseOptions { text_only: true, ..Default::default() };
+        traverse_fs(temp_path.to_str().unwrap(), &root, &options);
+
+        // Verify
+        let mut names: Vec<String> = root.borrow().children.iter().map(|c| c.borrow().text.clone()).collect();
+        names.sort();
+        assert_eq!(names, vec!["dir1", "text.txt"]);
+    }
+
+    #[test]
+    fn test_traverse_fs_count_hidden() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        fs::create_dir(temp_path.join("dir1")).unwrap();
+        File::create(temp_path.join("text.txt")).unwrap().write_all(b"content").unwrap();
+        File::create(temp_path.join("dir1/image.png")).unwrap().write_all(b"\x89PNG\0\0").unwrap();
+        File::create(temp_path.join("dir1/data.bin")).unwrap().write_all(b"\0").unwrap();
+
+        // Call
+        let root = TreeItem::new_top_level(temp_path.to_str().unwrap().to_string(), true);
+        let options = TraverseOptions { text_only: true, count_hidden: true, ..Default::default() };
+        traverse_fs(temp_path.to_str().unwrap(), &root, &options);
+
+        // Verify
+        let root_ref = root.borrow();
+        assert_eq!(root_ref.hidden, 0);
+        let dir1 = root_ref.children.iter().find(|c| c.borrow().is_dir).unwrap();
+        assert_eq!(dir1.borrow().hidden, 2);
+        assert!(dir1.borrow().children.is_empty());
+    }
+
+    #[test]
+    fn test_traverse_fs_dirs_first_and_collapse() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        File::create(temp_path.join("a.txt")).unwrap();
+        fs::create_dir_all(temp_path.join("target/debug")).unwrap();
+        fs::create_dir(temp_path.join("src")).unwrap();
+        File::create(temp_path.join("src/main.rs")).unwrap();
+
+        // Call
+        let root = TreeItem::new_top_level(temp_path.to_str().unwrap().to_string(), true);
+        let options = TraverseOptions { dirs_first: true, collapse: vec!["target".to_string()], ..Default::default() };
+        traverse_fs(temp_path.to_str().unwrap(), &root, &options);
+
+        // Verify
+        let root_ref = root.borrow();
+        let kinds: Vec<bool> = root_ref.children.iter().map(|c| c.borrow().is_dir).collect();
+        assert_eq!(kinds, vec![true, true, false]);
+        for child in &root_ref.children {
+            let child = child.borrow();
+            match child.text.as_str() {
+                "target" => {
+                    assert!(child.children.is_empty());
+                    assert_eq!(child.annotations, vec![COLLAPSED_ANNOTATION]);
+                }
+                "src" => assert_eq!(child.children.len(), 1),
+                _ => {}
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_traverse_fs_expand_links() {
+        use std::os::unix::fs::symlink;
+
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        fs::create_dir_all(temp_path.join("releases/v1.2")).unwrap();
+        File::create(temp_path.join("releases/v1.2/app.bin")).unwrap();
+        symlink("releases/v1.2", temp_path.join("current")).unwrap();
+        symlink("releases", temp_path.join("all")).unwrap();
+        symlink("..", temp_path.join("releases/v1.2/current")).unwrap();
+        let path = temp_path.to_str().unwrap();
+        let rules = IgnoreRules::parse(Path::new("--expand-links"), "current").unwrap();
+        let options = TraverseOptions { expand_links: Some(rules), max_depth: Some(5), roots: vec![path.to_string()], ..Default::default() };
+
+        // Call
+        let root = TreeItem::new_top_level(path.to_string(), true);
+        traverse_fs(path, &root, &options);
+
+        // Verify
+        let current = root.borrow().children.iter().find(|c| c.borrow().text == "current").map(Rc::clone).unwrap();
+        let names: Vec<String> = current.borrow().children.iter().map(|c| c.borrow().to_string()).collect();
+        assert_eq!(current.borrow().to_string(), "current/ -> releases/v1.2");
+        assert_eq!(names.len(), 2);
+        assert!(names.contains(&"app.bin".to_string()) && names.contains(&"current".to_string()));
+        let all = root.borrow().children.iter().find(|c| c.borrow().text == "all").map(Rc::clone).unwrap();
+        assert!(!all.borrow().is_dir);
+        let problems = options.diagnostics.take();
+        assert_eq!(problems.len(), 2);
+        assert!(problems.iter().all(|problem| problem.kind == "link_cycle"));
+    }
+
+    #[test]
+    fn test_traverse_fs_exclude_from() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        fs::create_dir_all(temp_path.join("docs/api")).unwrap();
+        fs::create_dir_all(temp_path.join("src/api")).unwrap();
+        File::create(temp_path.join("src/debug.log")).unwrap();
+        let path = temp_path.to_str().unwrap();
+        let rules = IgnoreRules::parse(Path::new("excludes.txt"), "/docs/api\n*.log").unwrap();
+
+        // Call
+        let root = TreeItem::new_top_level(path.to_string(), true);
+        let options = TraverseOptions { exclude_from: Some(rules), roots: vec![path.to_string()], ..Default::default() };
+        traverse_fs(path, &root, &options);
+
+        // Verify
+        let mut paths = Vec::new();
+        collect_paths(&root, temp_path, &mut paths);
+        paths.sort();
+        assert_eq!(paths, vec![PathBuf::from("docs"), PathBuf::from("src"), Path::new("src").join("api")]);
+    }
+
+    #[test]
+    fn test_traverse_fs_env_ignore_has_lowest_precedence() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        File::create(temp_path.join("debug.log")).unwrap();
+        File::create(temp_path.join("keep.log")).unwrap();
+        let path = temp_path.to_str().unwrap();
+        let env_ignore = IgnoreRules::parse(Path::new("FTREE_IGNORE"), "*.log").unwrap();
+        let exclude_from = IgnoreRules::parse(Path::new("excludes.txt"), "!keep.log").unwrap();
+
+        // Call
+        let root = TreeItem::new_top_level(path.to_string(), true);
+        let options = TraverseOptions { env_ignore: Some(env_ignore), exclude_from: Some(exclude_from), ..Default::default() };
+        traverse_fs(path, &root, &options);
+
+        // Verify
+        let names: Vec<String> = root.borrow().children.iter().map(|c| c.borrow().text.clone()).collect();
+        assert_eq!(names, vec!["keep.log"]);
+    }
+
+    fn collect_paths(item: &Rc<TreeItemRefCell>, root: &Path, paths: &mut Vec<PathBuf>) {
+        for child in &item.borrow().children {
+            paths.push(child.borrow().path().strip_prefix(root).unwrap().to_path_buf());
+            collect_paths(child, root, paths);
+        }
+    }
+
+    #[test]
+    fn test_expand_roots() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        fs::create_dir_all(temp_path.join("packages/a/src")).unwrap();
+        fs::create_dir_all(temp_path.join("packages/b/src")).unwrap();
+        fs::create_dir_all(temp_path.join("packages/c")).unwrap();
+
+        let roots = expand_roots(&temp_path.join("packages/*/src"));
+        let missing = temp_path.join("nothing/*");
+
+        assert_eq!(roots, vec![temp_path.join("packages/a/src"), temp_path.join("packages/b/src")]);
+        assert_eq!(expand_roots(&missing), vec![missing.clone()]);
+        assert_eq!(expand_roots(temp_path), vec![temp_path.to_path_buf()]);
+    }
+
+    #[test]
+    fn test_top_level_item() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("Cargo.toml");
+        File::create(&file_path).unwrap().write_all(b"[package]").unwrap();
+
+        let dir = top_level_item(temp_dir.path().to_str().unwrap());
+        let file = top_level_item(file_path.to_str().unwrap());
+        let missing = top_level_item(temp_dir.path().join("missing").to_str().unwrap());
+
+        assert!(dir.borrow().is_dir);
+        assert!(!file.borrow().is_dir);
+        assert_eq!(file.borrow().meta.size, 9);
+        assert!(missing.borrow().is_dir);
+        #[cfg(unix)]
+        {
+            let link_path = temp_dir.path().join("manifest");
+            std::os::unix::fs::symlink(&file_path, &link_path).unwrap();
+            let link = top_level_item(link_path.to_str().unwrap());
+            assert_eq!((link.borrow().text.as_str(), link.borrow().meta.size), (link_path.to_str().unwrap(), 9));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_read_entries_detects_special_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let _listener = std::os::unix::net::UnixListener::bind(temp_dir.path().join("app.sock")).unwrap();
+        File::create(temp_dir.path().join("plain.txt")).unwrap();
+
+        let mut entries = read_entries(temp_dir.path().to_str().unwrap(), &Diagnostics::default()).unwrap();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        assert_eq!(entries[0].meta.special, Some(SpecialFile::Socket));
+        assert_eq!(entries[1].meta.special, None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_read_entries_reads_allocated_size() {
+        let temp_dir = TempDir::new().unwrap();
+        // Extending a file without writing leaves a hole on file systems that support sparse files
+        File::create(temp_dir.path().join("disk.img")).unwrap().set_len(10 * 1024 * 1024).unwrap();
+
+        let entries = read_entries(temp_dir.path().to_str().unwrap(), &Diagnostics::default()).unwrap();
+
+        assert_eq!(entries[0].meta.size, 10 * 1024 * 1024);
+        assert!(entries[0].meta.allocated.unwrap() < entries[0].meta.size);
+    }
+
+    #[test]
+    fn test_traverse_fs_records_skipped() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        fs::create_dir_all(temp_path.join("target/debug")).unwrap();
+        fs::create_dir_all(temp_path.join("src/nested")).unwrap();
+        File::create(temp_path.join("src/data.bin")).unwrap().write_all(b"\0").unwrap();
+        let path = temp_path.to_str().unwrap();
+
+        // Call
+        let root = TreeItem::new_top_level(path.to_string(), true);
+        let options = TraverseOptions {
+            max_depth: Some(2),
+            text_only: true,
+            collapse: vec!["target".to_string()],
+            skipped: Some(SkippedLog::default()),
+            ..Default::default()
+        };
+        traverse_fs(path, &root, &options);
+
+        // Verify
+        let mut skipped = options.skipped.unwrap().take();
+        skipped.sort_by(|a, b| a.0.cmp(&b.0));
+        let expected = vec![
+            (format!("{}/src/data.bin", path), Exclusion::Binary),
+            (format!("{}/src/nested/…", path), Exclusion::MaxDepth(2)),
+            (format!("{}/target/…", path), Exclusion::Collapsed),
+        ];
+        assert_eq!(skipped, expected);
+    }
+
+    #[test]
+    fn test_skipped_log_counts_per_filter() {
+        let log = SkippedLog::default();
+        log.record("a/.git".to_string(), Exclusion::GitFolder);
+        log.record("a/target".to_string(), Exclusion::Gitignore(PathBuf::from("a/.gitignore")));
+        log.record("a/x.swp".to_string(), Exclusion::ExcludeFrom(PathBuf::from(IGNORE_ENV_VAR)));
+        log.record("a/x.log".to_string(), Exclusion::ExcludeFrom(PathBuf::from("excludes.txt")));
+        log.record_removed("--contains", 7);
+
+        let counts = log.take_filter_counts();
+
+        let expected = HashMap::from([("--gitignore", 2), (IGNORE_ENV_VAR, 1), ("--exclude-from", 1), ("--contains", 7)]);
+        assert_eq!(counts, expected);
+        assert!(log.take_filter_counts().is_empty());
+
+        let options = TraverseOptions { gitignore: true, max_depth: Some(2), env_ignore: Some(IgnoreRules::parse(Path::new(IGNORE_ENV_VAR), "").unwrap()), ..Default::default() };
+        assert_eq!(active_filters(&options), vec!["--gitignore", IGNORE_ENV_VAR, "depth limit"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_traverse_fs_records_problems() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        std::os::unix::fs::symlink(temp_path.join("missing"), temp_path.join("link")).unwrap();
+        let path = temp_path.to_str().unwrap();
+
+        // Call
+        let root = TreeItem::new_top_level(path.to_string(), true);
+        let options = TraverseOptions::default();
+        traverse_fs(path, &root, &options);
+        traverse_fs(&format!("{}/missing", path), &root, &options);
+
+        // Verify: the broken link is still listed
+        assert_eq!(root.borrow().children.len(), 1);
+        let kinds: Vec<&str> = options.diagnostics.take().iter().map(|problem| problem.kind).collect();
+        assert_eq!(kinds, vec!["broken_link", "not_found"]);
+    }
+
+    #[test]
+    fn test_explain() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        fs::create_dir_all(temp_path.join("target/debug")).unwrap();
+        fs::create_dir_all(temp_path.join("src/nested")).unwrap();
+        File::create(temp_path.join("src/nested/deep.rs")).unwrap();
+        File::create(temp_path.join("src/data.bin")).unwrap().write_all(b"\0").unwrap();
+        let root = temp_path.to_str().unwrap();
+        let options = TraverseOptions { max_depth: Some(2), text_only: true, collapse: vec!["target".to_string()], ..Default::default() };
+
+        // Call
+        let verdict = |rel_path: &str| explain(root, &temp_path.join(rel_path), &options).unwrap();
+
+        // Verify
+        assert_eq!(verdict("src/nested"), Verdict::Included);
+        let excluded = |rel_path: &str, reason| Verdict::Excluded { path: format!("{}/{}", root, rel_path), reason };
+        assert_eq!(verdict("src/data.bin"), excluded("src/data.bin", Exclusion::Binary));
+        assert_eq!(verdict("src/nested/deep.rs"), excluded("src/nested/deep.rs", Exclusion::MaxDepth(2)));
+        assert_eq!(verdict("target/debug"), excluded("target", Exclusion::Collapsed));
+        assert!(explain(root, Path::new("/"), &options).is_err());
+    }
+
+    #[test]
+    fn test_resolve_root() {
+        let home = home_dir().unwrap();
+        assert_eq!(resolve_root(Path::new("~/proj/./src/"), false).unwrap(), home.join("proj/src"));
+        assert_eq!(resolve_root(Path::new("~"), false).unwrap(), home);
+        assert_eq!(resolve_root(Path::new("./"), false).unwrap(), PathBuf::from("."));
+        assert_eq!(resolve_root(Path::new("../a/~"), false).unwrap(), PathBuf::from("../a/~"));
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("dir1")).unwrap();
+        let canonical = resolve_root(&temp_dir.path().join("dir1/../dir1"), true).unwrap();
+        assert_eq!(canonical, temp_dir.path().canonicalize().unwrap().join("dir1"));
+    }
+
+    #[test]
+    fn test_traverse_fs_sorts_by_chained_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().to_str().unwrap();
+        for (name, content) in [("b.txt", ""), ("big.rs", "12345"), ("a.rs", ""), ("c.md", "1")] {
+            fs::write(temp_dir.path().join(name), content).unwrap();
+        }
+        fs::create_dir(temp_dir.path().join("dir")).unwrap();
+        let names = |options: &TraverseOptions| {
+            let root = TreeItem::new_top_level(path.to_string(), true);
+            traverse_fs(path, &root, options);
+            let names: Vec<String> = root.borrow().children.iter().map(|child| child.borrow().text.clone()).collect();
+            names
+        };
+
+        let options = TraverseOptions { sort: vec![SortKey::Size, SortKey::Name], ..Default::default() };
+        assert_eq!(names(&options), vec!["big.rs", "c.md", "a.rs", "b.txt", "dir"]);
+
+        let options = TraverseOptions { sort: vec![SortKey::Extension, SortKey::Name], dirs_first: true, ..Default::default() };
+        assert_eq!(names(&options), vec!["dir", "c.md", "a.rs", "big.rs", "b.txt"]);
+    }
+
+    #[test]
+    fn test_relative_to() {
+        let base = absolute_path(Path::new("/repo/sub/./x/.."));
+        assert_eq!(base, PathBuf::from("/repo/sub"));
+
+        assert_eq!(relative_to(Path::new("/repo/sub/src/main.rs"), &base), PathBuf::from("src/main.rs"));
+        assert_eq!(relative_to(Path::new("/repo/lib"), &base), PathBuf::from("../lib"));
+        assert_eq!(relative_to(Path::new("/repo/sub"), &base), PathBuf::from("."));
+        assert_eq!(relative_to(Path::new("/other"), &base), PathBuf::from("../../other"));
+        assert_eq!(absolute_path(Path::new("a/b")), std::env::current_dir().unwrap().join("a/b"));
+    }
+
+    #[test]
+    fn test_is_within() {
+        let now = SystemTime::now();
+        let day = Duration::from_secs(86400);
+        assert!(is_within(Some(now), Some(day)));
+        assert!(!is_within(Some(now - 2 * day), Some(day)));
+        assert!(is_within(None, Some(day)));
+        assert!(is_within(Some(now - 2 * day), None));
+    }
 }
@@ -0,0 +1,37 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Returns the paths of the files below `dir` that differ from `git_ref`, relative to `dir`
+/// with `/` separators, like `git diff --name-only <git_ref>`. Includes uncommitted changes,
+/// but not untracked files.
+pub(crate) fn changed_since(dir: &Path, git_ref: &str) -> io::Result<HashSet<String>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["diff", "--name-only", "--relative", "-z", git_ref, "--"])
+        .output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(format!("git diff failed: {}", message)));
+    }
+    Ok(parse_name_list(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses the NUL-separated output of `git diff --name-only -z`.
+fn parse_name_list(output: &str) -> HashSet<String> {
+    output.split('\0').filter(|path| !path.is_empty()).map(str::to_string).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_name_list_splits_on_nul() {
+        let paths = parse_name_list("src/main.rs\0docs/with space.md\0");
+        assert_eq!(paths, HashSet::from(["src/main.rs".to_string(), "docs/with space.md".to_string()]));
+        assert!(parse_name_list("").is_empty());
+    }
+}
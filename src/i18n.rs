@@ -0,0 +1,235 @@
+use clap::ValueEnum;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Environment variables that name the locale of messages, in the order POSIX looks them up.
+const LOCALE_ENV_VARS: [&str; 3] = ["LC_ALL", "LC_MESSAGES", "LANG"];
+
+/// German messages by their English text. `{}` takes the next argument, `{0}`, `{1}`, ... the one
+/// at that position, for languages that need another order.
+const CATALOG_DE: [(&str, &str); 87] = [
+    // Legend and sections
+    ("Legend:", "Legende:"),
+    ("directory", "Verzeichnis"),
+    ("symbolic link", "symbolischer Link"),
+    ("executable file", "ausführbare Datei"),
+    ("file size in bytes", "Dateigröße in Bytes"),
+    ("file size in binary units", "Dateigröße in Binäreinheiten"),
+    ("last modification", "letzte Änderung"),
+    ("creation, or last modification if unknown", "Erstellung, oder letzte Änderung, falls unbekannt"),
+    ("last access", "letzter Zugriff"),
+    ("added since the last run", "seit dem letzten Lauf hinzugekommen"),
+    ("removed since the last run", "seit dem letzten Lauf entfernt"),
+    ("size changed since the last run", "Größe seit dem letzten Lauf geändert"),
+    ("lines containing the searched text", "Zeilen mit dem gesuchten Text"),
+    ("entries hidden by filters", "durch Filter ausgeblendete Einträge"),
+    ("directory not read, e.g. build output", "nicht gelesenes Verzeichnis, z. B. Build-Ausgabe"),
+    ("share of the data shared with other files, e.g. by reflinks", "Anteil der Daten, den die Datei mit anderen teilt, z. B. per Reflink"),
+    ("sparse file, with the share of its size allocated on disk", "Sparse-Datei, mit dem auf der Platte belegten Anteil ihrer Größe"),
+    ("permissions differ from most files in the directory", "Berechtigungen weichen von den meisten Dateien im Verzeichnis ab"),
+    ("name differs from a sibling's only by case", "Name unterscheidet sich von einem Nachbarn nur in der Groß-/Kleinschreibung"),
+    ("name that causes trouble on some platform", "Name, der auf manchen Plattformen Probleme macht"),
+    ("well-known file, e.g. a Dockerfile", "bekannte Datei, z. B. ein Dockerfile"),
+    ("file the user can't read", "Datei, die der Benutzer nicht lesen kann"),
+    ("directory the user can enter, but not list", "Verzeichnis, das der Benutzer betreten, aber nicht auflisten kann"),
+    ("directory the user can't enter", "Verzeichnis, das der Benutzer nicht betreten kann"),
+    ("a directory above blocks the user", "ein übergeordnetes Verzeichnis sperrt den Benutzer aus"),
+    ("owners from CODEOWNERS, where they differ from the directory's", "Besitzer aus CODEOWNERS, wo sie von denen des Verzeichnisses abweichen"),
+    ("CODEOWNERS rule without owners", "CODEOWNERS-Regel ohne Besitzer"),
+    ("Skipped:", "Übersprungen:"),
+    ("Name problems:", "Namensprobleme:"),
+    ("none", "keine"),
+    ("… ({} item below)", "… ({} Eintrag darunter)"),
+    ("… ({} items below)", "… ({} Einträge darunter)"),
+    ("Combined:", "Zusammen:"),
+    ("Shared paths:", "Gemeinsame Pfade:"),
+    ("… {} more", "… {} weitere"),
+    ("… {} hidden item", "… {} ausgeblendeter Eintrag"),
+    ("… {} hidden items", "… {} ausgeblendete Einträge"),
+    ("No active filters", "Keine aktiven Filter"),
+    ("Hidden entries per filter:", "Ausgeblendete Einträge je Filter:"),
+    ("Fingerprint: {}", "Fingerabdruck: {}"),
+    // Statistics
+    ("dirs", "Verz."),
+    ("files", "Dateien"),
+    ("size", "Größe"),
+    ("total", "gesamt"),
+    ("owner", "Besitzer"),
+    ("mode", "Modus"),
+    ("entries", "Einträge"),
+    ("depth", "Tiefe"),
+    ("bytes", "Bytes"),
+    ("(files)", "(Dateien)"),
+    ("(other)", "(Rest)"),
+    ("< 1 week", "< 1 Woche"),
+    ("< 1 month", "< 1 Monat"),
+    ("< 6 months", "< 6 Monate"),
+    ("< 1 year", "< 1 Jahr"),
+    ("≥ 1 year", "≥ 1 Jahr"),
+    ("no entries", "keine Einträge"),
+    ("no files", "keine Dateien"),
+    ("deepest: {} (depth {})", "am tiefsten: {} (Tiefe {})"),
+    ("average depth: {}", "mittlere Tiefe: {}"),
+    // Commands
+    ("{} violation", "{} Verstoß"),
+    ("{} violations", "{} Verstöße"),
+    ("Serving {} on http://{}", "Stelle {} bereit auf http://{}"),
+    ("Dry run: {} command not run", "Probelauf: {} Befehl nicht ausgeführt"),
+    ("Dry run: {} commands not run", "Probelauf: {} Befehle nicht ausgeführt"),
+    ("{} command ran, {} failed", "{} Befehl ausgeführt, {} fehlgeschlagen"),
+    ("{} commands ran, {} failed", "{} Befehle ausgeführt, {} fehlgeschlagen"),
+    (", {} skipped", ", {} übersprungen"),
+    // Errors
+    ("Error: {}", "Fehler: {}"),
+    ("Error reading {}: {}", "Fehler beim Lesen von {}: {}"),
+    ("Error serving {}: {}", "Fehler beim Bereitstellen von {}: {}"),
+    ("Error updating ftree: {}", "Fehler beim Aktualisieren von ftree: {}"),
+    ("Error resolving {}: {}", "Fehler beim Auflösen von {}: {}"),
+    ("Error in the badges of {}: {}", "Fehler in den Badges von {}: {}"),
+    ("Error explaining {}: {}", "Fehler beim Erklären von {}: {}"),
+    ("Error reading the disk space of {}: {}", "Fehler beim Lesen des Speicherplatzes von {}: {}"),
+    ("Error listing the changes since {}: {}", "Fehler beim Auflisten der Änderungen seit {}: {}"),
+    ("Error looking up user {}: {}", "Fehler beim Nachschlagen des Benutzers {}: {}"),
+    ("Error: there's no row {} in the tree of {}", "Fehler: Im Baum von {1} gibt es keine Zeile {0}"),
+    (
+        "Error: no CODEOWNERS file in {} (looked in .github/, the top level, docs/ and .gitlab/)",
+        "Fehler: keine CODEOWNERS-Datei in {} (gesucht in .github/, der obersten Ebene, docs/ und .gitlab/)",
+    ),
+    ("Invalid {}: {}", "Ungültiger Wert für {}: {}"),
+    ("Error writing output: {}", "Fehler beim Schreiben der Ausgabe: {}"),
+    ("Unable to write cache {}: {}", "Cache {} kann nicht geschrieben werden: {}"),
+    ("{} doesn't contain a tree", "{} enthält keinen Baum"),
+    ("Error running {}: {}", "Fehler beim Ausführen von {}: {}"),
+    ("Error handling request: {}", "Fehler beim Bearbeiten der Anfrage: {}"),
+    (
+        "Unable to lower I/O priority with ionice, continuing with the default priority",
+        "I/O-Priorität kann mit ionice nicht gesenkt werden, weiter mit der Standardpriorität",
+    ),
+];
+
+/// Language of the legend, the sections after the trees and the messages on stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub(crate) enum Locale {
+    #[default]
+    En,
+    De,
+}
+
+static LOCALE: AtomicU8 = AtomicU8::new(Locale::En as u8);
+
+pub(crate) fn set_locale(locale: Locale) {
+    LOCALE.store(locale as u8, Ordering::Relaxed);
+}
+
+pub(crate) fn locale() -> Locale {
+    match LOCALE.load(Ordering::Relaxed) {
+        x if x == Locale::De as u8 => Locale::De,
+        _ => Locale::En,
+    }
+}
+
+impl Locale {
+    /// Returns the locale of the first non-empty `LOCALE_ENV_VARS`, English if it isn't translated.
+    pub(crate) fn detect() -> Locale {
+        let value = LOCALE_ENV_VARS.iter().filter_map(|var| std::env::var(var).ok()).find(|value| !value.is_empty());
+        value.map_or(Locale::En, |value| Locale::from_env_value(&value))
+    }
+
+    /// Returns the locale of a value like `de_DE.UTF-8`, English if it isn't translated.
+    fn from_env_value(value: &str) -> Locale {
+        let language = value.split(['_', '.', '@']).next().unwrap_or_default();
+        match language.to_lowercase().as_str() {
+            "de" => Locale::De,
+            _ => Locale::En,
+        }
+    }
+
+    fn catalog(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Locale::En => &[],
+            Locale::De => &CATALOG_DE,
+        }
+    }
+}
+
+/// Returns the translation of the English `text` in `locale`, or `text` if there's none.
+pub(crate) fn translate_to(locale: Locale, text: &str) -> &str {
+    locale.catalog().iter().find(|(english, _)| *english == text).map_or(text, |(_, translated)| translated)
+}
+
+/// Returns the translation of the English `text` in the current locale, or `text` if there's none.
+pub(crate) fn translate(text: &str) -> &str {
+    translate_to(locale(), text)
+}
+
+/// Replaces the placeholders of `template` by `args`: `{}` by the next one, `{N}` by the one at `N`.
+pub(crate) fn format_message(template: &str, args: &[&dyn Display]) -> String {
+    let mut out = String::new();
+    let mut next = 0;
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else { break };
+        let index = match &rest[start + 1..start + len] {
+            "" => {
+                next += 1;
+                next - 1
+            }
+            position => match position.parse::<usize>() {
+                Ok(position) => position,
+                Err(_) => {
+                    out.push_str(&rest[..start + len + 1]);
+                    rest = &rest[start + len + 1..];
+                    continue;
+                }
+            },
+        };
+        out.push_str(&rest[..start]);
+        if let Some(arg) = args.get(index) {
+            out.push_str(&arg.to_string());
+        }
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Formats an English message like `format!`, translated to the current locale.
+macro_rules! tr {
+    ($template:literal $(, $arg:expr)* $(,)?) => {
+        $crate::i18n::format_message($crate::i18n::translate($template), &[$(&$arg as &dyn std::fmt::Display),*])
+    };
+}
+
+pub(crate) use tr;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locale_from_env_values() {
+        assert_eq!(Locale::from_env_value("de_DE.UTF-8"), Locale::De);
+        assert_eq!(Locale::from_env_value("de_AT@euro"), Locale::De);
+        assert_eq!(Locale::from_env_value("en_US.UTF-8"), Locale::En);
+        assert_eq!(Locale::from_env_value("C"), Locale::En);
+        assert_eq!(Locale::from_env_value("fr_FR"), Locale::En);
+    }
+
+    #[test]
+    fn messages_are_translated_and_formatted() {
+        let template = translate_to(Locale::De, "Error: there's no row {} in the tree of {}");
+
+        assert_eq!(format_message(template, &[&7, &"./src"]), "Fehler: Im Baum von ./src gibt es keine Zeile 7");
+        assert_eq!(translate_to(Locale::De, "not in the catalog"), "not in the catalog");
+        assert_eq!(translate_to(Locale::En, "Legend:"), "Legend:");
+        assert_eq!(format_message("Error reading {}: {}", &[&"a.toml", &"denied"]), "Error reading a.toml: denied");
+    }
+
+    #[test]
+    fn translations_keep_the_placeholders() {
+        let placeholders = |text: &str| text.matches('{').count();
+        for (english, translated) in CATALOG_DE {
+            assert_eq!(placeholders(english), placeholders(translated), "{}", english);
+        }
+    }
+}
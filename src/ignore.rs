@@ -0,0 +1,158 @@
+use glob::{MatchOptions, Pattern};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Environment variable with a personal list of patterns that are always excluded.
+pub(crate) const IGNORE_ENV_VAR: &str = "FTREE_IGNORE";
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions { case_sensitive: true, require_literal_separator: true, require_literal_leading_dot: false };
+
+/// A pattern of a rule file, e.g. `target/` or `!keep.log`.
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: Pattern,
+    /// Re-includes what earlier rules excluded (`!` prefix)
+    negated: bool,
+    /// Only matches directories (`/` suffix)
+    dirs_only: bool,
+    /// Matches the path relative to the root instead of the name at any depth. True for
+    /// patterns with a `/` other than at the end, as in gitignore.
+    anchored: bool,
+}
+
+///
+/// Rules in gitignore syntax, from a `.gitignore` or a file given on the command line, e.g.
+/// `--exclude-from`:
+///
+/// ```text
+/// # Comments and blank lines are skipped
+/// *.log        # any file or directory named *.log, at any depth
+/// build/       # only directories
+/// /docs/api    # relative to the root
+/// !keep.log    # re-include what an earlier rule matched
+/// docs/**/*.md # `**` matches any number of directories, also none
+/// \#notes      # a leading `\` escapes `#` and `!`
+/// ```
+///
+/// Patterns with a `/` are relative to the directory of the file (or the root), the others match
+/// names at any depth. The last matching rule wins.
+///
+#[derive(Debug, Clone)]
+pub(crate) struct IgnoreRules {
+    /// The file the rules were read from.
+    pub(crate) source: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl IgnoreRules {
+    pub(crate) fn load(source: &Path) -> io::Result<IgnoreRules> {
+        let text = fs::read_to_string(source)?;
+        IgnoreRules::parse(source, &text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Reads the patterns of `FTREE_IGNORE`, separated like `PATH` (`:`, or `;` on Windows).
+    /// Returns `None` if it isn't set.
+    pub(crate) fn from_env() -> Option<Result<IgnoreRules, String>> {
+        let value = env::var_os(IGNORE_ENV_VAR)?;
+        let text = env::split_paths(&value).map(|pattern| pattern.to_string_lossy().to_string()).collect::<Vec<_>>().join("\n");
+        Some(IgnoreRules::parse(Path::new(IGNORE_ENV_VAR), &text))
+    }
+
+    pub(crate) fn parse(source: &Path, text: &str) -> Result<IgnoreRules, String> {
+        let mut rules = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').filter(|rest| rest.starts_with(['#', '!'])).unwrap_or(line)),
+            };
+            let (dirs_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            let glob = line.strip_prefix('/').unwrap_or(line);
+            let pattern = Pattern::new(glob).map_err(|err| format!("line {}: invalid pattern '{}': {}", i + 1, line, err))?;
+            rules.push(Rule { pattern, negated, dirs_only, anchored });
+        }
+        Ok(IgnoreRules { source: source.to_path_buf(), rules })
+    }
+
+    /// Returns whether the rules match the entry at `rel_path` (relative to the root, with `/`
+    /// separators), i.e. exclude it for `--exclude-from` or list it for `--include-from`.
+    pub(crate) fn matches(&self, rel_path: &str, is_dir: bool) -> bool {
+        self.verdict(rel_path, is_dir) == Some(true)
+    }
+
+    /// Returns whether the last matching rule matches (`true`) or re-includes (`false`) the
+    /// entry at `rel_path`, or `None` if no rule matches it. Lets rules of a higher precedence
+    /// source override the ones of a lower one.
+    pub(crate) fn verdict(&self, rel_path: &str, is_dir: bool) -> Option<bool> {
+        let name = rel_path.rsplit('/').next().unwrap_or(rel_path);
+        let matching = self.rules.iter().rev().find(|rule| {
+            let candidate = if rule.anchored { rel_path } else { name };
+            (is_dir || !rule.dirs_only) && rule.pattern.matches_with(candidate, MATCH_OPTIONS)
+        });
+        matching.map(|rule| !rule.negated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_match() {
+        let text = "# build output\n\n*.log\nbuild/\n/docs/api\nsrc/**/gen\n!keep.log\n";
+        let rules = IgnoreRules::parse(Path::new("excludes.txt"), text).unwrap();
+
+        assert!(rules.matches("a/b/debug.log", false));
+        assert!(!rules.matches("a/keep.log", false));
+        assert!(rules.matches("x/build", true));
+        assert!(!rules.matches("x/build", false));
+        assert!(rules.matches("docs/api", true));
+        assert!(!rules.matches("x/docs/api", true));
+        assert!(rules.matches("src/a/b/gen", true));
+        assert!(!rules.matches("main.rs", false));
+        assert_eq!(rules.verdict("a/keep.log", false), Some(false));
+        assert_eq!(rules.verdict("main.rs", false), None);
+    }
+
+    #[test]
+    fn match_like_git() {
+        // Pattern, path, whether it's a directory, whether git ignores it
+        let cases = [
+            ("/build", "build", true, true),
+            ("/build", "src/build", true, false),
+            ("build", "src/build", true, true),
+            ("build/", "src/build", false, false),
+            ("doc/frotz", "doc/frotz", true, true),
+            ("doc/frotz", "a/doc/frotz", true, false),
+            ("**/foo", "foo", false, true),
+            ("**/foo", "a/b/foo", false, true),
+            ("**/foo/bar", "x/foo/bar", false, true),
+            ("abc/**", "abc/x/y", false, true),
+            ("a/**/b", "a/b", false, true),
+            ("a/**/b", "a/x/y/b", false, true),
+            ("a/*/b", "a/x/y/b", false, false),
+            ("*.log", "logs/debug.log", false, true),
+            ("\\#notes", "#notes", false, true),
+            ("\\!important", "!important", false, true),
+        ];
+        for (pattern, path, is_dir, ignored) in cases {
+            let rules = IgnoreRules::parse(Path::new(".gitignore"), pattern).unwrap();
+            assert_eq!(rules.matches(path, is_dir), ignored, "{} against {}", pattern, path);
+        }
+    }
+
+    #[test]
+    fn parse_rejects_invalid_patterns() {
+        let err = IgnoreRules::parse(Path::new("excludes.txt"), "ok\n[unclosed").unwrap_err();
+        assert_eq!(err.lines().next().unwrap().split(':').next(), Some("line 2"));
+    }
+}
@@ -0,0 +1,37 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Messages about phases and configuration, shown with `-v`.
+pub(crate) const INFO: u8 = 1;
+/// Messages about every single decision, e.g. why an entry was excluded, shown with `-vv`.
+pub(crate) const DEBUG: u8 = 2;
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(0);
+
+pub(crate) fn set_verbosity(level: u8) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+pub(crate) fn enabled(level: u8) -> bool {
+    VERBOSITY.load(Ordering::Relaxed) >= level
+}
+
+/// Logs to stderr with `-v`.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::INFO) {
+            eprintln!("[ftree] {}", format_args!($($arg)*));
+        }
+    };
+}
+
+/// Logs to stderr with `-vv`.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::DEBUG) {
+            eprintln!("[ftree] {}", format_args!($($arg)*));
+        }
+    };
+}
+
+pub(crate) use debug;
+pub(crate) use info;
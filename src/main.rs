@@ -1,29 +1,1096 @@
+mod access;
+mod cache;
+mod check;
+mod clones;
+mod codeowners;
+mod content;
+mod diagnostics;
+mod disk;
+mod exec;
+mod fingerprint;
 mod fs_utils;
+mod git;
+mod hash;
+mod i18n;
+mod ignore;
+mod logging;
+mod names;
+mod perms;
+mod project;
+mod serve;
+mod shell;
+mod size_utils;
+mod stats;
+mod throttle;
+mod time_utils;
 mod tree;
-use std::path::PathBuf;
-use crate::tree::TreeItem;
-use clap::Parser;
+mod update;
+use std::ffi::OsString;
+use std::io::{self, BufWriter, Write};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+use crate::access::User;
+use crate::cache::{Cache, Change, DiffFormat};
+use crate::codeowners::CodeOwners;
+use crate::diagnostics::ErrorFormat;
+use crate::fs_utils::{SkippedLog, SortKey, TraverseOptions};
+use crate::ignore::IgnoreRules;
+use crate::project::ProjectConfig;
+use crate::shell::Shell;
+use crate::size_utils::SizeFormat;
+use crate::stats::StatsKind;
+use crate::throttle::Throttle;
+use crate::time_utils::{DirTimes, TimeKind, TimeStyle};
+use crate::tree::badges::Badges;
+use crate::tree::legend::Marker;
+use crate::tree::{Priority, RenderOptions, SymdirPolicy, Theme, TreeItem, TreeItemRefCell};
+use clap::{ArgAction, Parser, Subcommand};
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Exclude git-related files and directories from the output
     #[arg(long)]
     gitignore: bool,
 
-    /// The directory to visualize (defaults to current directory if not specified)
+    /// Detect the project type (Cargo.toml, package.json, pyproject.toml) and apply curated defaults:
+    /// --gitignore, directories first, depth 4 and collapsed build directories
+    #[arg(long)]
+    smart: bool,
+
+    /// Ignore the per-project defaults of a .ftree.toml in the directory or its parents
+    #[arg(long)]
+    no_config: bool,
+
+    /// Print the date of the last modification (or the time selected with --time) before each name
+    #[arg(short = 'D')]
+    date: bool,
+
+    /// Which time to print with -D
+    #[arg(long, value_enum, value_name = "TIME", requires = "date")]
+    time: Option<TimeKind>,
+
+    /// How to format the times of -D
+    #[arg(long, value_enum, value_name = "STYLE", default_value = "iso", requires = "date")]
+    time_style: TimeStyle,
+
+    /// Which time -D shows for directories: their own, or the newest of the files anywhere below them
+    #[arg(long, value_enum, value_name = "WHICH", default_value = "own", requires = "date", conflicts_with = "stream")]
+    dir_times: DirTimes,
+
+    /// Print the size of files in bytes in a right-aligned column before each row
+    #[arg(short = 's', long)]
+    size: bool,
+
+    /// Print sizes with binary units, e.g. 1.5 KiB. Implies --size
+    #[arg(long)]
+    human: bool,
+
+    /// Minimum width of the size column
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    size_width: usize,
+
+    /// Show this after the names of directories instead of `/`. Use '' to show nothing
+    #[arg(long, value_name = "SUFFIX")]
+    dir_suffix: Option<String>,
+
+    /// Show this after the names of symbolic links, e.g. @
+    #[arg(long, value_name = "INDICATOR")]
+    symlink_indicator: Option<String>,
+
+    /// Show this after the names of executable files, e.g. *
+    #[arg(long, value_name = "INDICATOR")]
+    exec_indicator: Option<String>,
+
+    /// Show symbolic links to directories like directories or like other links, also in --stats. They're only followed with --expand-links
+    #[arg(long, value_enum, value_name = "POLICY", default_value = "as-link")]
+    symdirs: SymdirPolicy,
+
+    /// Print a type code (DIR, FIL, LNK, SOC, FIF, BLK or CHR) in a column before each row, e.g. for monochrome logs
+    #[arg(long, conflicts_with = "fzf")]
+    type_column: bool,
+
+    /// Prefix each row with its number, e.g. to refer to "item 37" in a review. See --select
+    #[arg(long, conflicts_with_all = ["fzf", "stream", "bfs", "stats"])]
+    number: bool,
+
+    /// Print the full paths of the rows with the given numbers of --number instead of the tree, e.g. 12,37
+    #[arg(long, value_name = "N,...", value_delimiter = ',', conflicts_with_all = ["fzf", "stream", "bfs", "stats", "number", "legend"])]
+    select: Vec<usize>,
+
+    /// Drop the least important rows (deepest, then smallest) until the tree fits in N characters, e.g. for an LLM prompt
+    #[arg(long, value_name = "N", conflicts_with_all = ["fzf", "stream", "bfs", "stats", "number", "select"])]
+    budget_chars: Option<usize>,
+
+    /// Keep the entries matching this pattern (gitignore syntax) before all others with --budget-chars, e.g. 'src/**'. Can be repeated
+    #[arg(long, value_name = "PATTERN", requires = "budget_chars")]
+    keep: Vec<String>,
+
+    /// Drop the entries matching this pattern (gitignore syntax) before all others with --budget-chars, e.g. 'tests/'. Can be repeated
+    #[arg(long, value_name = "PATTERN", requires = "budget_chars")]
+    drop_first: Vec<String>,
+
+    /// Order the entries of each directory by this key instead of the file system order
+    #[arg(long, value_enum, value_name = "KEY")]
+    sort: Option<SortKey>,
+
+    /// Order entries with the same --sort key by this key, e.g. --sort size --then name. Can be repeated
+    #[arg(long, value_enum, value_name = "KEY", requires = "sort")]
+    then: Vec<SortKey>,
+
+    /// Color the branch lines and names by nesting level, like rainbow brackets, to follow very deep trees. Off if NO_COLOR is set
+    #[arg(long, conflicts_with_all = ["fzf", "stream", "bfs", "stats"])]
+    depth_colors: bool,
+
+    /// Colors of --depth-colors for a dark or light terminal background [default: detected from COLORFGBG, else dark]
+    #[arg(long, value_enum, requires = "depth_colors")]
+    theme: Option<Theme>,
+
+    /// Show a `… 7 hidden items` row in each directory with entries hidden by filters, e.g. --gitignore
+    #[arg(long, conflicts_with_all = ["fzf", "bfs"])]
+    count_hidden: bool,
+
+    /// With a depth limit (--smart or `depth` in .ftree.toml), count the entries below the directories that aren't read
+    #[arg(long, conflicts_with = "stream")]
+    count_below: bool,
+
+    /// Mark symbolic links with @ and executable files with *, like `ls -F`
+    #[arg(short = 'F', long)]
+    classify: bool,
+
+    /// Only show files created within the given duration, e.g. 30m, 12h, 7d or 2w
+    #[arg(long, value_name = "DURATION", value_parser = time_utils::parse_duration)]
+    created_within: Option<Duration>,
+
+    /// Only show files created before the given date, e.g. 2024-03-31
+    #[arg(long, value_name = "DATE", value_parser = time_utils::parse_date)]
+    created_before: Option<SystemTime>,
+
+    /// Only show files accessed within the given duration, e.g. 30m, 12h, 7d or 2w
+    #[arg(long, value_name = "DURATION", value_parser = time_utils::parse_duration)]
+    accessed_within: Option<Duration>,
+
+    /// Only show files containing the given text, with the number of matching lines. Binary files are skipped
+    #[arg(long, value_name = "TEXT")]
+    contains: Option<String>,
+
+    /// Append the first non-blank line of each text file, e.g. a shebang or docstring, cut off after 60 characters
+    #[arg(long, conflicts_with = "stream")]
+    first_line: bool,
+
+    /// Append the first heading or line of the README of each directory, e.g. parser/ "Parses the spec files"
+    #[arg(long, conflicts_with = "stream")]
+    peek_readme: bool,
+
+    /// Print the total, used and free space of the file system of each directory before the tree
+    #[arg(long, conflicts_with_all = ["fzf", "select", "preview_filters"])]
+    df: bool,
+
+    /// Annotate sparse files with the share of their size that's allocated on disk, e.g. disk.img [sparse 2%]
+    #[arg(long)]
+    sparse: bool,
+
+    /// Flag files whose permissions differ from the overwhelming majority in their directory, e.g. one 777 file among 644s
+    #[arg(long, conflicts_with = "stream")]
+    perm_anomalies: bool,
+
+    /// Flag files that share their data with others, e.g. reflinks on Btrfs or XFS: [clone], or [clone 40%] if partly. Needs filefrag
+    #[arg(long, conflicts_with = "stream")]
+    clones: bool,
+
+    /// Flag siblings whose names differ only by case, e.g. README.md and Readme.md, and list them after the tree
+    #[arg(long, conflicts_with_all = ["stream", "fzf"])]
+    detect_case_conflicts: bool,
+
+    /// Flag names that cause trouble on some platform, e.g. trailing dots, CON or NUL, `:` or `?`, and list them after the tree
+    #[arg(long, conflicts_with_all = ["stream", "fzf"])]
+    lint_names: bool,
+
+    /// Exit with 3 if --lint-names or --detect-case-conflicts found problems, e.g. to fail a CI job
+    #[arg(long)]
+    fail_on_names: bool,
+
+    /// Flag the entries USER can't read, list or enter, judged by the permission bits, e.g. to debug access on a shared server. Needs root, Unix only
+    #[arg(long, value_name = "USER", conflicts_with = "stream")]
+    as_user: Option<String>,
+
+    /// Annotate entries with their owners from the CODEOWNERS file of the directory, where they change
+    #[arg(long, conflicts_with = "stream")]
+    codeowners: bool,
+
+    /// Run the given shell command for each file and show its output after the name, e.g. 'wc -l < {}'. {} is the path
+    #[arg(long, value_name = "COMMAND", conflicts_with = "stream")]
+    exec_label: Option<String>,
+
+    /// Kill a command of --exec-label that takes longer than the given duration, e.g. 30s
+    #[arg(long, value_name = "DURATION", value_parser = time_utils::parse_duration, default_value = "10s", requires = "exec_label")]
+    exec_timeout: Duration,
+
+    /// Run the given shell command for each file the filters keep instead of showing the tree, e.g. 'chmod 644 {}'. {} is the path
+    #[arg(
+        long,
+        value_name = "COMMAND",
+        conflicts_with_all = ["exec_label", "fzf", "stream", "bfs", "stats", "select", "preview_filters", "parse_tree", "df", "legend", "report_skipped"]
+    )]
+    exec: Option<String>,
+
+    /// Ask before running each command of --exec
+    #[arg(long, requires = "exec")]
+    exec_confirm: bool,
+
+    /// List the commands --exec would run without running them
+    #[arg(long, requires = "exec", conflicts_with = "exec_confirm")]
+    dry_run: bool,
+
+    /// Hide binary files, e.g. to list only the sources of a project
+    #[arg(long)]
+    text_only: bool,
+
+    /// Hide the paths matching the patterns in the given file, one per line in gitignore syntax
+    #[arg(long, value_name = "FILE")]
+    exclude_from: Option<PathBuf>,
+
+    /// Only show the paths matching the patterns in the given file (in gitignore syntax), with their parents and contents
+    #[arg(long, value_name = "FILE", conflicts_with = "stream")]
+    include_from: Option<PathBuf>,
+
+    /// Only show the files changed since the given git ref (like `git diff --name-only REF`), e.g. origin/main
+    #[arg(long, value_name = "REF", conflicts_with_all = ["stream", "parse_tree"])]
+    since_ref: Option<String>,
+
+    /// Read the tree from a previous `tree` or `ftree` output in the given file (`-` for stdin) instead of the file system
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["stream", "cache", "diff_cache", "contains"])]
+    parse_tree: Option<PathBuf>,
+
+    /// Print a summary instead of the tree
+    #[arg(long, value_enum, value_name = "KIND", conflicts_with_all = ["fzf", "stream", "bfs", "legend"])]
+    stats: Option<StatsKind>,
+
+    /// Print how much space deleting the files the filters keep would free, per directory, instead of the tree
+    #[arg(long, conflicts_with_all = ["stats", "fzf", "stream", "bfs", "legend", "number", "select", "budget_chars"])]
+    reclaim: bool,
+
+    /// After the trees of several directories, print the totals of each and of all, and the paths they have in common. The directories are read one after another
+    #[arg(long, conflicts_with_all = ["merge", "stream", "fzf", "fingerprint_only", "exec", "preview_filters"])]
+    combined: bool,
+
+    /// Print a hash of the layout below each tree, its paths and which are directories, to detect layout changes in CI
+    #[arg(long, conflicts_with_all = ["stream", "fzf", "exec", "preview_filters"])]
+    fingerprint: bool,
+
+    /// Print only the hashes of --fingerprint, one per tree with its directory like sha256sum, instead of the trees
+    #[arg(long, conflicts_with_all = ["fingerprint", "stream", "fzf", "stats", "reclaim", "legend", "report_skipped", "exec", "preview_filters"])]
+    fingerprint_only: bool,
+
+    /// Show names as they are, without escaping bidirectional and zero-width characters as <U+202E>
+    #[arg(long)]
+    raw_names: bool,
+
+    /// Tag well-known files with a badge, e.g. Dockerfile [docker] or Cargo.toml [rust]. More in `badges` of .ftree.toml
+    #[arg(long)]
+    badges: bool,
+
+    /// Append a legend explaining the markers and columns in the output
+    #[arg(long, conflicts_with = "fzf")]
+    legend: bool,
+
+    /// Append a section listing every skipped path with the reason, e.g. gitignore, depth limit or permission denied
+    #[arg(long, conflicts_with = "fzf")]
+    report_skipped: bool,
+
+    /// Print how many entries each active filter hides instead of the tree, e.g. to check a stack of filters
+    #[arg(long, conflicts_with_all = ["fzf", "stream", "bfs", "stats", "legend", "report_skipped"])]
+    preview_filters: bool,
+
+    /// Report whether the filters show the given path, or which filter hides it
+    #[arg(long, value_name = "PATH")]
+    explain: Option<PathBuf>,
+
+    /// Language of the legend, the sections after the trees and errors. Defaults to the locale of LC_ALL, LC_MESSAGES or LANG
+    #[arg(long, value_enum)]
+    lang: Option<i18n::Locale>,
+
+    /// Log to stderr why entries are excluded (-vv) and how long each phase takes (-v)
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// How to report directories and entries that couldn't be read, on stderr. With json, an array is always printed
+    #[arg(long, value_enum, value_name = "FORMAT", default_value = "text")]
+    errors: ErrorFormat,
+
+    /// Print a flat, NUL-separated list of `<tree row>\t<path>` records for piping into fzf
+    #[arg(long)]
+    fzf: bool,
+
+    /// Cache directory listings and only re-read directories whose entries changed since the last run. Sizes and times of files edited in place may be stale
+    #[arg(long)]
+    cache: bool,
+
+    /// Mark entries that are new, removed or resized since the last run with --cache or --diff-cache
+    #[arg(long)]
+    diff_cache: bool,
+
+    /// How to show the changes of --diff-cache. With json, an array of changes is printed instead of the tree, e.g. for CI checks
+    #[arg(long, value_enum, value_name = "FORMAT", default_value = "tree", requires = "diff_cache", conflicts_with_all = ["fzf", "bfs", "stats", "select", "merge", "parse_tree", "preview_filters", "legend", "report_skipped"])]
+    diff_format: DiffFormat,
+
+    /// Limit file system operations per second (default: 1000) and lower the I/O priority, to go easy on shared disks
+    #[arg(long, value_name = "OPS_PER_SEC", num_args = 0..=1, default_missing_value = "1000")]
+    throttle: Option<u32>,
+
+    /// Read the symbolic links to directories matching this pattern (gitignore syntax) like directories, e.g. 'current'. Can be repeated
+    #[arg(long, value_name = "PATTERN")]
+    expand_links: Vec<String>,
+
+    /// Write each row as soon as it's read instead of building the tree first. Keeps memory low on huge trees
+    #[arg(long, conflicts_with_all = ["fzf", "cache", "diff_cache", "contains", "expand_links"])]
+    stream: bool,
+
+    /// Show the full paths of --bfs, --fzf and --select relative to this directory, e.g. the repository root when listing a subdirectory
+    #[arg(long, value_name = "PATH")]
+    relative_to: Option<PathBuf>,
+
+    /// Remove this prefix from the full paths of --bfs and --fzf, e.g. /ci/workspace/. Can be repeated
+    #[arg(long, value_name = "PREFIX")]
+    strip_prefix: Vec<String>,
+
+    /// Replace the prefix OLD of the full paths of --bfs and --fzf by NEW, e.g. /ci/workspace=. Can be repeated
+    #[arg(long, value_name = "OLD=NEW", value_parser = tree::parse_prefix_map)]
+    map_prefix: Vec<(String, String)>,
+
+    /// List entries breadth-first (all entries of depth 1, then depth 2, ...) with their full paths
+    #[arg(long, conflicts_with_all = ["fzf", "stream"])]
+    bfs: bool,
+
+    /// Show the top level directory as an absolute path without symbolic links
+    #[arg(long)]
+    canonical: bool,
+
+    /// The directories to visualize (defaults to current directory if not specified), or single files. A leading `~` is expanded
     #[arg(value_name = "DIRECTORY", default_value = ".")]
-    directory: PathBuf,
+    directories: Vec<PathBuf>,
+
+    /// Show all directories in one tree below a common top level item instead of one tree each
+    #[arg(long, conflicts_with = "stream")]
+    merge: bool,
+
+    /// Name of the common top level item of --merge
+    #[arg(long, value_name = "LABEL", default_value = ".", requires = "merge")]
+    root_label: String,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Serve the tree as JSON (`/api/tree?path=...&depth=...`) and as a minimal HTML page
+    Serve {
+        /// The port to listen on
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+
+        /// The address to listen on. Use 0.0.0.0 to make the tree reachable from other machines
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+
+        /// Exclude git-related files and directories from the output
+        #[arg(long)]
+        gitignore: bool,
+
+        /// The directory to serve (defaults to current directory if not specified)
+        #[arg(value_name = "DIRECTORY", default_value = ".")]
+        directory: PathBuf,
+    },
+    /// Check the directory against a layout spec with required and forbidden paths. Exits with 1 if the spec is violated
+    Check {
+        /// The spec, a TOML file like `required = ["src/"]` and `forbidden = ["**/.env"]`
+        #[arg(value_name = "SPEC")]
+        spec: PathBuf,
+
+        /// Exclude git-related files and directories from the check
+        #[arg(long)]
+        gitignore: bool,
+
+        /// The directory to check (defaults to current directory if not specified)
+        #[arg(value_name = "DIRECTORY", default_value = ".")]
+        directory: PathBuf,
+    },
+    /// Print the shell integration defining `ftcd`, which picks a path from a searchable tree with fzf and cds into it
+    Init {
+        /// The shell to print the integration for
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Replace this executable with the binary of the latest GitHub release, after verifying its checksum
+    SelfUpdate {
+        /// Install the latest release even if it's older than this version
+        #[arg(long)]
+        allow_downgrade: bool,
+    },
 }
 
 fn main() {
-    let args = Args::parse();
+    i18n::set_locale(i18n::Locale::detect());
+    if cfg!(windows) {
+        update::remove_old_executable();
+    }
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    if args.iter().any(|arg| arg.to_str().is_some_and(|arg| arg.starts_with('@'))) {
+        // Aliases are taken from the current directory, because the roots aren't known before parsing
+        let config = ProjectConfig::find(Path::new(".")).filter(|_| !args.iter().any(|arg| arg == "--no-config")).map(|file| {
+            ProjectConfig::load(&file).unwrap_or_else(|err| {
+                eprintln!("{}", i18n::tr!("Error reading {}: {}", file.display(), err));
+                std::process::exit(1);
+            })
+        });
+        args = project::expand_aliases(args, config.as_ref()).unwrap_or_else(|err| {
+            eprintln!("{}", i18n::tr!("Error: {}", err));
+            std::process::exit(2);
+        });
+    }
+    let args = Args::parse_from(args);
+    logging::set_verbosity(args.verbose);
+    if let Some(lang) = args.lang {
+        i18n::set_locale(lang);
+    }
+
+    if let Some(Command::Serve { port, host, gitignore, directory }) = args.command {
+        let directory = fs_utils::resolve_root(&directory, false).unwrap_or(directory);
+        let options = TraverseOptions {
+            gitignore,
+            env_ignore: env_ignore(),
+            roots: vec![directory.to_string_lossy().to_string()],
+            ..Default::default()
+        };
+        if let Err(err) = serve::serve(&directory, &format!("{}:{}", host, port), &options) {
+            eprintln!("{}", i18n::tr!("Error serving {}: {}", directory.display(), err));
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(Command::Init { shell }) = args.command {
+        print!("{}", shell::init_script(shell));
+        return;
+    }
+
+    if let Some(Command::SelfUpdate { allow_downgrade }) = &args.command {
+        match update::self_update(*allow_downgrade) {
+            Ok(message) => println!("{}", message),
+            Err(err) => {
+                eprintln!("{}", i18n::tr!("Error updating ftree: {}", err));
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(Command::Check { spec, gitignore, directory }) = &args.command {
+        let options = TraverseOptions { gitignore: *gitignore, ..Default::default() };
+        let directory = fs_utils::resolve_root(directory, false).unwrap_or_else(|_| directory.clone());
+        std::process::exit(check_layout(spec, &directory, &options));
+    }
 
-    let path = args.directory;
-    let root = TreeItem::new_top_level(path.to_str().unwrap().to_string(), true);
+    let resolve = |path: &Path| {
+        fs_utils::resolve_root(path, args.canonical).unwrap_or_else(|err| {
+            eprintln!("{}", i18n::tr!("Error resolving {}: {}", path.display(), err));
+            std::process::exit(1);
+        })
+    };
+    // Expand globs before canonicalizing, which requires an existing path
+    let roots: Vec<PathBuf> = args
+        .directories
+        .iter()
+        .flat_map(|directory| fs_utils::expand_roots(&fs_utils::resolve_root(directory, false).unwrap_or_else(|_| directory.clone())))
+        .map(|root| resolve(&root))
+        .collect();
+    let path = &roots[0];
+
+    let load_rules = |file: &PathBuf| {
+        IgnoreRules::load(file).unwrap_or_else(|err| {
+            eprintln!("{}", i18n::tr!("Error reading {}: {}", file.display(), err));
+            std::process::exit(1);
+        })
+    };
 
     // If --git is passed, use gitignore
-    fs_utils::traverse_fs(path.to_str().unwrap(), &root, args.gitignore);
+    let mut options = TraverseOptions {
+        gitignore: args.gitignore,
+        throttle: args.throttle.map(Throttle::new),
+        created_within: args.created_within,
+        created_before: args.created_before,
+        accessed_within: args.accessed_within,
+        text_only: args.text_only,
+        sort: args.sort.into_iter().chain(args.then.iter().copied()).collect(),
+        count_hidden: args.count_hidden,
+        count_below: args.count_below,
+        skipped: (args.report_skipped || args.preview_filters).then(SkippedLog::default),
+        exclude_from: args.exclude_from.as_ref().map(load_rules),
+        env_ignore: env_ignore(),
+        include_from: args.include_from.as_ref().map(load_rules),
+        expand_links: flag_rules("--expand-links", &args.expand_links),
+        roots: roots.iter().map(|root| root.to_string_lossy().to_string()).collect(),
+        ..Default::default()
+    };
+    let config = ProjectConfig::find(path).filter(|_| !args.no_config).map(|file| {
+        let config = ProjectConfig::load(&file).unwrap_or_else(|err| {
+            eprintln!("{}", i18n::tr!("Error reading {}: {}", file.display(), err));
+            std::process::exit(1);
+        });
+        logging::info!("{}: {:?}", file.display(), config);
+        config
+    });
+    if let Some(config) = &config {
+        config.apply(&mut options);
+    }
+    if args.smart {
+        let kind = project::apply_smart_defaults(path, &mut options);
+        logging::info!("--smart: detected project type {:?}", kind);
+    }
+    logging::info!("options: {:?}", options);
+    let render_options = RenderOptions {
+        time: args.date.then(|| args.time.unwrap_or(TimeKind::Modified)),
+        time_style: args.time_style,
+        dir_times: args.dir_times,
+        size: if args.human {
+            Some(SizeFormat::Human)
+        } else {
+            args.size.then_some(SizeFormat::Bytes)
+        },
+        column_width: args.size_width,
+        dir_suffix: args.dir_suffix.clone(),
+        symlink_indicator: args.symlink_indicator.clone().or_else(|| args.classify.then(|| "@".to_string())),
+        exec_indicator: args.exec_indicator.clone().or_else(|| args.classify.then(|| "*".to_string())),
+        symdirs: args.symdirs,
+        path_prefixes: args
+            .strip_prefix
+            .iter()
+            .map(|prefix| (prefix.clone(), String::new()))
+            .chain(args.map_prefix.iter().cloned())
+            .collect(),
+        raw_names: args.raw_names,
+        badges: args.badges.then(|| {
+            Badges::new(config.as_ref().map_or(&[][..], |config| &config.badges)).unwrap_or_else(|err| {
+                eprintln!("{}", i18n::tr!("Error in the badges of {}: {}", project::CONFIG_FILE, err));
+                std::process::exit(1);
+            })
+        }),
+        relative_to: args.relative_to.as_ref().map(|base| fs_utils::absolute_path(&resolve(base))),
+        depth_colors: (args.depth_colors && tree::colors_supported()).then(|| args.theme.unwrap_or_else(Theme::detect)),
+        type_column: args.type_column,
+        sparse: args.sparse,
+    };
+    if options.throttle.is_some() {
+        throttle::lower_io_priority();
+    }
+
+    if let Some(target) = &args.explain {
+        match fs_utils::explain(path.to_str().unwrap(), target, &options) {
+            Ok(verdict) => {
+                let line = format!("{}: {}", target.display(), verdict);
+                println!("{}", if args.raw_names { line } else { tree::escape_invisible(&line) });
+            }
+            Err(err) => {
+                eprintln!("{}", i18n::tr!("Error explaining {}: {}", target.display(), err));
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    let result = write_output(&args, &roots, &options, &render_options, &mut out).and_then(|_| out.flush());
+    exit_on_write_error(result);
+
+    if report_errors(&options, args.errors) {
+        std::process::exit(1);
+    }
+    if args.fail_on_names && !options.name_findings.findings().is_empty() {
+        std::process::exit(3);
+    }
+}
+
+/// Writes the trees of all `roots`, separated by empty lines or merged with --merge, followed
+/// by the sections.
+fn write_output(args: &Args, roots: &[PathBuf], options: &TraverseOptions, render_options: &RenderOptions, out: &mut impl Write) -> io::Result<()> {
+    // A tree read with --parse-tree doesn't depend on the roots
+    let roots = if args.parse_tree.is_some() { &roots[..1] } else { roots };
+    if args.preview_filters {
+        for root in roots {
+            build_tree(args, root, options);
+        }
+        return writeln!(out, "{}", preview_str(args, options));
+    }
+    if args.diff_format == DiffFormat::Json {
+        let changes: Vec<Change> = roots.iter().flat_map(|root| list_changes(root, options)).collect();
+        return writeln!(out, "{}", cache::to_changes_json(&changes));
+    }
+    if let Some(command) = &args.exec {
+        return run_exec(args, roots, options, command, out);
+    }
+    if args.df {
+        write_disk_space(roots, out)?;
+    }
+    if args.merge {
+        // Merge after filtering, as the filters read the files by their path
+        let merged = TreeItem::new_top_level(args.root_label.clone(), true);
+        for root in roots {
+            tree::graft(&merged, build_tree(args, root, options));
+        }
+        if args.fingerprint_only {
+            return writeln!(out, "{}  {}", fingerprint::fingerprint(&merged), args.root_label);
+        }
+        write_fingerprinted_tree(args, &merged, render_options, out)?;
+    } else if args.fingerprint_only {
+        for root in roots {
+            writeln!(out, "{}  {}", fingerprint::fingerprint(&build_tree(args, root, options)), root.display())?;
+        }
+        return Ok(());
+    } else {
+        // The trees of --combined, which are compared after all of them are written
+        let mut trees = Vec::new();
+        for (i, root) in roots.iter().enumerate() {
+            if i > 0 && !args.fzf {
+                writeln!(out)?;
+            }
+            if args.stream {
+                tree::stream::stream_tree(root.to_str().unwrap(), options, render_options, out)?;
+            } else {
+                let tree = build_tree(args, root, options);
+                write_fingerprinted_tree(args, &tree, render_options, out)?;
+                if args.combined {
+                    trees.push(tree);
+                }
+            }
+        }
+        if args.combined {
+            writeln!(out, "\n{}", stats::combined_report(&trees, render_options.raw_names))?;
+        }
+    }
+    write_sections(args, options, render_options, out)
+}
+
+/// Writes the tree of `root`, followed by its hash with --fingerprint. The hash is taken before
+/// rendering, so options like --select don't change it.
+fn write_fingerprinted_tree(args: &Args, root: &Rc<TreeItemRefCell>, render_options: &RenderOptions, out: &mut impl Write) -> io::Result<()> {
+    let hash = args.fingerprint.then(|| fingerprint::fingerprint(root));
+    write_tree(args, root, render_options, out)?;
+    match hash {
+        Some(hash) => writeln!(out, "{}", i18n::tr!("Fingerprint: {}", hash)),
+        None => Ok(()),
+    }
+}
 
-    println!("{}", root.borrow().to_row_str(false));
+/// Runs the command of --exec for the files of all `roots` that the filters keep, and writes a
+/// summary. Exits with 1 if a command failed.
+fn run_exec(args: &Args, roots: &[PathBuf], options: &TraverseOptions, command: &str, out: &mut impl Write) -> io::Result<()> {
+    let mut confirm = |shown: &str| {
+        if !args.exec_confirm {
+            return true;
+        }
+        eprint!("{} [y/N] ", shown);
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer).is_ok() && matches!(answer.trim(), "y" | "Y" | "yes")
+    };
+    let mut summary = exec::ExecSummary::default();
+    for root in roots {
+        let root_summary = exec::run_on_files(&build_tree(args, root, options), command, args.dry_run, &mut confirm, out)?;
+        summary.ran += root_summary.ran;
+        summary.failed += root_summary.failed;
+        summary.skipped += root_summary.skipped;
+        summary.listed += root_summary.listed;
+    }
+    writeln!(out, "{}", summary)?;
+    if summary.failed > 0 {
+        out.flush()?;
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Writes the space of the file systems of `roots` for --df, once per file system, followed by
+/// an empty line. File systems whose space can't be read are reported on stderr.
+fn write_disk_space(roots: &[PathBuf], out: &mut impl Write) -> io::Result<()> {
+    let mut mounts = Vec::new();
+    for root in roots {
+        match disk::disk_space(root) {
+            Ok(space) if !mounts.contains(&space.mount) => {
+                writeln!(out, "{}", space)?;
+                mounts.push(space.mount);
+            }
+            Ok(_) => {}
+            Err(err) => eprintln!("{}", i18n::tr!("Error reading the disk space of {}: {}", root.display(), err)),
+        }
+    }
+    if mounts.is_empty() {
+        return Ok(());
+    }
+    writeln!(out)
+}
+
+/// Reads and filters the tree of `path` as selected by `args`.
+fn build_tree(args: &Args, path: &Path, options: &TraverseOptions) -> Rc<TreeItemRefCell> {
+    let started = Instant::now();
+    let root = match &args.parse_tree {
+        Some(dump) => read_tree_dump(dump),
+        None => scan(args, path, options),
+    };
+    logging::info!("walk: {:?}", started.elapsed());
+
+    let started = Instant::now();
+    // Counts the entries a filter removes, for --preview-filters
+    let counted = |filter: &'static str, apply: &dyn Fn()| match &options.skipped {
+        Some(log) => {
+            let before = tree::count_entries(&root);
+            apply();
+            log.record_removed(filter, before - tree::count_entries(&root));
+        }
+        None => apply(),
+    };
+    if let Some(rules) = &options.include_from {
+        counted("--include-from", &|| {
+            tree::retain_listed(&root, &|rel_path, is_dir| rules.matches(rel_path, is_dir));
+        });
+    }
+    if let Some(git_ref) = &args.since_ref {
+        let changed = git::changed_since(path, git_ref).unwrap_or_else(|err| {
+            eprintln!("{}", i18n::tr!("Error listing the changes since {}: {}", git_ref, err));
+            std::process::exit(1);
+        });
+        counted("--since-ref", &|| {
+            tree::retain_listed(&root, &|rel_path, _| changed.contains(rel_path));
+        });
+    }
+    if let Some(pattern) = &args.contains {
+        counted("--contains", &|| content::filter_by_content(&root, pattern));
+    }
+    if args.first_line {
+        content::annotate_first_lines(&root);
+    }
+    if args.peek_readme {
+        content::annotate_readmes(&root);
+    }
+    if args.perm_anomalies {
+        perms::annotate_perm_anomalies(&root);
+    }
+    if args.clones {
+        clones::annotate_clones(&root, &options.diagnostics);
+    }
+    if args.detect_case_conflicts {
+        names::annotate_case_conflicts(&root, &options.name_findings);
+    }
+    if args.lint_names {
+        names::annotate_name_problems(&root, &options.name_findings);
+    }
+    if args.codeowners {
+        codeowners::annotate_owners(&root, &load_codeowners(path));
+    }
+    if let Some(name) = &args.as_user {
+        if let Err(err) = access::check_superuser() {
+            eprintln!("{}", i18n::tr!("Error: {}", err));
+            std::process::exit(1);
+        }
+        let user = User::lookup(name).unwrap_or_else(|err| {
+            eprintln!("{}", i18n::tr!("Error looking up user {}: {}", name, err));
+            std::process::exit(1);
+        });
+        access::annotate_access(&root, &user);
+    }
+    if let Some(command) = &args.exec_label {
+        exec::annotate_labels(&root, command, args.exec_timeout, &options.diagnostics);
+    }
+    logging::info!("filter and annotate: {:?}", started.elapsed());
+    root
+}
+
+/// Renders the tree below `root` as selected by `args`.
+fn write_tree(args: &Args, root: &Rc<TreeItemRefCell>, render_options: &RenderOptions, out: &mut impl Write) -> io::Result<()> {
+    let started = Instant::now();
+    if !args.select.is_empty() {
+        return match tree::select_rows(root, &args.select, render_options) {
+            Ok(paths) => paths.iter().try_for_each(|path| writeln!(out, "{}", path)),
+            Err(number) => {
+                eprintln!("{}", i18n::tr!("Error: there's no row {} in the tree of {}", number, root.borrow().path().display()));
+                std::process::exit(1);
+            }
+        };
+    }
+    tree::decorate_tree(root, render_options);
+    if args.number {
+        tree::number_rows(root);
+    }
+    tree::align_leading_columns(root, render_options.column_width);
+    if let Some(budget) = args.budget_chars {
+        let (keep, drop_first) = (flag_rules("--keep", &args.keep), flag_rules("--drop-first", &args.drop_first));
+        tree::fit_budget(root, budget, render_options.depth_colors, &|rel_path, is_dir| {
+            if keep.as_ref().is_some_and(|rules| rules.matches(rel_path, is_dir)) {
+                Priority::Keep
+            } else if drop_first.as_ref().is_some_and(|rules| rules.matches(rel_path, is_dir)) {
+                Priority::DropFirst
+            } else {
+                Priority::Normal
+            }
+        });
+    }
+
+    match args.stats {
+        Some(kind) => writeln!(out, "{}", stats::to_stats_str(root, kind, SystemTime::now(), render_options.symdirs, render_options.raw_names))?,
+        None if args.reclaim => writeln!(out, "{}", stats::reclaim_report(root, render_options.raw_names))?,
+        None if args.fzf => write!(out, "{}", tree::fzf::to_fzf_str(&root.borrow(), render_options))?,
+        None if args.bfs => writeln!(out, "{}", tree::bfs::to_bfs_str(root, render_options))?,
+        None => root.borrow().write_rows(false, render_options.depth_colors, out)?,
+    }
+    logging::info!("render: {:?}", started.elapsed());
+    Ok(())
+}
+
+/// Reads the CODEOWNERS file of the repository at `path`. Exits if there's none or it's invalid.
+fn load_codeowners(path: &Path) -> CodeOwners {
+    let Some(file) = CodeOwners::find(path) else {
+        eprintln!("{}", i18n::tr!("Error: no CODEOWNERS file in {} (looked in .github/, the top level, docs/ and .gitlab/)", path.display()));
+        std::process::exit(1);
+    };
+    CodeOwners::load(&file).unwrap_or_else(|err| {
+        eprintln!("{}", i18n::tr!("Error reading {}: {}", file.display(), err));
+        std::process::exit(1);
+    })
+}
+
+/// Returns the rules of the `patterns` given with `flag`, or `None` if there are none. Exits if they're invalid.
+fn flag_rules(flag: &str, patterns: &[String]) -> Option<IgnoreRules> {
+    if patterns.is_empty() {
+        return None;
+    }
+    let rules = IgnoreRules::parse(Path::new(flag), &patterns.join("\n")).unwrap_or_else(|err| {
+        eprintln!("{}", i18n::tr!("Invalid {}: {}", flag, err));
+        std::process::exit(1);
+    });
+    Some(rules)
+}
+
+/// Returns the personal exclusions from `FTREE_IGNORE`, if set. Exits if they're invalid.
+fn env_ignore() -> Option<IgnoreRules> {
+    IgnoreRules::from_env().map(|rules| {
+        rules.unwrap_or_else(|err| {
+            eprintln!("{}", i18n::tr!("Invalid {}: {}", ignore::IGNORE_ENV_VAR, err));
+            std::process::exit(1);
+        })
+    })
+}
+
+/// Writes the sections after the tree: the legend and the skipped entries, if requested.
+fn write_sections(args: &Args, options: &TraverseOptions, render_options: &RenderOptions, out: &mut impl Write) -> io::Result<()> {
+    if args.legend {
+        writeln!(out, "\n{}", legend_str(args, options, render_options))?;
+    }
+    if args.report_skipped {
+        writeln!(out, "\n{}", skipped_str(options, render_options.raw_names))?;
+    }
+    if args.detect_case_conflicts || args.lint_names {
+        writeln!(out, "\n{}", names::to_findings_str(&options.name_findings.findings(), render_options.raw_names))?;
+    }
+    Ok(())
+}
+
+/// Exits if writing the output failed. A reader that stops early, e.g. `ftree | head`, closes
+/// the pipe, which isn't an error: there's just nothing left to do.
+fn exit_on_write_error(result: io::Result<()>) {
+    match result {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => std::process::exit(0),
+        Err(err) => {
+            eprintln!("{}", i18n::tr!("Error writing output: {}", err));
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Prints the problems that occurred while reading the tree to stderr.
+/// Returns whether there were any.
+fn report_errors(options: &TraverseOptions, format: ErrorFormat) -> bool {
+    let problems = options.diagnostics.take();
+    if !problems.is_empty() || format == ErrorFormat::Json {
+        eprintln!("{}", diagnostics::format_diagnostics(&problems, format));
+    }
+    !problems.is_empty()
+}
+
+/// Checks `directory` against the spec in `spec_file` and prints the violations.
+/// Returns the exit code: 0 if the layout matches, 1 if it doesn't, 2 if the spec is invalid
+/// or the directory couldn't be read completely.
+fn check_layout(spec_file: &Path, directory: &Path, options: &TraverseOptions) -> i32 {
+    let spec = fs::read_to_string(spec_file)
+        .map_err(|err| err.to_string())
+        .and_then(|text| check::parse_spec(&text));
+    let spec = match spec {
+        Ok(spec) => spec,
+        Err(err) => {
+            eprintln!("{}", i18n::tr!("Error reading {}: {}", spec_file.display(), err));
+            return 2;
+        }
+    };
+
+    let root = TreeItem::new_top_level(directory.to_str().unwrap().to_string(), true);
+    fs_utils::traverse_fs(directory.to_str().unwrap(), &root, options);
+    if report_errors(options, ErrorFormat::Text) {
+        return 2;
+    }
+    let violations = check::check(&root, &spec);
+    for violation in &violations {
+        println!("{}", violation);
+    }
+    if violations.is_empty() {
+        0
+    } else {
+        match violations.len() {
+            1 => println!("{}", i18n::tr!("{} violation", 1)),
+            count => println!("{}", i18n::tr!("{} violations", count)),
+        }
+        1
+    }
+}
+
+/// Returns the section of `--report-skipped`: the entries the filters skipped and the paths that
+/// couldn't be read, each with the reason. Invisible characters are escaped unless `raw_names`.
+fn skipped_str(options: &TraverseOptions, raw_names: bool) -> String {
+    let mut out = i18n::translate("Skipped:").to_string();
+    let skipped = options.skipped.as_ref().map(SkippedLog::take).unwrap_or_default();
+    for (path, reason) in skipped {
+        out.push_str(&format!("\n  {} ({})", path, reason));
+    }
+    for problem in options.diagnostics.problems() {
+        out.push_str(&format!("\n  {} ({})", problem.path, problem.message));
+    }
+    if raw_names {
+        out
+    } else {
+        tree::escape_invisible(&out)
+    }
+}
+
+/// Returns the number of entries each active filter hid, in the order the filters are applied.
+fn preview_str(args: &Args, options: &TraverseOptions) -> String {
+    let counts = options.skipped.as_ref().map(SkippedLog::take_filter_counts).unwrap_or_default();
+    let mut filters = fs_utils::active_filters(options);
+    filters.extend(args.since_ref.as_ref().map(|_| "--since-ref"));
+    filters.extend(args.contains.as_ref().map(|_| "--contains"));
+    if filters.is_empty() {
+        return i18n::translate("No active filters").to_string();
+    }
+
+    let width = filters.iter().map(|filter| filter.len()).max().unwrap_or(0);
+    let mut out = i18n::translate("Hidden entries per filter:").to_string();
+    for filter in filters {
+        out.push_str(&format!("\n  {:<w$}  {}", filter, counts.get(filter).copied().unwrap_or(0), w = width));
+    }
+    out
+}
+
+/// Returns the legend of the markers that `args` enable.
+fn legend_str(args: &Args, options: &TraverseOptions, render_options: &RenderOptions) -> String {
+    let mut markers = Marker::active(render_options);
+    if options.count_hidden {
+        markers.push(Marker::Hidden);
+    }
+    if !options.collapse.is_empty() {
+        markers.push(Marker::Collapsed);
+    }
+    if args.diff_cache {
+        markers.push(Marker::Changes);
+    }
+    if args.contains.is_some() {
+        markers.push(Marker::Matches);
+    }
+    if args.perm_anomalies {
+        markers.push(Marker::PermAnomalies);
+    }
+    if args.clones {
+        markers.push(Marker::Clones);
+    }
+    if args.detect_case_conflicts {
+        markers.push(Marker::CaseConflicts);
+    }
+    if args.lint_names {
+        markers.push(Marker::NameProblems);
+    }
+    if args.badges {
+        markers.push(Marker::Badges);
+    }
+    if let Some(user) = &args.as_user {
+        markers.push(Marker::Access(user.clone()));
+    }
+    if args.codeowners {
+        markers.push(Marker::Owners);
+    }
+    tree::legend::to_legend_str(&markers)
+}
+
+/// Builds the tree of `path`, using the cache if requested.
+fn scan(args: &Args, path: &Path, options: &TraverseOptions) -> Rc<TreeItemRefCell> {
+    let root = fs_utils::top_level_item(path.to_str().unwrap());
+    if !root.borrow().is_dir {
+        return root;
+    }
+    let cache_file = if args.cache || args.diff_cache { cache::cache_file(path) } else { None };
+    match cache_file {
+        Some(cache_file) if args.diff_cache => {
+            let (previous, cache) = rescan(path, &root, &cache_file, options);
+            cache::annotate_changes(&root, &previous, &cache, options);
+        }
+        Some(cache_file) => {
+            let mut cache = Cache::load(path.to_str().unwrap(), &cache_file);
+            fs_utils::traverse_fs_cached(path.to_str().unwrap(), &root, options, &mut cache);
+            if let Err(err) = cache.save(&cache_file) {
+                eprintln!("{}", i18n::tr!("Unable to write cache {}: {}", cache_file.display(), err));
+            }
+        }
+        None => fs_utils::traverse_fs(path.to_str().unwrap(), &root, options),
+    }
+    root
+}
+
+/// Reads the tree of `path` into `root` for --diff-cache and updates `cache_file`.
+/// Returns the caches of the last run and of this one.
+fn rescan(path: &Path, root: &Rc<TreeItemRefCell>, cache_file: &Path, options: &TraverseOptions) -> (Cache, Cache) {
+    // Read everything, as cached listings may contain outdated sizes
+    let previous = Cache::load(path.to_str().unwrap(), cache_file);
+    let mut cache = Cache::new(path.to_str().unwrap());
+    fs_utils::traverse_fs_cached(path.to_str().unwrap(), root, options, &mut cache);
+    if let Err(err) = cache.save(cache_file) {
+        eprintln!("{}", i18n::tr!("Unable to write cache {}: {}", cache_file.display(), err));
+    }
+    (previous, cache)
+}
+
+/// Returns the changes below `path` since the last run, for --diff-format json.
+fn list_changes(path: &Path, options: &TraverseOptions) -> Vec<Change> {
+    let root = fs_utils::top_level_item(path.to_str().unwrap());
+    let cache_file = cache::cache_file(path).filter(|_| root.borrow().is_dir);
+    match cache_file {
+        Some(cache_file) => {
+            let (previous, cache) = rescan(path, &root, &cache_file, options);
+            cache::find_changes(&root, &previous, &cache, options)
+        }
+        None => Vec::new(),
+    }
+}
 
+/// Parses the tree from a previous output stored in `dump`, or read from stdin if `dump` is `-`.
+fn read_tree_dump(dump: &Path) -> Rc<TreeItemRefCell> {
+    let text = if dump == Path::new("-") {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(dump)
+    };
+    let text = text.unwrap_or_else(|err| {
+        eprintln!("{}", i18n::tr!("Error reading {}: {}", dump.display(), err));
+        std::process::exit(1);
+    });
+    tree::parse::parse_tree(&text).unwrap_or_else(|| {
+        eprintln!("{}", i18n::tr!("{} doesn't contain a tree", dump.display()));
+        std::process::exit(1);
+    })
 }
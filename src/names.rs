@@ -0,0 +1,206 @@
+use crate::i18n;
+use crate::tree::{escape_invisible, TreeItemRefCell};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Names Windows reserves for devices, also with an extension, e.g. `nul.txt`.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2", "LPT3",
+    "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Characters Windows doesn't allow in names, besides control characters.
+const INVALID_CHARS: [char; 8] = ['<', '>', ':', '"', '\\', '|', '?', '*'];
+
+/// Maximum length of a name in bytes on most file systems, e.g. ext4 and NTFS (in UTF-16 units).
+const MAX_NAME_BYTES: usize = 255;
+
+/// A problem with the name of an entry, e.g. a case conflict with a sibling.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct NameFinding {
+    pub(crate) path: String,
+    pub(crate) message: String,
+}
+
+/// Collects the problems with names found in the trees, for the section after them.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct NameFindings {
+    findings: RefCell<Vec<NameFinding>>,
+}
+
+impl NameFindings {
+    fn record(&self, path: String, message: String) {
+        self.findings.borrow_mut().push(NameFinding { path, message });
+    }
+
+    /// Returns the findings recorded so far without clearing them.
+    pub(crate) fn findings(&self) -> Vec<NameFinding> {
+        self.findings.borrow().clone()
+    }
+}
+
+///
+/// Annotates the entries whose names differ from a sibling's only by case, e.g.
+/// `README.md [case conflict with Readme.md]`, and records them in `findings`. Only one of them
+/// survives a checkout on a case-insensitive file system, like the defaults of macOS and Windows.
+///
+/// Names are compared by their lowercase form. Only the entries in the tree are compared, so
+/// siblings hidden by filters aren't found.
+///
+pub(crate) fn annotate_case_conflicts(item: &Rc<TreeItemRefCell>, findings: &NameFindings) {
+    let mut groups: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, child) in item.borrow().children.iter().enumerate() {
+        groups.entry(child.borrow().text.to_lowercase()).or_default().push(i);
+    }
+
+    let item_ref = item.borrow();
+    let mut conflicts: Vec<&Vec<usize>> = groups.values().filter(|group| group.len() > 1).collect();
+    conflicts.sort();
+    for group in conflicts {
+        for &i in group {
+            let others: Vec<String> = group.iter().filter(|&&j| j != i).map(|&j| item_ref.children[j].borrow().text.clone()).collect();
+            let message = format!("case conflict with {}", others.join(", "));
+            let mut child = item_ref.children[i].borrow_mut();
+            findings.record(child.path().display().to_string(), message.clone());
+            child.annotations.push(format!("[{}]", message));
+        }
+    }
+
+    for child in &item_ref.children {
+        if child.borrow().is_dir {
+            annotate_case_conflicts(child, findings);
+        }
+    }
+}
+
+///
+/// Annotates the entries below `item` whose names cause trouble on some platform, e.g.
+/// `aux.c [name: reserved on Windows]`, and records them in `findings`. Flags trailing spaces
+/// and dots, which Windows drops, the device names Windows reserves (`CON`, `NUL`, `COM1`, ...),
+/// characters Windows doesn't allow (`<>:"\|?*` and control characters), and names longer than
+/// 255 bytes.
+///
+pub(crate) fn annotate_name_problems(item: &Rc<TreeItemRefCell>, findings: &NameFindings) {
+    for child in &item.borrow().children {
+        let problems = name_problems(&child.borrow().text);
+        if !problems.is_empty() {
+            let message = problems.join(", ");
+            findings.record(child.borrow().path().display().to_string(), message.clone());
+            child.borrow_mut().annotations.push(format!("[name: {}]", message));
+        }
+        if child.borrow().is_dir {
+            annotate_name_problems(child, findings);
+        }
+    }
+}
+
+/// Returns what's wrong with `name` on some platform, or nothing if it's portable.
+fn name_problems(name: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+    if name.ends_with(' ') {
+        problems.push("trailing space".to_string());
+    }
+    if name.ends_with('.') && name != "." && name != ".." {
+        problems.push("trailing dot".to_string());
+    }
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    if RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem)) {
+        problems.push("reserved on Windows".to_string());
+    }
+    let mut invalid: Vec<String> = Vec::new();
+    for c in name.chars().filter(|c| INVALID_CHARS.contains(c) || c.is_control()) {
+        let shown = if c.is_control() { format!("{:?}", c) } else { format!("'{}'", c) };
+        if !invalid.contains(&shown) {
+            invalid.push(shown);
+        }
+    }
+    if !invalid.is_empty() {
+        problems.push(format!("{} invalid on Windows", invalid.join(" ")));
+    }
+    if name.len() > MAX_NAME_BYTES {
+        problems.push(format!("{} bytes long", name.len()));
+    }
+    problems
+}
+
+/// Renders `findings` as a section, one per line with the path, e.g.
+/// `./docs/README.md (case conflict with Readme.md)`. Invisible characters in the paths are
+/// escaped like in the tree, unless `raw_names`.
+pub(crate) fn to_findings_str(findings: &[NameFinding], raw_names: bool) -> String {
+    let mut out = i18n::translate("Name problems:").to_string();
+    if findings.is_empty() {
+        out.push_str(&format!("\n  {}", i18n::translate("none")));
+    }
+    for finding in findings {
+        out.push_str(&format!("\n  {} ({})", finding.path, finding.message));
+    }
+    if raw_names {
+        out
+    } else {
+        escape_invisible(&out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::TreeItem;
+
+    #[test]
+    fn annotate_case_conflicts_flags_siblings() {
+        // Prepare
+        let root = TreeItem::new_top_level(".".to_string(), true);
+        let docs = TreeItem::new(&root, "docs".to_string(), true);
+        let upper = TreeItem::new(&docs, "README.md".to_string(), false);
+        let title = TreeItem::new(&docs, "Readme.md".to_string(), false);
+        let lower = TreeItem::new(&docs, "readme.md".to_string(), false);
+        let other = TreeItem::new(&root, "README.md".to_string(), false);
+        let findings = NameFindings::default();
+
+        // Call
+        annotate_case_conflicts(&root, &findings);
+
+        // Verify
+        assert_eq!(upper.borrow().annotations, vec!["[case conflict with Readme.md, readme.md]"]);
+        assert_eq!(title.borrow().annotations, vec!["[case conflict with README.md, readme.md]"]);
+        assert_eq!(lower.borrow().annotations, vec!["[case conflict with README.md, Readme.md]"]);
+        assert!(other.borrow().annotations.is_empty());
+        let expected = "Name problems:\n  ./docs/README.md (case conflict with Readme.md, readme.md)\n  \
+                        ./docs/Readme.md (case conflict with README.md, readme.md)\n  \
+                        ./docs/readme.md (case conflict with README.md, Readme.md)";
+        assert_eq!(to_findings_str(&findings.findings(), false), expected);
+        assert_eq!(to_findings_str(&[], false), "Name problems:\n  none");
+    }
+
+    #[test]
+    fn name_problems_across_platforms() {
+        assert!(name_problems("main.rs").is_empty());
+        assert!(name_problems("..").is_empty());
+        assert!(name_problems("console.log").is_empty());
+        assert_eq!(name_problems("notes "), vec!["trailing space"]);
+        assert_eq!(name_problems("draft."), vec!["trailing dot"]);
+        assert_eq!(name_problems("nul.txt"), vec!["reserved on Windows"]);
+        assert_eq!(name_problems("Com1"), vec!["reserved on Windows"]);
+        assert_eq!(name_problems("a:b?c:d"), vec!["':' '?' invalid on Windows"]);
+        assert_eq!(name_problems("tab\there"), vec!["'\\t' invalid on Windows"]);
+        assert_eq!(name_problems(&"x".repeat(256)), vec!["256 bytes long"]);
+    }
+
+    #[test]
+    fn annotate_name_problems_records_findings() {
+        let root = TreeItem::new_top_level("project".to_string(), true);
+        let src = TreeItem::new(&root, "src".to_string(), true);
+        let aux = TreeItem::new(&src, "aux.c".to_string(), false);
+        let findings = NameFindings::default();
+
+        annotate_name_problems(&root, &findings);
+
+        assert_eq!(aux.borrow().to_string(), "aux.c [name: reserved on Windows]");
+        assert_eq!(findings.findings(), vec![NameFinding { path: "project/src/aux.c".to_string(), message: "reserved on Windows".to_string() }]);
+
+        let hidden = [NameFinding { path: "./pay\u{202E}fdp.sh".to_string(), message: "trailing dot".to_string() }];
+        assert_eq!(to_findings_str(&hidden, false), "Name problems:\n  ./pay<U+202E>fdp.sh (trailing dot)");
+        assert_eq!(to_findings_str(&hidden, true), "Name problems:\n  ./pay\u{202E}fdp.sh (trailing dot)");
+    }
+}
@@ -0,0 +1,121 @@
+use crate::tree::TreeItemRefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Minimum number of files in a directory before deviating permissions are flagged.
+const MIN_FILES: usize = 4;
+
+/// Share of the files in a directory that must have the same permissions for it to be the norm.
+const MAJORITY_SHARE: f64 = 0.75;
+
+///
+/// Annotates files whose permissions differ from the overwhelming majority of the files in
+/// their directory, e.g. `deploy.sh [perm 777, mostly 644]`. A directory needs at least
+/// `MIN_FILES` files, and `MAJORITY_SHARE` of them must share the same permissions.
+///
+/// Only the permission bits are compared (including setuid, setgid and sticky), not the owner.
+/// Symbolic links are skipped, as their own mode is meaningless.
+/// Files that can't be read, e.g. in a tree from `--parse-tree`, are ignored. Does nothing on
+/// platforms without Unix permissions.
+///
+pub(crate) fn annotate_perm_anomalies(item: &Rc<TreeItemRefCell>) {
+    let modes: Vec<(usize, u32)> = item
+        .borrow()
+        .children
+        .iter()
+        .enumerate()
+        // Symbolic links always have mode 777 on Linux, whatever their target's is
+        .filter(|(_, child)| !child.borrow().is_dir && !child.borrow().meta.is_symlink)
+        .filter_map(|(i, child)| mode(&child.borrow().path()).map(|mode| (i, mode)))
+        .collect();
+
+    if let Some(norm) = majority_mode(modes.iter().map(|(_, mode)| *mode)) {
+        let item = item.borrow();
+        for (i, mode) in modes {
+            if mode != norm {
+                item.children[i].borrow_mut().annotations.push(format!("[perm {:o}, mostly {:o}]", mode, norm));
+            }
+        }
+    }
+
+    for child in &item.borrow().children {
+        if child.borrow().is_dir {
+            annotate_perm_anomalies(child);
+        }
+    }
+}
+
+/// Returns the mode shared by the overwhelming majority of `modes`, if there is one.
+fn majority_mode(modes: impl Iterator<Item = u32>) -> Option<u32> {
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    let mut total = 0;
+    for mode in modes {
+        *counts.entry(mode).or_default() += 1;
+        total += 1;
+    }
+    if total < MIN_FILES {
+        return None;
+    }
+    let (mode, count) = counts.into_iter().max_by_key(|(_, count)| *count)?;
+    (count as f64 >= total as f64 * MAJORITY_SHARE).then_some(mode)
+}
+
+/// Returns the permission bits of the entry at `path`, including setuid, setgid and sticky.
+#[cfg(unix)]
+pub(crate) fn mode(path: &std::path::Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::symlink_metadata(path).ok().map(|meta| meta.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn mode(_path: &std::path::Path) -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn majority_mode_needs_overwhelming_majority() {
+        assert_eq!(majority_mode([0o644, 0o644, 0o644, 0o777].into_iter()), Some(0o644));
+        assert_eq!(majority_mode([0o644, 0o644, 0o755, 0o755].into_iter()), None);
+        assert_eq!(majority_mode([0o644, 0o644, 0o777].into_iter()), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn annotate_perm_anomalies_flags_deviating_file() {
+        use crate::fs_utils::{traverse_fs, TraverseOptions};
+        use crate::tree::TreeItem;
+        use std::fs::{self, File, Permissions};
+        use std::os::unix::fs::PermissionsExt;
+        use tempfile::TempDir;
+
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        for name in ["a.txt", "b.txt", "c.txt", "d.sh"] {
+            let path = temp_dir.path().join(name);
+            File::create(&path).unwrap();
+            let mode = if name == "d.sh" { 0o777 } else { 0o644 };
+            fs::set_permissions(&path, Permissions::from_mode(mode)).unwrap();
+        }
+        std::os::unix::fs::symlink("a.txt", temp_dir.path().join("link")).unwrap();
+        let path = temp_dir.path().to_str().unwrap();
+        let root = TreeItem::new_top_level(path.to_string(), true);
+        traverse_fs(path, &root, &TraverseOptions::default());
+
+        // Call
+        annotate_perm_anomalies(&root);
+
+        // Verify
+        let flagged: Vec<String> = root
+            .borrow()
+            .children
+            .iter()
+            .filter(|child| !child.borrow().annotations.is_empty())
+            .map(|child| child.borrow().to_string())
+            .collect();
+        assert_eq!(flagged, vec!["d.sh [perm 777, mostly 644]"]);
+    }
+}
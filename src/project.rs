@@ -0,0 +1,304 @@
+use crate::check::{parse_string_array, strip_comment};
+use crate::fs_utils::TraverseOptions;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the file with per-project defaults, looked up from the root upwards.
+pub(crate) const CONFIG_FILE: &str = ".ftree.toml";
+
+/// Depth that `--smart` limits the tree to, unless a depth is set explicitly.
+const SMART_MAX_DEPTH: usize = 4;
+
+/// A kind of project, recognized by its manifest in the top level directory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ProjectKind {
+    Rust,
+    Node,
+    Python,
+}
+
+impl ProjectKind {
+    /// Returns the kind of the project at `path`, or `None` if there's no known manifest.
+    pub(crate) fn detect(path: &Path) -> Option<Self> {
+        [
+            ("Cargo.toml", ProjectKind::Rust),
+            ("package.json", ProjectKind::Node),
+            ("pyproject.toml", ProjectKind::Python),
+        ]
+        .into_iter()
+        .find(|(manifest, _)| path.join(manifest).is_file())
+        .map(|(_, kind)| kind)
+    }
+
+    /// Directories with build output or dependencies, which are rarely interesting to look into.
+    fn build_dirs(&self) -> &'static [&'static str] {
+        match self {
+            ProjectKind::Rust => &["target"],
+            ProjectKind::Node => &["node_modules", "dist", "build", "coverage"],
+            ProjectKind::Python => &["__pycache__", ".venv", "venv", "build", "dist", ".pytest_cache", ".mypy_cache"],
+        }
+    }
+}
+
+///
+/// Layers the defaults of `--smart` under `options`: respect `.gitignore`, list directories
+/// first, limit the depth to `SMART_MAX_DEPTH` and collapse the build directories of the
+/// project at `path`. Options that were set explicitly are kept, collapsed directories are added.
+///
+/// Returns the detected project kind. Without one, only the project independent defaults apply.
+///
+pub(crate) fn apply_smart_defaults(path: &Path, options: &mut TraverseOptions) -> Option<ProjectKind> {
+    options.gitignore = true;
+    options.dirs_first = true;
+    options.max_depth = options.max_depth.or(Some(SMART_MAX_DEPTH));
+
+    let kind = ProjectKind::detect(path);
+    if let Some(kind) = kind {
+        options.collapse.extend(kind.build_dirs().iter().map(|dir| dir.to_string()));
+    }
+    kind
+}
+
+///
+/// Per-project defaults from a `.ftree.toml` in the root or one of its parents, like `.editorconfig`:
+///
+/// ```toml
+/// # Limit the depth, unless a depth is set otherwise
+/// depth = 3
+/// # Directories that are listed but not read
+/// collapse = ["target", "node_modules"]
+/// # More badges for --badges, see `Badges`
+/// badges = ["Justfile=just"]
+///
+/// # Flag bundles, used like `ftree @review src/`, see `expand_aliases`
+/// [alias]
+/// review = "--gitignore -s --human --depth-colors"
+/// ```
+///
+/// Only this subset of TOML is supported: comments, an integer `depth`, the arrays of strings
+/// `collapse` and `badges`, and strings in the `[alias]` table.
+///
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ProjectConfig {
+    pub(crate) depth: Option<usize>,
+    pub(crate) collapse: Vec<String>,
+    pub(crate) badges: Vec<String>,
+    /// Names and flags of the `[alias]` table, in the order of the file
+    pub(crate) aliases: Vec<(String, String)>,
+}
+
+impl ProjectConfig {
+    /// Returns the nearest `CONFIG_FILE` in `path` or its parents.
+    pub(crate) fn find(path: &Path) -> Option<PathBuf> {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        path.ancestors().map(|dir| dir.join(CONFIG_FILE)).find(|file| file.is_file())
+    }
+
+    pub(crate) fn load(file: &Path) -> io::Result<ProjectConfig> {
+        let text = fs::read_to_string(file)?;
+        ProjectConfig::parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub(crate) fn parse(text: &str) -> Result<ProjectConfig, String> {
+        let mut config = ProjectConfig::default();
+        let mut lines = text.lines().enumerate();
+        let mut in_alias = false;
+
+        while let Some((i, line)) = lines.next() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(table) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                if table.trim() != "alias" {
+                    return Err(format!("line {}: unknown table '{}', expected alias", i + 1, table.trim()));
+                }
+                in_alias = true;
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| format!("line {}: expected `key = value`", i + 1))?;
+            let mut value = value.trim().to_string();
+            if in_alias {
+                let flags = parse_string_array(&format!("[{}]", value))
+                    .ok()
+                    .filter(|strings| strings.len() == 1)
+                    .ok_or_else(|| format!("line {}: expected a string of flags for alias '{}', got '{}'", i + 1, key.trim(), value))?;
+                config.aliases.push((key.trim().to_string(), flags[0].clone()));
+                continue;
+            }
+            match key.trim() {
+                "depth" => {
+                    let depth = value.parse().map_err(|_| format!("line {}: expected a number for depth, got '{}'", i + 1, value))?;
+                    config.depth = Some(depth);
+                }
+                key @ ("collapse" | "badges") => {
+                    // Arrays may span multiple lines
+                    while value.starts_with('[') && !value.ends_with(']') {
+                        let (_, next) = lines.next().ok_or_else(|| format!("line {}: unterminated array", i + 1))?;
+                        value.push_str(strip_comment(next).trim());
+                    }
+                    let array = parse_string_array(&value).map_err(|err| format!("line {}: {}", i + 1, err))?;
+                    if key == "collapse" {
+                        config.collapse = array;
+                    } else {
+                        config.badges = array;
+                    }
+                }
+                other => return Err(format!("line {}: unknown key '{}', expected depth, collapse or badges", i + 1, other)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Layers the defaults under `options`: an explicit depth is kept, collapsed directories are added.
+    pub(crate) fn apply(&self, options: &mut TraverseOptions) {
+        options.max_depth = options.max_depth.or(self.depth);
+        options.collapse.extend(self.collapse.iter().cloned());
+    }
+}
+
+///
+/// Replaces arguments like `@review` by the flags of the alias `review` in `config`, before they
+/// are parsed, so `ftree @review src/` runs `ftree --gitignore -s --human --depth-colors src/`.
+/// The flags are split at whitespace, except within quotes. Aliases don't expand further.
+///
+/// Arguments after `--` and existing paths starting with `@`, e.g. `node_modules/@types`, are
+/// kept. Fails on unknown aliases.
+///
+pub(crate) fn expand_aliases(args: Vec<OsString>, config: Option<&ProjectConfig>) -> Result<Vec<OsString>, String> {
+    let mut expanded = Vec::with_capacity(args.len());
+    let mut args = args.into_iter();
+    // The name of the executable
+    expanded.extend(args.next());
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            expanded.push(arg);
+            expanded.extend(args.by_ref());
+            break;
+        }
+        let Some(name) = arg.to_str().and_then(|arg| arg.strip_prefix('@')).filter(|_| !Path::new(&arg).exists()) else {
+            expanded.push(arg);
+            continue;
+        };
+        let aliases = config.map_or(&[][..], |config| &config.aliases);
+        let (_, flags) = aliases.iter().find(|(alias, _)| alias == name).ok_or_else(|| {
+            let names: Vec<String> = aliases.iter().map(|(alias, _)| format!("@{}", alias)).collect();
+            match names.is_empty() {
+                true => format!("unknown alias '@{}', there's no [alias] table in {}", name, CONFIG_FILE),
+                false => format!("unknown alias '@{}', expected one of {}", name, names.join(", ")),
+            }
+        })?;
+        let words = split_words(flags).map_err(|err| format!("alias '@{}': {}", name, err))?;
+        expanded.extend(words.into_iter().map(OsString::from));
+    }
+    Ok(expanded)
+}
+
+/// Splits `text` at whitespace like a shell, keeping whitespace within single or double quotes.
+fn split_words(text: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    for c in text.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(q) = quote {
+        return Err(format!("unterminated {} quote", q));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempfile::TempDir;
+
+    #[test]
+    fn apply_smart_defaults_for_rust_project() {
+        let temp_dir = TempDir::new().unwrap();
+        File::create(temp_dir.path().join("Cargo.toml")).unwrap();
+        let mut options = TraverseOptions::default();
+
+        let kind = apply_smart_defaults(temp_dir.path(), &mut options);
+
+        assert_eq!(kind, Some(ProjectKind::Rust));
+        assert!(options.gitignore);
+        assert!(options.dirs_first);
+        assert_eq!(options.max_depth, Some(SMART_MAX_DEPTH));
+        assert_eq!(options.collapse, vec!["target"]);
+    }
+
+    #[test]
+    fn apply_smart_defaults_keeps_explicit_depth() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut options = TraverseOptions { max_depth: Some(2), ..Default::default() };
+
+        let kind = apply_smart_defaults(temp_dir.path(), &mut options);
+
+        assert_eq!(kind, None);
+        assert_eq!(options.max_depth, Some(2));
+        assert!(options.collapse.is_empty());
+    }
+
+    #[test]
+    fn project_config_is_found_in_parents() {
+        let temp_dir = TempDir::new().unwrap();
+        let nested = temp_dir.path().join("a/b");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(ProjectConfig::find(&nested).filter(|file| file.starts_with(temp_dir.path())), None);
+
+        fs::write(temp_dir.path().join(CONFIG_FILE), "depth = 2\ncollapse = [\n  \"target\", # build output\n]\nbadges = [\"Justfile=just\"]\n").unwrap();
+
+        let file = ProjectConfig::find(&nested).unwrap();
+        assert_eq!(file, fs::canonicalize(temp_dir.path()).unwrap().join(CONFIG_FILE));
+        let config = ProjectConfig::load(&file).unwrap();
+        let expected = ProjectConfig { depth: Some(2), collapse: vec!["target".to_string()], badges: vec!["Justfile=just".to_string()], ..Default::default() };
+        assert_eq!(config, expected);
+    }
+
+    #[test]
+    fn project_config_rejects_invalid_input() {
+        assert!(ProjectConfig::parse("depth = deep").unwrap_err().starts_with("line 1:"));
+        assert!(ProjectConfig::parse("# defaults\ncolour = true").unwrap_err().starts_with("line 2: unknown key"));
+    }
+
+    #[test]
+    fn aliases_expand_before_parsing() {
+        let config = ProjectConfig::parse("depth = 2\n\n[alias]\nreview = \"--gitignore -s --root-label 'my project'\" # shared\n").unwrap();
+        let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+
+        let expanded = expand_aliases(args(&["ftree", "@review", "src/", "--", "@review"]), Some(&config)).unwrap();
+
+        assert_eq!(config.depth, Some(2));
+        assert_eq!(expanded, args(&["ftree", "--gitignore", "-s", "--root-label", "my project", "src/", "--", "@review"]));
+        assert_eq!(expand_aliases(args(&["ftree", "@docs"]), Some(&config)).unwrap_err(), "unknown alias '@docs', expected one of @review");
+        assert!(expand_aliases(args(&["ftree", "@docs"]), None).unwrap_err().contains("no [alias] table"));
+        assert!(ProjectConfig::parse("[aliases]").unwrap_err().starts_with("line 1: unknown table"));
+        assert!(ProjectConfig::parse("[alias]\nreview = [\"-s\"]").unwrap_err().starts_with("line 2: expected a string"));
+    }
+
+    #[test]
+    fn project_config_keeps_explicit_options() {
+        let config = ProjectConfig { depth: Some(3), collapse: vec!["dist".to_string()], ..Default::default() };
+        let mut options = TraverseOptions { max_depth: Some(1), collapse: vec!["target".to_string()], ..Default::default() };
+
+        config.apply(&mut options);
+
+        assert_eq!(options.max_depth, Some(1));
+        assert_eq!(options.collapse, vec!["target", "dist"]);
+    }
+}
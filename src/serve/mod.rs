@@ -0,0 +1,374 @@
+use crate::fs_utils::{is_entry_excluded, traverse_fs, Entry, TraverseOptions};
+use crate::i18n;
+use crate::tree::json::to_json_str;
+use crate::tree::TreeItem;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const INDEX_HTML: &str = include_str!("index.html");
+
+/// Depth used by `/api/tree` if the request doesn't specify one.
+const DEFAULT_DEPTH: usize = 1;
+
+/// Deepest `depth` that `/api/tree` reads, so a single request can't walk a whole huge tree.
+const MAX_DEPTH: usize = 16;
+
+/// Connections handled at the same time. Further ones are answered with 503 right away.
+const MAX_CONNECTIONS: usize = 32;
+
+/// Bytes read of the request line and headers together, so a request can't grow without bound.
+const MAX_REQUEST_BYTES: u64 = 16 * 1024;
+
+/// Time a client gets to send its request and to take the response, so an idle one doesn't
+/// keep its connection open forever.
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: String) -> Self {
+        Self { status: "200 OK", content_type, body }
+    }
+
+    fn error(status: &'static str, message: &str) -> Self {
+        Self { status, content_type: "text/plain; charset=utf-8", body: message.to_string() }
+    }
+}
+
+/// Serves the tree of `root` over HTTP until the process is stopped.
+///
+/// * `/` returns a minimal HTML page to browse the tree.
+/// * `/api/tree?path=<relative path>&depth=<n>` returns the subtree at `path` as JSON,
+///   reading only `depth` levels below it (see `tree::json::to_json_str`).
+///
+/// Each connection is handled on its own thread, so a failing request doesn't stop the server.
+/// As the server may be reachable from the network, it handles at most `MAX_CONNECTIONS` at a
+/// time, each with `IO_TIMEOUT` and at most `MAX_REQUEST_BYTES` of request.
+pub(crate) fn serve(root: &Path, address: &str, options: &TraverseOptions) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    println!("{}", i18n::tr!("Serving {} on http://{}", root.display(), listener.local_addr()?));
+
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        // Failing to accept, e.g. when out of file descriptors, only affects that connection
+        let Ok(stream) = stream else { continue };
+        if active.load(Ordering::SeqCst) >= MAX_CONNECTIONS {
+            let _ = stream.set_write_timeout(Some(IO_TIMEOUT));
+            let _ = write_response(&stream, &Response::error("503 Service Unavailable", "Too many connections"));
+            continue;
+        }
+        active.fetch_add(1, Ordering::SeqCst);
+        let active = Arc::clone(&active);
+        let root = root.to_path_buf();
+        let options = options.clone();
+        thread::spawn(move || {
+            if let Err(err) = handle_connection(stream, &root, &options, IO_TIMEOUT) {
+                eprintln!("{}", i18n::tr!("Error handling request: {}", err));
+            }
+            active.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, root: &Path, options: &TraverseOptions, timeout: Duration) -> io::Result<()> {
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let response = match read_request(&stream)? {
+        Some(request_line) => respond(&request_line, root, options),
+        None => Response::error("431 Request Header Fields Too Large", "The request is too large"),
+    };
+    write_response(&stream, &response)
+}
+
+/// Returns the request line of the request in `stream` after reading its headers, or `None` if
+/// they're longer than `MAX_REQUEST_BYTES`.
+fn read_request(stream: impl Read) -> io::Result<Option<String>> {
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_BYTES));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Skip the headers, they aren't needed for any route
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+    if reader.get_ref().limit() == 0 {
+        return Ok(None);
+    }
+    Ok(Some(request_line))
+}
+
+fn write_response(mut stream: &TcpStream, response: &Response) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+fn respond(request_line: &str, root: &Path, options: &TraverseOptions) -> Response {
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Response::error("400 Bad Request", "Malformed request");
+    };
+    if method != "GET" {
+        return Response::error("405 Method Not Allowed", "Only GET is supported");
+    }
+
+    let (route, query) = target.split_once('?').unwrap_or((target, ""));
+    match route {
+        "/" => Response::ok("text/html; charset=utf-8", INDEX_HTML.to_string()),
+        "/api/tree" => tree_response(query, root, options),
+        _ => Response::error("404 Not Found", "Not found"),
+    }
+}
+
+fn tree_response(query: &str, root: &Path, options: &TraverseOptions) -> Response {
+    let mut rel_path = String::new();
+    let mut depth = DEFAULT_DEPTH;
+    for (key, value) in parse_query(query) {
+        match key.as_str() {
+            "path" => rel_path = value,
+            "depth" => match value.parse::<usize>() {
+                Ok(d) if d <= MAX_DEPTH => depth = d.max(1),
+                _ => return Response::error("400 Bad Request", &format!("depth must be a number up to {}", MAX_DEPTH)),
+            },
+            _ => {}
+        }
+    }
+
+    let Some(dir) = resolve_path(root, &rel_path, options) else {
+        return Response::error("404 Not Found", "No such directory below the served root");
+    };
+
+    let Some(dir) = dir.to_str() else {
+        return Response::error("500 Internal Server Error", "The served directory's path isn't valid UTF-8");
+    };
+    let text = if rel_path.is_empty() { ".".to_string() } else { rel_path };
+    let item = TreeItem::new_top_level(text, true);
+    let options = TraverseOptions { max_depth: Some(depth), ..options.clone() };
+    traverse_fs(dir, &item, &options);
+
+    let json = to_json_str(&item.borrow(), Some(depth));
+    Response::ok("application/json", json)
+}
+
+/// Resolves `rel_path` against `root`. Returns `None` for paths that would leave `root`
+/// (absolute paths, `..` components or symbolic links to outside of it), for paths hidden by the
+/// filters in `options`, e.g. `.git` with `--gitignore`, and for paths that aren't directories.
+fn resolve_path(root: &Path, rel_path: &str, options: &TraverseOptions) -> Option<PathBuf> {
+    let rel_path = Path::new(rel_path);
+    let escapes_root = rel_path
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+    if escapes_root {
+        return None;
+    }
+
+    let path = root.join(rel_path);
+    let real_root = fs::canonicalize(root).ok()?;
+    if !fs::canonicalize(&path).ok()?.starts_with(&real_root) || !path.is_dir() {
+        return None;
+    }
+
+    // Each directory on the way must be one the tree would show
+    let mut parent = root.to_path_buf();
+    for name in rel_path.components().filter_map(|c| match c {
+        Component::Normal(name) => name.to_str(),
+        _ => None,
+    }) {
+        let metadata = fs::symlink_metadata(parent.join(name)).ok()?;
+        let entry = Entry::from_metadata(name.to_string(), &metadata);
+        if is_entry_excluded(parent.to_str()?, &entry, options) {
+            return None;
+        }
+        parent.push(name);
+    }
+    Some(path)
+}
+
+/// Splits a query string like `path=src%2Ftree&depth=2` into decoded key/value pairs.
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (_, Some(byte)) => {
+                decoded.push(byte);
+                i += 2;
+            }
+            (b'+', _) => decoded.push(b' '),
+            (byte, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn parse_query_decodes_pairs() {
+        let pairs = parse_query("path=src%2Fmy+dir&depth=2");
+        assert_eq!(pairs, vec![
+            ("path".to_string(), "src/my dir".to_string()),
+            ("depth".to_string(), "2".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn percent_decode_keeps_malformed_escapes() {
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+
+    #[test]
+    fn resolve_path_rejects_escaping_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("dir1")).unwrap();
+
+        let options = TraverseOptions::default();
+
+        assert_eq!(resolve_path(temp_dir.path(), "", &options), Some(temp_dir.path().to_path_buf()));
+        assert_eq!(resolve_path(temp_dir.path(), "./dir1", &options), Some(temp_dir.path().join("./dir1")));
+        assert_eq!(resolve_path(temp_dir.path(), "../", &options), None);
+        assert_eq!(resolve_path(temp_dir.path(), "dir1/../..", &options), None);
+        assert_eq!(resolve_path(temp_dir.path(), "/etc", &options), None);
+        assert_eq!(resolve_path(temp_dir.path(), "missing", &options), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_path_rejects_links_out_of_the_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let served = temp_dir.path().join("served");
+        fs::create_dir_all(served.join("dir1")).unwrap();
+        fs::create_dir(temp_dir.path().join("outside")).unwrap();
+        std::os::unix::fs::symlink("/", served.join("rootlink")).unwrap();
+        std::os::unix::fs::symlink("../outside", served.join("sibling")).unwrap();
+        std::os::unix::fs::symlink("dir1", served.join("inner")).unwrap();
+        let options = TraverseOptions::default();
+
+        assert_eq!(resolve_path(&served, "rootlink", &options), None);
+        assert_eq!(resolve_path(&served, "sibling", &options), None);
+        assert_eq!(resolve_path(&served, "inner", &options), Some(served.join("inner")));
+        assert_eq!(respond("GET /api/tree?path=rootlink HTTP/1.1", &served, &options).status, "404 Not Found");
+    }
+
+    #[test]
+    fn resolve_path_applies_the_filters() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join(".git/objects")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("build/out")).unwrap();
+        fs::create_dir(temp_dir.path().join("src")).unwrap();
+        fs::write(temp_dir.path().join(".gitignore"), "build/\n").unwrap();
+        let root = temp_dir.path().to_str().unwrap().to_string();
+        let options = TraverseOptions { gitignore: true, roots: vec![root], ..Default::default() };
+
+        assert_eq!(resolve_path(temp_dir.path(), ".git", &options), None);
+        assert_eq!(resolve_path(temp_dir.path(), ".git/objects", &options), None);
+        assert_eq!(resolve_path(temp_dir.path(), "build/out", &options), None);
+        assert_eq!(resolve_path(temp_dir.path(), "src", &options), Some(temp_dir.path().join("src")));
+        assert_eq!(resolve_path(temp_dir.path(), ".git", &TraverseOptions::default()), Some(temp_dir.path().join(".git")));
+    }
+
+    #[test]
+    fn read_request_limits_the_size() {
+        let request = "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        assert_eq!(read_request(request.as_bytes()).unwrap(), Some("GET / HTTP/1.1\r\n".to_string()));
+
+        let without_newline = "a".repeat(MAX_REQUEST_BYTES as usize * 4);
+        assert_eq!(read_request(without_newline.as_bytes()).unwrap(), None);
+        let many_headers = format!("GET / HTTP/1.1\r\n{}\r\n", "X-Padding: 0\r\n".repeat(10_000));
+        assert_eq!(read_request(many_headers.as_bytes()).unwrap(), None);
+    }
+
+    #[test]
+    fn handle_connection_times_out_idle_clients() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        let started = std::time::Instant::now();
+        let result = handle_connection(stream, Path::new("."), &TraverseOptions::default(), Duration::from_millis(100));
+
+        assert!(matches!(result.unwrap_err().kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn handle_connection_rejects_oversized_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        client.write_all(&vec![b'a'; MAX_REQUEST_BYTES as usize]).unwrap();
+
+        handle_connection(stream, Path::new("."), &TraverseOptions::default(), Duration::from_secs(5)).unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 431 "), "{}", response);
+    }
+
+    #[test]
+    fn respond_routes_requests() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("dir1")).unwrap();
+        let options = TraverseOptions::default();
+
+        let index = respond("GET / HTTP/1.1", temp_dir.path(), &options);
+        assert_eq!(index.status, "200 OK");
+        assert!(index.body.contains("<html"));
+
+        let tree = respond("GET /api/tree?path=&depth=1 HTTP/1.1", temp_dir.path(), &options);
+        assert_eq!(tree.status, "200 OK");
+        assert_eq!(
+            tree.body,
+            r#"{"name":"./","path":".","type":"dir","children":[{"name":"dir1/","path":"./dir1","type":"dir","children":null}]}"#
+        );
+
+        let deep = respond(&format!("GET /api/tree?depth={} HTTP/1.1", MAX_DEPTH + 1), temp_dir.path(), &options);
+        assert_eq!(deep.status, "400 Bad Request");
+        assert_eq!(respond("GET /nope HTTP/1.1", temp_dir.path(), &options).status, "404 Not Found");
+        assert_eq!(respond("POST / HTTP/1.1", temp_dir.path(), &options).status, "405 Method Not Allowed");
+    }
+}
@@ -0,0 +1,14 @@
+# ftree shell integration for bash. Load it in ~/.bashrc with:
+#   eval "$(ftree init bash)"
+# Optionally bind the picker to a key, e.g. Alt-T:
+#   bind -x '"\et": ftcd'
+
+# Picks a path from a searchable tree with fzf and changes into it (or into the directory
+# of a picked file). Arguments are passed to ftree, e.g. `ftcd --gitignore src`.
+ftcd() {
+    local selected
+    selected="$(command ftree --fzf "$@" | fzf --read0 --delimiter='\t' --with-nth=1 --no-sort --layout=reverse | cut -f2-)" || return
+    [ -n "$selected" ] || return
+    [ -d "$selected" ] || selected="$(dirname -- "$selected")"
+    cd -- "$selected" || return
+}
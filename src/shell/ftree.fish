@@ -0,0 +1,14 @@
+# ftree shell integration for fish. Load it in ~/.config/fish/config.fish with:
+#   ftree init fish | source
+# Optionally bind the picker to a key, e.g. Alt-T:
+#   bind \et 'ftcd; commandline -f repaint'
+
+# Picks a path from a searchable tree with fzf and changes into it (or into the directory
+# of a picked file). Arguments are passed to ftree, e.g. `ftcd --gitignore src`.
+function ftcd --description 'Pick a path from a searchable tree and cd into it'
+    set -l selected (command ftree --fzf $argv | fzf --read0 --delimiter='\t' --with-nth=1 --no-sort --layout=reverse | cut -f2-)
+    or return
+    test -n "$selected"; or return
+    test -d "$selected"; or set selected (dirname -- "$selected")
+    cd -- "$selected"
+end
@@ -0,0 +1,33 @@
+use clap::ValueEnum;
+
+/// A shell that `ftree init` can print the integration for.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Returns the script defining `ftcd`, which picks a path from `ftree --fzf` with fzf and
+/// changes into it. The zsh script also defines the `ftree-cd-widget` line editor widget.
+pub(crate) fn init_script(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => include_str!("ftree.bash"),
+        Shell::Zsh => include_str!("ftree.zsh"),
+        Shell::Fish => include_str!("ftree.fish"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn init_script_defines_ftcd() {
+        for shell in Shell::value_variants() {
+            let script = init_script(*shell);
+            assert!(script.contains("ftcd"), "{:?}", shell);
+            assert!(script.contains("ftree --fzf"), "{:?}", shell);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+
+/// How to show sizes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SizeFormat {
+    /// Bytes with thousands separators, see `format_bytes`
+    Bytes,
+    /// Binary units, see `format_human`
+    Human,
+}
+
+impl SizeFormat {
+    pub(crate) fn format(&self, bytes: u64) -> String {
+        match self {
+            SizeFormat::Bytes => format_bytes(bytes),
+            SizeFormat::Human => format_human(bytes),
+        }
+    }
+}
+
+/// Formats `bytes` with thousands separators, e.g. `12,345,678`.
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    separate_thousands(bytes.to_string())
+}
+
+/// Formats a number of entries with thousands separators, e.g. `1,234`.
+pub(crate) fn format_count(count: usize) -> String {
+    separate_thousands(count.to_string())
+}
+
+fn separate_thousands(digits: String) -> String {
+    let mut result = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            result.push(',');
+        }
+        result.push(digit);
+    }
+    result
+}
+
+/// Formats `bytes` in binary units with one decimal, e.g. `512 B` or `1.2 MiB`.
+pub(crate) fn format_human(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_bytes_separates_thousands() {
+        assert_eq!(format_bytes(0), "0");
+        assert_eq!(format_bytes(999), "999");
+        assert_eq!(format_bytes(1000), "1,000");
+        assert_eq!(format_bytes(12345678), "12,345,678");
+        assert_eq!(format_count(1234), "1,234");
+    }
+
+    #[test]
+    fn format_human_units() {
+        assert_eq!(format_human(512), "512 B");
+        assert_eq!(format_human(1536), "1.5 KiB");
+        assert_eq!(format_human(5 * 1024 * 1024), "5.0 MiB");
+    }
+}
@@ -0,0 +1,889 @@
+use crate::check::collect_relative_paths;
+use crate::i18n;
+use crate::perms;
+use crate::size_utils::format_human;
+use crate::tree::{display_name, escape_invisible, SymdirPolicy, TreeItemRefCell};
+use clap::ValueEnum;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// Upper bounds of the age buckets with their labels. Older files fall into `OLDEST_BUCKET`.
+const AGE_BUCKETS: [(Duration, &str); 4] = [
+    (Duration::from_secs(7 * DAY_SECS), "< 1 week"),
+    (Duration::from_secs(30 * DAY_SECS), "< 1 month"),
+    (Duration::from_secs(182 * DAY_SECS), "< 6 months"),
+    (Duration::from_secs(365 * DAY_SECS), "< 1 year"),
+];
+const OLDEST_BUCKET: &str = "≥ 1 year";
+
+/// Label of the row for files directly in the top level directory.
+const TOP_LEVEL_FILES: &str = "(files)";
+
+/// Width of the longest bar in the depth histogram.
+const HISTOGRAM_WIDTH: usize = 40;
+
+/// Width of the bars of the treemap.
+const TREEMAP_WIDTH: usize = 60;
+
+/// Keys of the treemap blocks. Entries beyond these are summed up in `OTHER_ENTRIES`.
+const TREEMAP_KEYS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const OTHER_ENTRIES: &str = "(other)";
+const OTHER_KEY: char = '.';
+
+/// Maximum number of paths listed by `combined_report`, the others are counted.
+const MAX_SHARED_PATHS: usize = 20;
+
+/// Label of the row for files whose owner is unknown, e.g. on platforms without Unix owners.
+const UNKNOWN_OWNER: &str = "?";
+
+/// Permission bits flagged in `--stats perms`, with their labels.
+const RISKY_BITS: [(u32, &str); 3] = [(0o4000, "setuid"), (0o2000, "setgid"), (0o002, "world-writable")];
+
+/// Label of the row for entries whose permissions are unknown.
+const UNKNOWN_MODE: &str = "?";
+
+/// Which summary `--stats` prints instead of the tree.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum StatsKind {
+    /// Number of files and bytes per age bucket (by last modification) for each top level directory
+    Age,
+    /// Deepest path, average depth and a histogram of the number of entries per depth
+    Depth,
+    /// Number of files and bytes per owning user, largest first
+    Owner,
+    /// Number of entries per permission mode, flagging setuid, setgid and world-writable ones
+    Perms,
+    /// Proportional bars of the bytes and files per top level directory
+    Treemap,
+}
+
+/// Number of files and their total size.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Tally {
+    files: u64,
+    bytes: u64,
+}
+
+impl Tally {
+    fn add(&mut self, bytes: u64) {
+        self.files += 1;
+        self.bytes += bytes;
+    }
+
+    fn to_cell(self) -> String {
+        if self.files == 0 {
+            "-".to_string()
+        } else {
+            format!("{} ({})", self.files, format_human(self.bytes))
+        }
+    }
+}
+
+/// Renders the summary of the tree below `root` selected by `kind`. `symdirs` decides whether
+/// symbolic links to directories count as (empty) directories or as files. Invisible characters
+/// in the names are escaped like in the tree, unless `raw_names`.
+pub(crate) fn to_stats_str(root: &Rc<TreeItemRefCell>, kind: StatsKind, now: SystemTime, symdirs: SymdirPolicy, raw_names: bool) -> String {
+    match kind {
+        StatsKind::Age => age_report(root, now, symdirs, raw_names),
+        StatsKind::Depth => depth_report(root, raw_names),
+        StatsKind::Owner => owner_report(root, symdirs, &user_names()),
+        StatsKind::Perms => perms_report(root),
+        StatsKind::Treemap => treemap_report(root, symdirs, raw_names),
+    }
+}
+
+///
+/// Renders a table with the number of files and bytes per age bucket for each top level
+/// directory, e.g.:
+///
+/// ```text
+///             < 1 week  < 1 month   < 6 months     < 1 year      ≥ 1 year
+/// data/              -          -            -  2 (1.2 GiB)  40 (8.1 GiB)
+/// src/     3 (4.0 KiB)  1 (512 B)            -            -             -
+/// (files)            -          -  1 (1.1 KiB)            -             -
+/// total    3 (4.0 KiB)  1 (512 B)  1 (1.1 KiB)  2 (1.2 GiB)  40 (8.1 GiB)
+/// ```
+///
+/// The age is the time since the last modification. Files without one count as oldest.
+///
+fn age_report(root: &Rc<TreeItemRefCell>, now: SystemTime, symdirs: SymdirPolicy, raw_names: bool) -> String {
+    let bucket_count = AGE_BUCKETS.len() + 1;
+    let mut top_level_files = vec![Tally::default(); bucket_count];
+    let mut rows = Vec::new();
+
+    for child in &root.borrow().children {
+        let child_ref = child.borrow();
+        if child_ref.counts_as_dir(symdirs) {
+            let mut tallies = vec![Tally::default(); bucket_count];
+            tally_ages(child, now, symdirs, &mut tallies);
+            rows.push((shown_name(&child_ref.text, true, raw_names), tallies));
+        } else {
+            top_level_files[age_bucket(child_ref.meta.modified, now)].add(child_ref.meta.size);
+        }
+    }
+    rows.push((i18n::translate(TOP_LEVEL_FILES).to_string(), top_level_files));
+
+    let mut total = vec![Tally::default(); bucket_count];
+    for (_, tallies) in &rows {
+        for (sum, tally) in total.iter_mut().zip(tallies) {
+            sum.files += tally.files;
+            sum.bytes += tally.bytes;
+        }
+    }
+    rows.push((i18n::translate("total").to_string(), total));
+
+    let mut header = vec![String::new()];
+    header.extend(AGE_BUCKETS.iter().map(|(_, label)| i18n::translate(label).to_string()));
+    header.push(i18n::translate(OLDEST_BUCKET).to_string());
+    let mut table = vec![header];
+    for (name, tallies) in rows {
+        let mut row = vec![name];
+        row.extend(tallies.into_iter().map(Tally::to_cell));
+        table.push(row);
+    }
+    format_table(&table)
+}
+
+fn tally_ages(item: &Rc<TreeItemRefCell>, now: SystemTime, symdirs: SymdirPolicy, tallies: &mut [Tally]) {
+    for child in &item.borrow().children {
+        let child_ref = child.borrow();
+        if child_ref.counts_as_dir(symdirs) {
+            tally_ages(child, now, symdirs, tallies);
+        } else {
+            tallies[age_bucket(child_ref.meta.modified, now)].add(child_ref.meta.size);
+        }
+    }
+}
+
+/// Returns the index of the bucket for a file last modified at `modified`.
+fn age_bucket(modified: Option<SystemTime>, now: SystemTime) -> usize {
+    // Times in the future (e.g. clock skew) count as new
+    let age = modified.map(|modified| now.duration_since(modified).unwrap_or_default());
+    match age {
+        Some(age) => AGE_BUCKETS.iter().position(|(bound, _)| age < *bound).unwrap_or(AGE_BUCKETS.len()),
+        None => AGE_BUCKETS.len(),
+    }
+}
+
+///
+/// Renders the deepest path, the average depth and a histogram of the number of entries per
+/// depth, where the entries of the top level directory have depth 1, e.g.:
+///
+/// ```text
+/// deepest: ./node_modules/a/node_modules/b/lib/index.js (depth 6)
+/// average depth: 3.2
+///
+/// depth  entries
+/// 1            4  ███
+/// 2           52  ████████████████████████████████████████
+/// 3           17  █████████████
+/// ```
+///
+fn depth_report(root: &Rc<TreeItemRefCell>, raw_names: bool) -> String {
+    let mut counts: Vec<u64> = Vec::new();
+    let mut deepest = None;
+    count_depths(root, 1, &mut counts, &mut deepest);
+    let Some((deepest_depth, deepest_path)) = deepest else {
+        return i18n::translate("no entries").to_string();
+    };
+
+    let total: u64 = counts.iter().sum();
+    let depth_sum: u64 = counts.iter().enumerate().map(|(i, count)| (i as u64 + 1) * count).sum();
+    let max_count = counts.iter().copied().max().unwrap_or(1);
+
+    let mut table = vec![header_row(&["depth", "entries"])];
+    table.extend(counts.iter().enumerate().map(|(i, count)| vec![(i + 1).to_string(), count.to_string()]));
+    // The bars are left-aligned, so they're appended to the right-aligned table
+    let bars = counts.iter().map(|count| "█".repeat((*count as usize * HISTOGRAM_WIDTH).div_ceil(max_count as usize)));
+    let mut histogram = format_table(&table).lines().map(str::to_string).collect::<Vec<_>>();
+    for (row, bar) in histogram.iter_mut().skip(1).zip(bars) {
+        row.push_str("  ");
+        row.push_str(&bar);
+    }
+    format!(
+        "{}\n{}\n\n{}",
+        i18n::tr!("deepest: {} (depth {})", shown_name(&deepest_path, false, raw_names), deepest_depth),
+        i18n::tr!("average depth: {}", format!("{:.1}", depth_sum as f64 / total as f64)),
+        histogram.join("\n")
+    )
+}
+
+/// Counts the descendants of `item` per depth, where its children have `depth`, and keeps the
+/// first of the deepest ones.
+fn count_depths(item: &Rc<TreeItemRefCell>, depth: usize, counts: &mut Vec<u64>, deepest: &mut Option<(usize, String)>) {
+    for child in &item.borrow().children {
+        if counts.len() < depth {
+            counts.push(0);
+        }
+        counts[depth - 1] += 1;
+        if deepest.as_ref().is_none_or(|(max, _)| depth > *max) {
+            let child_ref = child.borrow();
+            *deepest = Some((depth, display_name(&child_ref.path().to_string_lossy(), child_ref.is_dir)));
+        }
+        count_depths(child, depth + 1, counts, deepest);
+    }
+}
+
+///
+/// Renders the share of bytes and files of each top level directory as proportional blocks,
+/// like a one-dimensional treemap, with a key, e.g.:
+///
+/// ```text
+/// bytes  AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABBC
+/// files  ABBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBCCC
+///
+/// A  data/    8.1 GiB  97%   40   5%
+/// B  src/   120.0 MiB   2%  812  90%
+/// C  (files)  9.0 MiB   1%   48   5%
+/// ```
+///
+/// Entries are ordered by size. Beyond 26 entries, the smallest ones are summed up as `(other)`.
+///
+fn treemap_report(root: &Rc<TreeItemRefCell>, symdirs: SymdirPolicy, raw_names: bool) -> String {
+    let mut entries: Vec<(String, Tally)> = Vec::new();
+    let mut top_level_files = Tally::default();
+    for child in &root.borrow().children {
+        let child_ref = child.borrow();
+        if child_ref.counts_as_dir(symdirs) {
+            let mut tally = Tally::default();
+            tally_files(child, symdirs, &mut tally);
+            entries.push((shown_name(&child_ref.text, true, raw_names), tally));
+        } else {
+            top_level_files.add(child_ref.meta.size);
+        }
+    }
+    if top_level_files.files > 0 {
+        entries.push((TOP_LEVEL_FILES.to_string(), top_level_files));
+    }
+    entries.sort_by(|(a_name, a), (b_name, b)| b.bytes.cmp(&a.bytes).then(b.files.cmp(&a.files)).then(a_name.cmp(b_name)));
+    if entries.len() > TREEMAP_KEYS.len() {
+        let rest = entries.split_off(TREEMAP_KEYS.len() - 1);
+        let mut other = Tally::default();
+        for (_, tally) in rest {
+            other.files += tally.files;
+            other.bytes += tally.bytes;
+        }
+        entries.push((OTHER_ENTRIES.to_string(), other));
+    }
+
+    let keys: Vec<char> = TREEMAP_KEYS.chars().take(entries.len()).collect();
+    let keys = if entries.last().is_some_and(|(name, _)| name == OTHER_ENTRIES) {
+        keys[..keys.len() - 1].iter().copied().chain([OTHER_KEY]).collect()
+    } else {
+        keys
+    };
+    let bytes: Vec<u64> = entries.iter().map(|(_, tally)| tally.bytes).collect();
+    let files: Vec<u64> = entries.iter().map(|(_, tally)| tally.files).collect();
+    let total_bytes: u64 = bytes.iter().sum();
+    let total_files: u64 = files.iter().sum();
+    if total_files == 0 {
+        return i18n::translate("no files").to_string();
+    }
+
+    let bar = |values: &[u64]| -> String {
+        let widths = proportional_widths(values, TREEMAP_WIDTH);
+        keys.iter().zip(widths).map(|(key, width)| key.to_string().repeat(width)).collect()
+    };
+    let share = |value: u64, total: u64| (value * 100 + total / 2).checked_div(total).map_or("-".to_string(), |percent| format!("{}%", percent));
+    let mut table = Vec::new();
+    for (key, (name, tally)) in keys.iter().zip(&entries) {
+        table.push(vec![
+            format!("{}  {}", key, i18n::translate(name)),
+            format_human(tally.bytes),
+            share(tally.bytes, total_bytes),
+            tally.files.to_string(),
+            share(tally.files, total_files),
+        ]);
+    }
+    let (bytes_label, files_label) = (i18n::translate("bytes"), i18n::translate("files"));
+    let width = bytes_label.chars().count().max(files_label.chars().count());
+    format!("{:<w$}  {}\n{:<w$}  {}\n\n{}", bytes_label, bar(&bytes), files_label, bar(&files), format_table(&table), w = width)
+}
+
+fn tally_files(item: &Rc<TreeItemRefCell>, symdirs: SymdirPolicy, tally: &mut Tally) {
+    for child in &item.borrow().children {
+        let child_ref = child.borrow();
+        if child_ref.counts_as_dir(symdirs) {
+            tally_files(child, symdirs, tally);
+        } else {
+            tally.add(child_ref.meta.size);
+        }
+    }
+}
+
+/// Splits `width` into parts proportional to `values` that add up to `width` (unless all
+/// values are 0), giving the rounding remainders to the largest fractions.
+fn proportional_widths(values: &[u64], width: usize) -> Vec<usize> {
+    let total: u64 = values.iter().sum();
+    if total == 0 {
+        return vec![0; values.len()];
+    }
+    let exact: Vec<u128> = values.iter().map(|value| *value as u128 * width as u128).collect();
+    let mut widths: Vec<usize> = exact.iter().map(|scaled| (scaled / total as u128) as usize).collect();
+    let mut by_remainder: Vec<usize> = (0..values.len()).collect();
+    by_remainder.sort_by_key(|i| std::cmp::Reverse(exact[*i] % total as u128));
+    let missing = width - widths.iter().sum::<usize>();
+    for i in by_remainder.into_iter().take(missing) {
+        widths[i] += 1;
+    }
+    widths
+}
+
+///
+/// Renders a table with the number of files and bytes per owning user, largest first, e.g.:
+///
+/// ```text
+/// owner  files       size
+/// alice    812    4.1 GiB
+/// bob       33  120.5 MiB
+/// total    845    4.2 GiB
+/// ```
+///
+/// Owners without a name in `names` are shown by their user id.
+///
+fn owner_report(root: &Rc<TreeItemRefCell>, symdirs: SymdirPolicy, names: &HashMap<u32, String>) -> String {
+    let mut tallies: HashMap<Option<u32>, Tally> = HashMap::new();
+    tally_owners(root, symdirs, &mut tallies);
+
+    let mut tallies: Vec<(Option<u32>, Tally)> = tallies.into_iter().collect();
+    tallies.sort_by(|(a_uid, a), (b_uid, b)| b.bytes.cmp(&a.bytes).then(a_uid.cmp(b_uid)));
+    let mut total = Tally::default();
+    let mut table = vec![header_row(&["owner", "files", "size"])];
+    for (uid, tally) in tallies {
+        let owner = match uid {
+            Some(uid) => names.get(&uid).cloned().unwrap_or_else(|| uid.to_string()),
+            None => UNKNOWN_OWNER.to_string(),
+        };
+        table.push(vec![owner, tally.files.to_string(), format_human(tally.bytes)]);
+        total.files += tally.files;
+        total.bytes += tally.bytes;
+    }
+    table.push(vec![i18n::translate("total").to_string(), total.files.to_string(), format_human(total.bytes)]);
+    format_table(&table)
+}
+
+fn tally_owners(item: &Rc<TreeItemRefCell>, symdirs: SymdirPolicy, tallies: &mut HashMap<Option<u32>, Tally>) {
+    for child in &item.borrow().children {
+        let child_ref = child.borrow();
+        if child_ref.counts_as_dir(symdirs) {
+            tally_owners(child, symdirs, tallies);
+        } else {
+            tallies.entry(owner(&child_ref.path())).or_default().add(child_ref.meta.size);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn owner(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::symlink_metadata(path).ok().map(|meta| meta.uid())
+}
+
+#[cfg(not(unix))]
+fn owner(_path: &Path) -> Option<u32> {
+    None
+}
+
+/// Returns the names of the local users by their id, from `/etc/passwd`. Users from directory
+/// services like LDAP aren't listed there and are shown by their id.
+fn user_names() -> HashMap<u32, String> {
+    std::fs::read_to_string("/etc/passwd").map(|text| parse_passwd(&text)).unwrap_or_default()
+}
+
+/// Parses lines like `alice:x:1000:1000:Alice:/home/alice:/bin/bash` into a map from user id to name.
+fn parse_passwd(text: &str) -> HashMap<u32, String> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let uid = fields.nth(1)?.parse().ok()?;
+            Some((uid, name.to_string()))
+        })
+        .collect()
+}
+
+///
+/// Renders a table with the number of entries per permission mode, most common first, with the
+/// modes that deserve a closer look flagged, e.g.:
+///
+/// ```text
+/// mode                  entries
+/// 644                       812
+/// 755                        40
+/// 777 (world-writable)        2
+/// 4755 (setuid)               1
+/// total                     855
+/// ```
+///
+/// Files and directories count alike, symbolic links don't, as their own permissions aren't used.
+/// Entries whose permissions can't be read, e.g. in a tree from `--parse-tree` or on platforms
+/// without Unix permissions, are counted as `?`.
+///
+fn perms_report(root: &Rc<TreeItemRefCell>) -> String {
+    let mut counts: HashMap<Option<u32>, u64> = HashMap::new();
+    tally_modes(root, &mut counts);
+
+    let mut counts: Vec<(Option<u32>, u64)> = counts.into_iter().collect();
+    counts.sort_by(|(a_mode, a), (b_mode, b)| b.cmp(a).then(a_mode.is_none().cmp(&b_mode.is_none())).then(a_mode.cmp(b_mode)));
+    let mut table = vec![header_row(&["mode", "entries"])];
+    for (mode, count) in &counts {
+        let label = match mode {
+            Some(mode) => mode_label(*mode),
+            None => UNKNOWN_MODE.to_string(),
+        };
+        table.push(vec![label, count.to_string()]);
+    }
+    table.push(vec![i18n::translate("total").to_string(), counts.iter().map(|(_, count)| count).sum::<u64>().to_string()]);
+    format_table(&table)
+}
+
+fn tally_modes(item: &Rc<TreeItemRefCell>, counts: &mut HashMap<Option<u32>, u64>) {
+    for child in &item.borrow().children {
+        let child_ref = child.borrow();
+        if !child_ref.meta.is_symlink {
+            *counts.entry(perms::mode(&child_ref.path())).or_default() += 1;
+        }
+        if child_ref.is_dir {
+            tally_modes(child, counts);
+        }
+    }
+}
+
+/// Returns the octal mode with its risky bits, e.g. `4755 (setuid)`.
+fn mode_label(mode: u32) -> String {
+    let flags: Vec<&str> = RISKY_BITS.iter().filter(|(bit, _)| mode & bit != 0).map(|(_, label)| *label).collect();
+    if flags.is_empty() {
+        format!("{:o}", mode)
+    } else {
+        format!("{:o} ({})", mode, flags.join(", "))
+    }
+}
+
+///
+/// Renders how much space deleting the files below `root` would free, per directory with such
+/// files, largest first, e.g. for the files left by filters like `--created-before`:
+///
+/// ```text
+/// directory        files      size
+/// ./logs/archive/    120   3.2 GiB
+/// ./logs/             14  81.0 MiB
+/// total              134   3.3 GiB
+/// ```
+///
+/// The size is the space allocated on disk where it's known, so sparse files count with what
+/// they actually take up. Files with other hard links free nothing, which isn't checked.
+///
+pub(crate) fn reclaim_report(root: &Rc<TreeItemRefCell>, raw_names: bool) -> String {
+    let mut rows = Vec::new();
+    tally_reclaim(root, raw_names, &mut rows);
+    rows.sort_by(|(a_dir, a), (b_dir, b)| b.bytes.cmp(&a.bytes).then(a_dir.cmp(b_dir)));
+
+    let mut table = vec![header_row(&["directory", "files", "size"])];
+    let mut total = Tally::default();
+    for (dir, tally) in rows {
+        table.push(vec![dir, tally.files.to_string(), format_human(tally.bytes)]);
+        total.files += tally.files;
+        total.bytes += tally.bytes;
+    }
+    table.push(vec![i18n::translate("total").to_string(), total.files.to_string(), format_human(total.bytes)]);
+    format_table(&table)
+}
+
+///
+/// Renders the section of `--combined` for the trees of several directories: the directories,
+/// files and bytes of each tree and of all of them, then the paths that are in more than one
+/// tree, relative to their top level directory, e.g.:
+///
+/// ```text
+/// Combined:
+/// directory  dirs  files      size
+/// api/          1      2  10.0 KiB
+/// worker/       1      1     512 B
+/// total         2      3  10.5 KiB
+///
+/// Shared paths:
+///   Cargo.toml (api/, worker/)
+///   src/ (api/, worker/)
+/// ```
+///
+/// Invisible characters in the paths are escaped like in the tree, unless `raw_names`.
+///
+pub(crate) fn combined_report(trees: &[Rc<TreeItemRefCell>], raw_names: bool) -> String {
+    let mut table = vec![header_row(&["directory", "dirs", "files", "size"])];
+    let mut total = (0, Tally::default());
+    let mut shared: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for tree in trees {
+        let label = shown_name(&tree.borrow().text, true, raw_names);
+        let (dirs, tally) = tally_tree(tree);
+        table.push(vec![label.clone(), dirs.to_string(), tally.files.to_string(), format_human(tally.bytes)]);
+        total.0 += dirs;
+        total.1.files += tally.files;
+        total.1.bytes += tally.bytes;
+
+        let mut entries = Vec::new();
+        collect_relative_paths(tree, "", &mut entries);
+        for (path, is_dir) in entries {
+            shared.entry(shown_name(&path, is_dir, raw_names)).or_default().push(label.clone());
+        }
+    }
+    table.push(vec![i18n::translate("total").to_string(), total.0.to_string(), total.1.files.to_string(), format_human(total.1.bytes)]);
+
+    let mut out = format!("{}\n{}\n\n{}", i18n::translate("Combined:"), format_table(&table), i18n::translate("Shared paths:"));
+    let shared: Vec<_> = shared.into_iter().filter(|(_, labels)| labels.len() > 1).collect();
+    if shared.is_empty() {
+        out.push_str(&format!("\n  {}", i18n::translate("none")));
+    }
+    for (path, labels) in shared.iter().take(MAX_SHARED_PATHS) {
+        out.push_str(&format!("\n  {} ({})", path, labels.join(", ")));
+    }
+    if shared.len() > MAX_SHARED_PATHS {
+        out.push_str(&format!("\n  {}", i18n::tr!("… {} more", shared.len() - MAX_SHARED_PATHS)));
+    }
+    out
+}
+
+/// Returns the number of directories below `item` and the number and bytes of its files.
+fn tally_tree(item: &Rc<TreeItemRefCell>) -> (u64, Tally) {
+    let mut dirs = 0;
+    let mut tally = Tally::default();
+    for child in &item.borrow().children {
+        if child.borrow().is_dir {
+            let (child_dirs, child_tally) = tally_tree(child);
+            dirs += 1 + child_dirs;
+            tally.files += child_tally.files;
+            tally.bytes += child_tally.bytes;
+        } else {
+            tally.add(child.borrow().meta.size);
+        }
+    }
+    (dirs, tally)
+}
+
+/// Adds a row with the files directly in `item` if there are any, then the rows of its directories.
+fn tally_reclaim(item: &Rc<TreeItemRefCell>, raw_names: bool, rows: &mut Vec<(String, Tally)>) {
+    let item_ref = item.borrow();
+    let mut tally = Tally::default();
+    for child in item_ref.children.iter().filter(|child| !child.borrow().is_dir) {
+        let meta = &child.borrow().meta;
+        tally.add(meta.allocated.unwrap_or(meta.size));
+    }
+    if tally.files > 0 {
+        rows.push((shown_name(&item_ref.path().to_string_lossy(), true, raw_names), tally));
+    }
+    for child in item_ref.children.iter().filter(|child| child.borrow().is_dir) {
+        tally_reclaim(child, raw_names, rows);
+    }
+}
+
+/// Returns the name of `text` like in the tree, with invisible characters escaped unless
+/// `raw_names`, before it's measured for the columns of a table.
+fn shown_name(text: &str, is_dir: bool, raw_names: bool) -> String {
+    let name = display_name(text, is_dir);
+    if raw_names {
+        name
+    } else {
+        escape_invisible(&name)
+    }
+}
+
+/// Returns the header of a table with the English `labels` translated to the current locale.
+fn header_row(labels: &[&str]) -> Vec<String> {
+    labels.iter().map(|label| i18n::translate(label).to_string()).collect()
+}
+
+/// Formats `rows` as a table with a left-aligned first column and right-aligned other columns,
+/// separated by two spaces.
+pub(crate) fn format_table(rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            let width = cell.chars().count();
+            match widths.get_mut(i) {
+                Some(max) => *max = (*max).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .enumerate()
+                .map(|(i, cell)| if i == 0 { format!("{:<w$}", cell, w = widths[i]) } else { format!("{:>w$}", cell, w = widths[i]) })
+                .collect();
+            cells.join("  ").trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::{Meta, TreeItem};
+
+    const DAY: Duration = Duration::from_secs(DAY_SECS);
+
+    #[test]
+    fn age_bucket_boundaries() {
+        let now = SystemTime::now();
+        assert_eq!(age_bucket(Some(now), now), 0);
+        assert_eq!(age_bucket(Some(now + DAY), now), 0);
+        assert_eq!(age_bucket(Some(now - 7 * DAY), now), 1);
+        assert_eq!(age_bucket(Some(now - 100 * DAY), now), 2);
+        assert_eq!(age_bucket(Some(now - 200 * DAY), now), 3);
+        assert_eq!(age_bucket(Some(now - 400 * DAY), now), 4);
+        assert_eq!(age_bucket(None, now), 4);
+    }
+
+    #[test]
+    fn age_report_per_top_level_directory() {
+        let now = SystemTime::now();
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let src = TreeItem::new(&root, "src".to_string(), true);
+        let nested = TreeItem::new(&src, "nested".to_string(), true);
+        for (parent, name, age_days, size) in [(&nested, "new.rs", 1, 100), (&src, "old.rs", 400, 2048), (&root, "README.md", 10, 50)] {
+            let file = TreeItem::new(parent, name.to_string(), false);
+            file.borrow_mut().meta.modified = Some(now - age_days * DAY);
+            file.borrow_mut().meta.size = size;
+        }
+
+        let result = to_stats_str(&root, StatsKind::Age, now, SymdirPolicy::AsLink, false);
+
+        let expected = [
+            "          < 1 week  < 1 month  < 6 months  < 1 year     ≥ 1 year",
+            "src/     1 (100 B)          -           -         -  1 (2.0 KiB)",
+            "(files)          -   1 (50 B)           -         -            -",
+            "total    1 (100 B)   1 (50 B)           -         -  1 (2.0 KiB)",
+        ];
+        assert_eq!(result, expected.join("\n"));
+    }
+
+    #[test]
+    fn age_report_applies_symdir_policy() {
+        let now = SystemTime::now();
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let link = TreeItem::new(&root, "docs".to_string(), false);
+        link.borrow_mut().meta = Meta { modified: Some(now), size: 10, is_symlink: true, is_symdir: true, ..Default::default() };
+
+        let as_link = to_stats_str(&root, StatsKind::Age, now, SymdirPolicy::AsLink, false);
+        let as_dir = to_stats_str(&root, StatsKind::Age, now, SymdirPolicy::AsDir, false);
+
+        assert!(as_link.lines().nth(1).unwrap().starts_with("(files)  1 (10 B)"));
+        assert!(as_dir.lines().nth(1).unwrap().starts_with("docs/  "));
+        assert!(as_dir.lines().nth(2).unwrap().starts_with("(files)  "));
+    }
+
+    #[test]
+    fn depth_report_with_histogram() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let src = TreeItem::new(&root, "src".to_string(), true);
+        let nested = TreeItem::new(&src, "nested".to_string(), true);
+        TreeItem::new(&nested, "deep.rs".to_string(), false);
+        TreeItem::new(&src, "lib.rs".to_string(), false);
+        TreeItem::new(&src, "main.rs".to_string(), false);
+        TreeItem::new(&root, "README.md".to_string(), false);
+
+        let result = to_stats_str(&root, StatsKind::Depth, SystemTime::now(), SymdirPolicy::AsLink, false);
+
+        let bar = |len| "█".repeat(len);
+        let expected = [
+            "deepest: root/src/nested/deep.rs (depth 3)".to_string(),
+            "average depth: 1.8".to_string(),
+            String::new(),
+            "depth  entries".to_string(),
+            format!("1            2  {}", bar(27)),
+            format!("2            3  {}", bar(40)),
+            format!("3            1  {}", bar(14)),
+        ];
+        assert_eq!(result, expected.join("\n"));
+        assert_eq!(to_stats_str(&TreeItem::new_top_level("root".to_string(), true), StatsKind::Depth, SystemTime::now(), SymdirPolicy::AsLink, false), "no entries");
+    }
+
+    #[test]
+    fn parse_passwd_maps_ids_to_names() {
+        let names = parse_passwd("# comment\nroot:x:0:0:root:/root:/bin/sh\nalice:x:1000:1000::/home/alice:/bin/bash\nbroken");
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[&0], "root");
+        assert_eq!(names[&1000], "alice");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn owner_report_per_user() {
+        use std::fs::File;
+        use std::io::Write;
+        use std::os::unix::fs::MetadataExt;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        File::create(temp_path.join("a.txt")).unwrap().write_all(b"hello").unwrap();
+        let uid = std::fs::metadata(temp_path).unwrap().uid();
+        let root = TreeItem::new_top_level(temp_path.to_str().unwrap().to_string(), true);
+        let dir = TreeItem::new(&root, "dir".to_string(), true);
+        let file = TreeItem::new(&root, "a.txt".to_string(), false);
+        let missing = TreeItem::new(&dir, "missing".to_string(), false);
+        file.borrow_mut().meta.size = 5;
+        missing.borrow_mut().meta.size = 1;
+        let names = HashMap::from([(uid, "alice".to_string())]);
+
+        let result = owner_report(&root, SymdirPolicy::AsLink, &names);
+
+        let expected = ["owner  files  size", "alice      1   5 B", "?          1   1 B", "total      2   6 B"];
+        assert_eq!(result, expected.join("\n"));
+    }
+
+    #[test]
+    fn mode_label_flags_risky_bits() {
+        assert_eq!(mode_label(0o644), "644");
+        assert_eq!(mode_label(0o4755), "4755 (setuid)");
+        assert_eq!(mode_label(0o6777), "6777 (setuid, setgid, world-writable)");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn perms_report_counts_modes() {
+        use crate::fs_utils::{traverse_fs, TraverseOptions};
+        use std::fs::{self, File, Permissions};
+        use std::os::unix::fs::PermissionsExt;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        fs::create_dir(temp_path.join("shared")).unwrap();
+        for name in ["a.txt", "shared/b.txt", "tool"] {
+            File::create(temp_path.join(name)).unwrap();
+        }
+        for (name, mode) in [("a.txt", 0o644), ("shared/b.txt", 0o644), ("shared", 0o777), ("tool", 0o4755)] {
+            fs::set_permissions(temp_path.join(name), Permissions::from_mode(mode)).unwrap();
+        }
+        std::os::unix::fs::symlink("a.txt", temp_path.join("link")).unwrap();
+        let root = TreeItem::new_top_level(temp_path.to_str().unwrap().to_string(), true);
+        traverse_fs(temp_path.to_str().unwrap(), &root, &TraverseOptions::default());
+        TreeItem::new(&root, "missing".to_string(), false);
+
+        let result = to_stats_str(&root, StatsKind::Perms, SystemTime::now(), SymdirPolicy::AsLink, false);
+
+        let expected = ["mode                  entries", "644                         2", "777 (world-writable)        1", "4755 (setuid)               1", "?                           1", "total                       5"];
+        assert_eq!(result, expected.join("\n"));
+    }
+
+    #[test]
+    fn reclaim_report_per_directory() {
+        let root = TreeItem::new_top_level(".".to_string(), true);
+        let logs = TreeItem::new(&root, "logs".to_string(), true);
+        let archive = TreeItem::new(&logs, "archive".to_string(), true);
+        TreeItem::new(&root, "empty".to_string(), true);
+        for (parent, name, size, allocated) in [(&archive, "a.log", 3 << 30, None), (&logs, "b.log", 2048, None), (&logs, "sparse.img", 1 << 30, Some(4096))] {
+            let meta = Meta { size, allocated, ..Default::default() };
+            TreeItem::new(parent, name.to_string(), false).borrow_mut().meta = meta;
+        }
+
+        let result = reclaim_report(&root, false);
+
+        let expected = ["directory        files     size", "./logs/archive/      1  3.0 GiB", "./logs/              2  6.0 KiB", "total                3  3.0 GiB"];
+        assert_eq!(result, expected.join("\n"));
+    }
+
+    #[test]
+    fn reports_escape_invisible_characters() {
+        let root = TreeItem::new_top_level(".".to_string(), true);
+        let dir = TreeItem::new(&root, "a\u{202E}b".to_string(), true);
+        TreeItem::new(&dir, "c.txt".to_string(), false);
+
+        let depth = to_stats_str(&root, StatsKind::Depth, SystemTime::now(), SymdirPolicy::AsLink, false);
+        let reclaim = reclaim_report(&root, false);
+
+        assert!(depth.starts_with("deepest: ./a<U+202E>b/c.txt (depth 2)"), "{}", depth);
+        let expected = ["directory      files  size", "./a<U+202E>b/      1   0 B", "total              1   0 B"];
+        assert_eq!(reclaim, expected.join("\n"));
+        assert!(reclaim_report(&root, true).contains("./a\u{202E}b/"));
+    }
+
+    #[test]
+    fn proportional_widths_add_up() {
+        assert_eq!(proportional_widths(&[1, 1, 1], 10), vec![4, 3, 3]);
+        assert_eq!(proportional_widths(&[97, 2, 1], 60), vec![58, 1, 1]);
+        assert_eq!(proportional_widths(&[0, 0], 60), vec![0, 0]);
+    }
+
+    #[test]
+    fn treemap_report_per_top_level_directory() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let data = TreeItem::new(&root, "data".to_string(), true);
+        let src = TreeItem::new(&root, "src".to_string(), true);
+        for (parent, name, size) in [(&data, "big.bin", 3000), (&src, "a.rs", 500), (&src, "b.rs", 500), (&root, "README.md", 0)] {
+            TreeItem::new(parent, name.to_string(), false).borrow_mut().meta.size = size;
+        }
+
+        let result = to_stats_str(&root, StatsKind::Treemap, SystemTime::now(), SymdirPolicy::AsLink, false);
+
+        let expected = [
+            format!("bytes  {}{}", "A".repeat(45), "B".repeat(15)),
+            format!("files  {}{}{}", "A".repeat(15), "B".repeat(30), "C".repeat(15)),
+            String::new(),
+            "A  data/    2.9 KiB  75%  1  25%".to_string(),
+            "B  src/      1000 B  25%  2  50%".to_string(),
+            "C  (files)      0 B   0%  1  25%".to_string(),
+        ];
+        assert_eq!(result, expected.join("\n"));
+    }
+
+    #[test]
+    fn combined_report_lists_totals_and_shared_paths() {
+        use crate::fs_utils::{traverse_fs, TraverseOptions};
+        use std::fs;
+        use tempfile::TempDir;
+
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let mut trees = Vec::new();
+        for (service, files) in [("api", vec![("src/main.rs", 10240), ("Cargo.toml", 0), ("\u{200B}", 0)]), ("worker", vec![("src/main.rs", 512), ("\u{200B}", 0)])] {
+            let root = TreeItem::new_top_level(service.to_string(), true);
+            for (path, size) in files {
+                let file = temp_dir.path().join(service).join(path);
+                fs::create_dir_all(file.parent().unwrap()).unwrap();
+                fs::write(&file, vec![b'x'; size]).unwrap();
+            }
+            traverse_fs(temp_dir.path().join(service).to_str().unwrap(), &root, &TraverseOptions::default());
+            trees.push(root);
+        }
+
+        // Call
+        let report = combined_report(&trees, false);
+
+        // Verify
+        let expected = "Combined:\n\
+                        directory  dirs  files      size\n\
+                        api/          1      3  10.0 KiB\n\
+                        worker/       1      2     512 B\n\
+                        total         2      5  10.5 KiB\n\
+                        \n\
+                        Shared paths:\n  \
+                        <U+200B> (api/, worker/)\n  \
+                        src/ (api/, worker/)\n  \
+                        src/main.rs (api/, worker/)";
+        assert_eq!(report, expected);
+    }
+
+    #[test]
+    fn format_table_aligns_columns() {
+        let rows = vec![vec!["a".to_string(), "1".to_string()], vec!["long".to_string(), "100".to_string()]];
+        assert_eq!(format_table(&rows), "a       1\nlong  100");
+    }
+
+    #[test]
+    fn labels_are_in_the_catalog() {
+        let labels = ["directory", "dirs", "files", "size", "total", "owner", "mode", "entries", "depth", "bytes", "no entries", "no files"];
+        let constants = AGE_BUCKETS.iter().map(|(_, label)| *label).chain([OLDEST_BUCKET, TOP_LEVEL_FILES, OTHER_ENTRIES]);
+        for label in labels.into_iter().chain(constants) {
+            assert_ne!(i18n::translate_to(i18n::Locale::De, label), label);
+        }
+    }
+}
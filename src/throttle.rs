@@ -0,0 +1,61 @@
+use crate::i18n;
+use std::cell::Cell;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Limits the rate of file system operations during traversal.
+#[derive(Debug, Clone)]
+pub(crate) struct Throttle {
+    interval: Duration,
+    next: Cell<Option<Instant>>,
+}
+
+impl Throttle {
+    pub(crate) fn new(ops_per_sec: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / ops_per_sec.max(1),
+            next: Cell::new(None),
+        }
+    }
+
+    /// Blocks until the operations acquired so far fit into the rate limit, then acquires
+    /// `ops` more operations.
+    pub(crate) fn acquire(&self, ops: u32) {
+        let now = Instant::now();
+        let next = self.next.get().map_or(now, |next| next.max(now));
+        if next > now {
+            thread::sleep(next - now);
+        }
+        self.next.set(Some(next + self.interval * ops));
+    }
+}
+
+/// Asks the OS to schedule this process' I/O only when no other process needs the disk.
+/// Uses `ionice` on Linux and does nothing on other platforms.
+pub(crate) fn lower_io_priority() {
+    #[cfg(target_os = "linux")]
+    {
+        let pid = std::process::id().to_string();
+        let status = std::process::Command::new("ionice").args(["-c", "3", "-p", &pid]).status();
+        if !matches!(status, Ok(status) if status.success()) {
+            eprintln!("{}", i18n::tr!("Unable to lower I/O priority with ionice, continuing with the default priority"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acquire_paces_operations() {
+        let throttle = Throttle::new(100);
+        let start = Instant::now();
+
+        throttle.acquire(1);
+        throttle.acquire(2);
+        throttle.acquire(1);
+
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+}
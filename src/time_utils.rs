@@ -0,0 +1,285 @@
+use crate::tree::Meta;
+use clap::ValueEnum;
+use std::env;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SECS_PER_MINUTE: u64 = 60;
+const SECS_PER_HOUR: u64 = 60 * SECS_PER_MINUTE;
+const SECS_PER_DAY: u64 = 24 * SECS_PER_HOUR;
+const SECS_PER_MONTH: u64 = 30 * SECS_PER_DAY;
+const SECS_PER_YEAR: u64 = 365 * SECS_PER_DAY;
+
+/// Which timestamp of an entry to show or filter by.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum TimeKind {
+    /// Time of the last modification
+    #[value(alias = "mtime")]
+    Modified,
+    /// Time of creation (birth time). Falls back to the modification time where the platform
+    /// or file system doesn't record it
+    #[value(alias = "btime")]
+    Created,
+    /// Time of the last access. Only as accurate as the file system's atime updates
+    #[value(alias = "atime")]
+    Accessed,
+}
+
+impl TimeKind {
+    pub(crate) fn of(&self, meta: &Meta) -> Option<SystemTime> {
+        match self {
+            TimeKind::Modified => meta.modified,
+            TimeKind::Created => meta.created.or(meta.modified),
+            TimeKind::Accessed => meta.accessed,
+        }
+    }
+}
+
+/// Which time `-D` shows for directories.
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum DirTimes {
+    /// The time of the directory itself, which changes when entries are added or removed
+    #[default]
+    Own,
+    /// The newest time of the files anywhere below it, e.g. when anything in it was last modified
+    Newest,
+}
+
+/// How `-D` formats timestamps.
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum TimeStyle {
+    /// Like 2024-03-31 14:05 (UTC)
+    #[default]
+    Iso,
+    /// Like 3 days ago
+    Relative,
+    /// The date order of the locale in LC_ALL, LC_TIME or LANG, like 31.03.2024 14:05 for de_DE (UTC)
+    Locale,
+}
+
+impl TimeStyle {
+    /// Formats `time` in this style. `now` is the reference of `Relative`.
+    pub(crate) fn format(self, time: SystemTime, now: SystemTime) -> String {
+        match self {
+            TimeStyle::Iso => format_time(time),
+            TimeStyle::Relative => format_relative(time, now),
+            TimeStyle::Locale => format_locale(time, locale_date_order()),
+        }
+    }
+
+    /// Returns the widest formatted time, so columns of times stay aligned.
+    pub(crate) fn width(self) -> usize {
+        match self {
+            // e.g. `59 minutes ago`
+            TimeStyle::Relative => 14,
+            TimeStyle::Iso | TimeStyle::Locale => 16,
+        }
+    }
+}
+
+/// Order of the date fields of a locale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DateOrder {
+    /// `2024-03-31`
+    YearMonthDay,
+    /// `03/31/2024`
+    MonthDayYear,
+    /// `31/03/2024`
+    DaySlashMonth,
+    /// `31.03.2024`
+    DayDotMonth,
+}
+
+/// Returns the date order of the locale from the environment, e.g. `de_DE.UTF-8`. Locales
+/// without a known order use `YearMonthDay`.
+fn locale_date_order() -> DateOrder {
+    static ORDER: OnceLock<DateOrder> = OnceLock::new();
+    *ORDER.get_or_init(|| {
+        let locale = ["LC_ALL", "LC_TIME", "LANG"].iter().filter_map(|var| env::var(var).ok()).find(|value| !value.is_empty());
+        date_order(&locale.unwrap_or_default())
+    })
+}
+
+fn date_order(locale: &str) -> DateOrder {
+    let locale = locale.split(['.', '@']).next().unwrap_or_default();
+    let language = locale.split(['_', '-']).next().unwrap_or_default();
+    match (language, locale) {
+        (_, "en_US" | "en_PH" | "en-US") => DateOrder::MonthDayYear,
+        ("en" | "fr" | "es" | "it" | "pt" | "el" | "ga" | "vi", _) => DateOrder::DaySlashMonth,
+        ("de" | "ru" | "pl" | "cs" | "sk" | "fi" | "nb" | "no" | "da" | "tr" | "uk" | "ro" | "hr" | "sl" | "et", _) => DateOrder::DayDotMonth,
+        _ => DateOrder::YearMonthDay,
+    }
+}
+
+fn format_locale(time: SystemTime, order: DateOrder) -> String {
+    let iso = format_time(time);
+    let (date, clock) = iso.split_once(' ').unwrap_or((&iso, ""));
+    let mut fields = date.splitn(3, '-');
+    let (year, month, day) = (fields.next().unwrap_or_default(), fields.next().unwrap_or_default(), fields.next().unwrap_or_default());
+    let date = match order {
+        DateOrder::YearMonthDay => return iso,
+        DateOrder::MonthDayYear => format!("{}/{}/{}", month, day, year),
+        DateOrder::DaySlashMonth => format!("{}/{}/{}", day, month, year),
+        DateOrder::DayDotMonth => format!("{}.{}.{}", day, month, year),
+    };
+    format!("{} {}", date, clock)
+}
+
+/// Formats `time` relative to `now`, like `3 days ago` or `in 2 hours`.
+fn format_relative(time: SystemTime, now: SystemTime) -> String {
+    let (secs, future) = match now.duration_since(time) {
+        Ok(age) => (age.as_secs(), false),
+        Err(err) => (err.duration().as_secs(), true),
+    };
+    if secs < SECS_PER_MINUTE {
+        return "just now".to_string();
+    }
+    let (count, unit) = [(SECS_PER_YEAR, "year"), (SECS_PER_MONTH, "month"), (SECS_PER_DAY, "day"), (SECS_PER_HOUR, "hour"), (SECS_PER_MINUTE, "minute")]
+        .into_iter()
+        .find(|(unit_secs, _)| secs >= *unit_secs)
+        .map(|(unit_secs, unit)| (secs / unit_secs, unit))
+        .unwrap_or((secs / SECS_PER_MINUTE, "minute"));
+    let plural = if count == 1 { "" } else { "s" };
+    if future {
+        format!("in {} {}{}", count, unit, plural)
+    } else {
+        format!("{} {}{} ago", count, unit, plural)
+    }
+}
+
+/// Parses durations like `90s`, `30m`, `12h`, `7d` or `2w`.
+pub(crate) fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let unit_start = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(unit_start);
+    let number: u64 = number.parse().map_err(|_| format!("'{}' doesn't start with a number", value))?;
+
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => SECS_PER_MINUTE,
+        "h" => SECS_PER_HOUR,
+        "d" => SECS_PER_DAY,
+        "w" => 7 * SECS_PER_DAY,
+        _ => return Err(format!("unknown unit '{}', expected one of s, m, h, d, w", unit)),
+    };
+    Ok(Duration::from_secs(number * unit_secs))
+}
+
+/// Parses a date like `2024-03-31` as midnight UTC.
+pub(crate) fn parse_date(value: &str) -> Result<SystemTime, String> {
+    let invalid = || format!("'{}' isn't a date like 2024-03-31", value);
+
+    let mut parts = value.trim().splitn(3, '-');
+    let year: i64 = parts.next().and_then(|p| p.parse().ok()).ok_or_else(invalid)?;
+    let month: u32 = parts.next().and_then(|p| p.parse().ok()).ok_or_else(invalid)?;
+    let day: u32 = parts.next().and_then(|p| p.parse().ok()).ok_or_else(invalid)?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+
+    let secs = days_from_civil(year, month, day) * SECS_PER_DAY as i64;
+    if secs >= 0 {
+        Ok(UNIX_EPOCH + Duration::from_secs(secs as u64))
+    } else {
+        Ok(UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()))
+    }
+}
+
+/// Formats `time` as `2024-03-31 14:05` (UTC).
+pub(crate) fn format_time(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(since_epoch) => since_epoch.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    };
+    let days = secs.div_euclid(SECS_PER_DAY as i64);
+    let secs_of_day = secs.rem_euclid(SECS_PER_DAY as i64) as u64;
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / SECS_PER_HOUR,
+        secs_of_day % SECS_PER_HOUR / SECS_PER_MINUTE
+    )
+}
+
+/// Returns the number of days since 1970-01-01 for a date in the proleptic Gregorian calendar.
+/// See http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_from_march = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Inverse of `days_from_civil`.
+/// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * 3600)));
+        assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(7 * 86400)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * 86400)));
+        assert!(parse_duration("7").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("7y").is_err());
+    }
+
+    #[test]
+    fn time_styles() {
+        let now = parse_date("2024-03-31").unwrap();
+        assert_eq!(format_relative(now - Duration::from_secs(30), now), "just now");
+        assert_eq!(format_relative(now - Duration::from_secs(59 * 60), now), "59 minutes ago");
+        assert_eq!(format_relative(now - Duration::from_secs(3 * SECS_PER_DAY + 5), now), "3 days ago");
+        assert_eq!(format_relative(now - Duration::from_secs(SECS_PER_YEAR), now), "1 year ago");
+        assert_eq!(format_relative(now + Duration::from_secs(2 * SECS_PER_HOUR), now), "in 2 hours");
+        assert!(format_relative(now - Duration::from_secs(59 * 60), now).chars().count() <= TimeStyle::Relative.width());
+
+        let time = now + Duration::from_secs(14 * 3600 + 5 * 60);
+        assert_eq!(format_locale(time, date_order("de_DE.UTF-8")), "31.03.2024 14:05");
+        assert_eq!(format_locale(time, date_order("en_US.UTF-8")), "03/31/2024 14:05");
+        assert_eq!(format_locale(time, date_order("en_GB")), "31/03/2024 14:05");
+        assert_eq!(format_locale(time, date_order("ja_JP.UTF-8")), "2024-03-31 14:05");
+        assert_eq!(format_locale(time, date_order("")), "2024-03-31 14:05");
+    }
+
+    #[test]
+    fn parse_date_and_format_time() {
+        let date = parse_date("2024-02-29").unwrap();
+        assert_eq!(date.duration_since(UNIX_EPOCH).unwrap().as_secs(), 1709164800);
+        assert_eq!(format_time(date + Duration::from_secs(14 * 3600 + 5 * 60)), "2024-02-29 14:05");
+        assert_eq!(format_time(parse_date("1969-12-31").unwrap()), "1969-12-31 00:00");
+        assert!(parse_date("2024-13-01").is_err());
+        assert!(parse_date("yesterday").is_err());
+    }
+
+    #[test]
+    fn created_falls_back_to_modified() {
+        let modified = UNIX_EPOCH + Duration::from_secs(10);
+        let meta = Meta { modified: Some(modified), ..Default::default() };
+        assert_eq!(TimeKind::Created.of(&meta), Some(modified));
+    }
+}
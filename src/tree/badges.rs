@@ -0,0 +1,88 @@
+use glob::Pattern;
+
+/// Built-in badges of `--badges`: a glob for the file name, the badge and the index of its color
+/// in the palette of the `--theme`.
+const BUILTIN_BADGES: [(&str, &str, usize); 14] = [
+    ("Dockerfile*", "docker", 2),
+    ("*.dockerfile", "docker", 2),
+    ("docker-compose.y*ml", "docker", 2),
+    ("Makefile", "make", 4),
+    ("GNUmakefile", "make", 4),
+    ("LICEN[CS]E*", "license", 1),
+    ("COPYING*", "license", 1),
+    ("README*", "readme", 3),
+    ("Cargo.toml", "rust", 5),
+    ("package.json", "npm", 5),
+    ("pyproject.toml", "python", 5),
+    ("go.mod", "go", 5),
+    ("pom.xml", "maven", 5),
+    (".gitignore", "git", 0),
+];
+
+/// Palette index of the color of badges from `.ftree.toml`.
+const CUSTOM_BADGE_COLOR: usize = 0;
+
+/// A rule of `Badges`: files whose names match `pattern` get `badge`.
+#[derive(Debug, Clone)]
+struct BadgeRule {
+    pattern: Pattern,
+    badge: String,
+    color: usize,
+}
+
+///
+/// Short tags for well-known files, e.g. `Dockerfile [docker]` or `Cargo.toml [rust]`, so the
+/// anatomy of a project is obvious at a glance. Custom rules from the `badges` of `.ftree.toml`
+/// come first, so they can override the built-in ones:
+///
+/// ```toml
+/// badges = ["Justfile=just", "*.tf=terraform", "README*="]
+/// ```
+///
+/// An empty badge, like for `README*` above, turns the badge off.
+///
+#[derive(Debug, Clone)]
+pub(crate) struct Badges {
+    rules: Vec<BadgeRule>,
+}
+
+impl Badges {
+    /// Returns the built-in rules after `custom` rules like `Justfile=just`. Fails on rules
+    /// without `=` or with an invalid glob.
+    pub(crate) fn new(custom: &[String]) -> Result<Badges, String> {
+        let mut rules = Vec::new();
+        for rule in custom {
+            let (glob, badge) = rule.split_once('=').ok_or_else(|| format!("expected NAME=BADGE, got '{}'", rule))?;
+            let pattern = Pattern::new(glob.trim()).map_err(|err| format!("invalid pattern '{}': {}", glob, err))?;
+            rules.push(BadgeRule { pattern, badge: badge.trim().to_string(), color: CUSTOM_BADGE_COLOR });
+        }
+        for (glob, badge, color) in BUILTIN_BADGES {
+            rules.push(BadgeRule { pattern: Pattern::new(glob).unwrap(), badge: badge.to_string(), color });
+        }
+        Ok(Badges { rules })
+    }
+
+    /// Returns the badge of the file `name` with the palette index of its color, if it has one.
+    pub(crate) fn badge_for(&self, name: &str) -> Option<(&str, usize)> {
+        let rule = self.rules.iter().find(|rule| rule.pattern.matches(name))?;
+        (!rule.badge.is_empty()).then_some((rule.badge.as_str(), rule.color))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_rules_override_builtin_ones() {
+        let badges = Badges::new(&["Justfile=just".to_string(), "README*=".to_string(), "*.toml = config".to_string()]).unwrap();
+
+        assert_eq!(badges.badge_for("Dockerfile.dev"), Some(("docker", 2)));
+        assert_eq!(badges.badge_for("LICENSE-MIT"), Some(("license", 1)));
+        assert_eq!(badges.badge_for("Justfile"), Some(("just", CUSTOM_BADGE_COLOR)));
+        assert_eq!(badges.badge_for("Cargo.toml"), Some(("config", CUSTOM_BADGE_COLOR)));
+        assert_eq!(badges.badge_for("README.md"), None);
+        assert_eq!(badges.badge_for("main.rs"), None);
+        assert!(Badges::new(&["Justfile".to_string()]).unwrap_err().contains("NAME=BADGE"));
+    }
+}
@@ -0,0 +1,118 @@
+use crate::tree::{display_name, escape_invisible, RenderOptions, TreeItem, TreeItemRefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+///
+/// Lists the tree breadth-first, i.e. all entries of depth 1, then all entries of depth 2 etc.
+/// As the rows of a directory are no longer below it, every row shows its full path:
+///
+/// ```text
+/// ./
+/// ./src/
+/// ./README.md
+/// ./src/main.rs
+/// ```
+///
+/// The paths are rewritten by `options.path_prefixes` and end with the suffix of `decorate`, e.g.
+/// `options.dir_suffix` or the `@` of `-F` for symbolic links. The leading cells and columns of
+/// `decorate_tree`, e.g. the size of `-s` and the time of `-D`, come before the path like in the
+/// tree.
+///
+pub(crate) fn to_bfs_str(root: &Rc<TreeItemRefCell>, options: &RenderOptions) -> String {
+    let mut rows: Vec<String> = Vec::new();
+    let mut queue: VecDeque<Rc<TreeItemRefCell>> = VecDeque::new();
+    queue.push_back(Rc::clone(root));
+
+    while let Some(item) = queue.pop_front() {
+        let item = item.borrow();
+        rows.push(path_row(&item, options));
+        queue.extend(item.children.iter().map(Rc::clone));
+    }
+    rows.join("\n")
+}
+
+fn path_row(item: &TreeItem, options: &RenderOptions) -> String {
+    let mut row: String = item.leading.iter().chain(&item.columns).map(|cell| format!("{} ", cell)).collect();
+    let path = options.rewrite_path(&display_name(&item.path().to_string_lossy(), item.is_dir));
+    match &item.suffix {
+        // Like in the tree, the suffix of `decorate` replaces the `/` of directories
+        Some(suffix) => {
+            row.push_str(if item.is_dir { path.strip_suffix('/').unwrap_or(&path) } else { &path });
+            row.push_str(suffix);
+        }
+        None => row.push_str(&path),
+    }
+    // Annotations may quote names and file contents, e.g. link targets and --first-line
+    for annotation in &item.annotations {
+        row.push(' ');
+        row.push_str(annotation);
+    }
+    if options.raw_names {
+        row
+    } else {
+        escape_invisible(&row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::{decorate_tree, Meta};
+
+    #[test]
+    fn to_bfs_str_nested_structure() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let folder = TreeItem::new(&root, "folder".to_string(), true);
+        TreeItem::new(&folder, "file_in_folder.txt".to_string(), false);
+        TreeItem::new(&root, "file_in_root.txt".to_string(), false);
+
+        let result = to_bfs_str(&root, &RenderOptions::default());
+
+        let expected = "root/\nroot/folder/\nroot/file_in_root.txt\nroot/folder/file_in_folder.txt";
+        assert_eq!(result, expected);
+
+        let options = RenderOptions { path_prefixes: vec![("root/".to_string(), "./".to_string())], ..Default::default() };
+        let result = to_bfs_str(&root, &options);
+        assert_eq!(result, "./\n./folder/\n./file_in_root.txt\n./folder/file_in_folder.txt");
+    }
+
+    #[test]
+    fn to_bfs_str_shows_leading_cells_and_columns() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let file = TreeItem::new(&root, "a.txt".to_string(), false);
+        root.borrow_mut().leading = vec!["DIR".to_string(), "     ".to_string()];
+        file.borrow_mut().leading = vec!["   ".to_string(), "1.2 K".to_string()];
+        file.borrow_mut().columns.push("[2024-03-31 14:05]".to_string());
+
+        let result = to_bfs_str(&root, &RenderOptions::default());
+
+        assert_eq!(result, "DIR       root/\n    1.2 K [2024-03-31 14:05] root/a.txt");
+    }
+
+    #[test]
+    fn to_bfs_str_uses_the_suffixes_of_the_options() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        TreeItem::new(&root, "folder".to_string(), true);
+        let link = TreeItem::new(&root, "link".to_string(), false);
+        link.borrow_mut().meta = Meta { is_symlink: true, ..Default::default() };
+        let options = RenderOptions { dir_suffix: Some(String::new()), symlink_indicator: Some("@".to_string()), ..Default::default() };
+        decorate_tree(&root, &options);
+
+        let result = to_bfs_str(&root, &options);
+
+        assert_eq!(result, "root\nroot/folder\nroot/link@");
+    }
+
+    #[test]
+    fn to_bfs_str_escapes_names_and_annotations() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let file = TreeItem::new(&root, "a\u{202E}b.txt".to_string(), false);
+        file.borrow_mut().annotations.push("\"x\u{202E}y\"".to_string());
+
+        let result = to_bfs_str(&root, &RenderOptions::default());
+        assert_eq!(result, "root/\nroot/a<U+202E>b.txt \"x<U+202E>y\"");
+
+        let result = to_bfs_str(&root, &RenderOptions { raw_names: true, ..Default::default() });
+        assert_eq!(result, "root/\nroot/a\u{202E}b.txt \"x\u{202E}y\"");
+    }
+}
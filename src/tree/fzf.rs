@@ -0,0 +1,85 @@
+use crate::tree::{RenderOptions, TreeItem};
+
+/// Separates the display column from the path column. Matches `fzf --delimiter '\t'`.
+pub(crate) const FZF_DELIMITER: char = '\t';
+
+/// Replaces `FZF_DELIMITER` in the display column, so it stays a single field.
+const ESCAPED_DELIMITER: &str = "<U+0009>";
+
+/// Terminates each record, so paths containing newlines survive. Matches `fzf --read0`.
+pub(crate) const FZF_TERMINATOR: char = '\0';
+
+///
+/// Builds a flat, NUL-terminated list with one record per item. Each record holds two
+/// tab-separated columns: the item's row in the rendered tree and its path:
+///
+/// ```text
+/// ./\t./\0
+///  ├── src/\t./src\0
+///  │   └── main.rs\t./src/main.rs\0
+///  └── README.md\t./README.md\0
+/// ```
+///
+/// Intended to be piped into `fzf --read0 --delimiter '\t' --with-nth 1`, so the tree is
+/// searchable, and `cut -f2-` to get the path of the selected line. Tabs in the row are
+/// escaped, so the path is everything after the first tab, even if it contains tabs itself.
+/// The paths are rewritten by `options.path_prefixes`.
+///
+pub(crate) fn to_fzf_str(item: &TreeItem, options: &RenderOptions) -> String {
+    let mut out = String::new();
+    push_records(&mut out, item, options, false);
+    out
+}
+
+fn push_records(out: &mut String, item: &TreeItem, options: &RenderOptions, prefix_self: bool) {
+    let prefix = if prefix_self { item.row_prefix() } else { String::new() };
+    out.push_str(&format!("{}{}", prefix, item).replace(FZF_DELIMITER, ESCAPED_DELIMITER));
+    out.push(FZF_DELIMITER);
+    out.push_str(&options.rewrite_path(&item.path().to_string_lossy()));
+    out.push(FZF_TERMINATOR);
+
+    for child in &item.children {
+        push_records(out, &child.borrow(), options, true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn to_fzf_str_nested_structure() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let folder = TreeItem::new(&root, "folder".to_string(), true);
+        TreeItem::new(&folder, "file_in_folder.txt".to_string(), false);
+        TreeItem::new(&root, "file_in_root.txt".to_string(), false);
+
+        let result = to_fzf_str(&root.borrow(), &RenderOptions::default());
+        let records: Vec<&str> = result.split(FZF_TERMINATOR).collect();
+
+        let folder_path = Path::new("root").join("folder");
+        let nested_path = folder_path.join("file_in_folder.txt");
+        let root_file_path = Path::new("root").join("file_in_root.txt");
+        assert_eq!(records, vec![
+            "root/\troot".to_string(),
+            format!(" ├── folder/\t{}", folder_path.display()),
+            format!(" │   └── file_in_folder.txt\t{}", nested_path.display()),
+            format!(" └── file_in_root.txt\t{}", root_file_path.display()),
+            String::new(),
+        ]);
+    }
+
+    #[test]
+    fn to_fzf_str_keeps_tabs_out_of_the_row() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        TreeItem::new(&root, "a\tb".to_string(), false);
+
+        let result = to_fzf_str(&root.borrow(), &RenderOptions::default());
+        let record = result.split(FZF_TERMINATOR).nth(1).unwrap();
+
+        let (row, path) = record.split_once(FZF_DELIMITER).unwrap();
+        assert_eq!(row, " └── a<U+0009>b");
+        assert_eq!(path, Path::new("root").join("a\tb").to_str().unwrap());
+    }
+}
@@ -0,0 +1,103 @@
+use crate::tree::{display_name, TreeItem};
+
+///
+/// Serializes the tree into a JSON document like:
+///
+/// ```text
+/// {"name":"./","path":".","type":"dir","children":[
+///   {"name":"src/","path":"./src","type":"dir","children":null},
+///   {"name":"README.md","path":"./README.md","type":"file"}
+/// ]}
+/// ```
+///
+/// Directories at `max_depth` were not read, so their `children` are `null` rather than `[]`.
+/// Paths always use `/` as separator.
+///
+pub(crate) fn to_json_str(item: &TreeItem, max_depth: Option<usize>) -> String {
+    let mut out = String::new();
+    push_item(&mut out, item, max_depth, 0);
+    out
+}
+
+fn push_item(out: &mut String, item: &TreeItem, max_depth: Option<usize>, depth: usize) {
+    out.push_str("{\"name\":");
+    push_str_value(out, &display_name(&item.text, item.is_dir));
+    out.push_str(",\"path\":");
+    push_str_value(out, &item.path().to_string_lossy().replace('\\', "/"));
+    out.push_str(",\"type\":");
+    push_str_value(out, if item.is_dir { "dir" } else { "file" });
+
+    if item.is_dir {
+        out.push_str(",\"children\":");
+        if max_depth == Some(depth) {
+            out.push_str("null");
+        } else {
+            out.push('[');
+            for (i, child) in item.children.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                push_item(out, &child.borrow(), max_depth, depth + 1);
+            }
+            out.push(']');
+        }
+    }
+    out.push('}');
+}
+
+/// Appends `value` as a quoted JSON string, escaping quotes, backslashes and control characters.
+pub(crate) fn push_str_value(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_json_str_nested_structure() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let folder = TreeItem::new(&root, "folder".to_string(), true);
+        TreeItem::new(&folder, "file.txt".to_string(), false);
+
+        let result = to_json_str(&root.borrow(), None);
+        let expected = concat!(
+            r#"{"name":"root/","path":"root","type":"dir","children":["#,
+            r#"{"name":"folder/","path":"root/folder","type":"dir","children":["#,
+            r#"{"name":"file.txt","path":"root/folder/file.txt","type":"file"}]}]}"#
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn to_json_str_marks_unread_directories() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        TreeItem::new(&root, "folder".to_string(), true);
+
+        let result = to_json_str(&root.borrow(), Some(1));
+        let expected = concat!(
+            r#"{"name":"root/","path":"root","type":"dir","children":["#,
+            r#"{"name":"folder/","path":"root/folder","type":"dir","children":null}]}"#
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn push_str_value_escapes() {
+        let mut out = String::new();
+        push_str_value(&mut out, "a\"b\\c\nd\u{1}");
+        assert_eq!(out, r#""a\"b\\c\nd\u0001""#);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::i18n;
+use crate::size_utils::SizeFormat;
+use crate::time_utils::{parse_date, TimeKind, TimeStyle};
+use std::time::Duration;
+use crate::tree::RenderOptions;
+
+/// A marker that may appear in the output, with an example and its meaning.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Marker {
+    /// Directories, with the suffix after their names
+    Dir(String),
+    /// Symbolic links, with their indicator
+    Symlink(String),
+    /// Executable files, with their indicator
+    Executable(String),
+    Size(SizeFormat),
+    Time(TimeKind, TimeStyle),
+    /// Annotations of `--diff-cache`
+    Changes,
+    /// Annotations of `--contains`
+    Matches,
+    /// Annotations of `--perm-anomalies`
+    PermAnomalies,
+    /// Annotations of `--clones`
+    Clones,
+    /// Annotations of `--sparse`
+    Sparse,
+    /// Annotations of `--detect-case-conflicts`
+    CaseConflicts,
+    /// Annotations of `--lint-names`
+    NameProblems,
+    /// Annotations of `--badges`
+    Badges,
+    /// Annotations of `--codeowners`
+    Owners,
+    /// Annotations of `--as-user`, with the user's name
+    Access(String),
+    /// Placeholder rows of `--count-hidden`
+    Hidden,
+    /// Directories that aren't read, e.g. build output with `--smart`
+    Collapsed,
+}
+
+impl Marker {
+    /// Returns the markers produced by `decorate` with `options`.
+    pub(crate) fn active(options: &RenderOptions) -> Vec<Marker> {
+        let mut markers = vec![Marker::Dir(options.dir_suffix.clone().unwrap_or_else(|| "/".to_string()))];
+        markers.extend(options.symlink_indicator.clone().map(Marker::Symlink));
+        markers.extend(options.exec_indicator.clone().map(Marker::Executable));
+        markers.extend(options.size.map(Marker::Size));
+        markers.extend(options.time.map(|kind| Marker::Time(kind, options.time_style)));
+        if options.sparse {
+            markers.push(Marker::Sparse);
+        }
+        markers
+    }
+
+    /// Returns the examples of the marker with their meanings in the current locale.
+    fn entries(&self) -> Vec<(String, String)> {
+        let named = |suffix: &str, meaning: &str| vec![(format!("name{}", suffix), i18n::translate(meaning).to_string())];
+        let entries = match self {
+            Marker::Dir(suffix) => return named(suffix, "directory"),
+            Marker::Symlink(indicator) => return named(indicator, "symbolic link"),
+            Marker::Executable(indicator) => return named(indicator, "executable file"),
+            Marker::Size(SizeFormat::Bytes) => vec![("12,345", "file size in bytes")],
+            Marker::Size(SizeFormat::Human) => vec![("1.5 KiB", "file size in binary units")],
+            Marker::Time(kind, style) => {
+                let meaning = match kind {
+                    TimeKind::Modified => "last modification",
+                    TimeKind::Created => "creation, or last modification if unknown",
+                    TimeKind::Accessed => "last access",
+                };
+                let time = parse_date("2024-03-31").unwrap() + Duration::from_secs(14 * 3600 + 5 * 60);
+                let example = format!("[{}]", style.format(time, time + Duration::from_secs(3 * 24 * 3600)));
+                let zone = if *style == TimeStyle::Relative { "" } else { " (UTC)" };
+                return vec![(example, format!("{}{}", i18n::translate(meaning), zone))];
+            }
+            Marker::Changes => vec![
+                ("[new]", "added since the last run"),
+                ("[removed]", "removed since the last run"),
+                ("[resized a → b B]", "size changed since the last run"),
+            ],
+            Marker::Matches => vec![("(N matches)", "lines containing the searched text")],
+            Marker::Hidden => vec![("… 7 hidden items", "entries hidden by filters")],
+            Marker::Collapsed => vec![("[collapsed]", "directory not read, e.g. build output")],
+            Marker::Sparse => vec![("[sparse 2%]", "sparse file, with the share of its size allocated on disk")],
+            Marker::PermAnomalies => vec![("[perm 777, mostly 644]", "permissions differ from most files in the directory")],
+            Marker::Clones => vec![("[clone 40%]", "share of the data shared with other files, e.g. by reflinks")],
+            Marker::CaseConflicts => vec![("[case conflict with Readme.md]", "name differs from a sibling's only by case")],
+            Marker::NameProblems => vec![("[name: trailing dot]", "name that causes trouble on some platform")],
+            Marker::Badges => vec![("[docker]", "well-known file, e.g. a Dockerfile")],
+            Marker::Access(user) => {
+                let meanings = [
+                    ("read", "file the user can't read"),
+                    ("list", "directory the user can enter, but not list"),
+                    ("enter", "directory the user can't enter"),
+                    ("reach", "a directory above blocks the user"),
+                ];
+                return meanings.iter().map(|(action, meaning)| (format!("[{} can't {}]", user, action), i18n::translate(meaning).to_string())).collect();
+            }
+            Marker::Owners => vec![
+                ("@team", "owners from CODEOWNERS, where they differ from the directory's"),
+                ("[unowned]", "CODEOWNERS rule without owners"),
+            ],
+        };
+        entries.into_iter().map(|(example, meaning)| (example.to_string(), i18n::translate(meaning).to_string())).collect()
+    }
+}
+
+///
+/// Renders a legend explaining `markers`, e.g.:
+///
+/// ```text
+/// Legend:
+///   name/     directory
+///   12,345    file size in bytes
+/// ```
+///
+pub(crate) fn to_legend_str(markers: &[Marker]) -> String {
+    let entries: Vec<_> = markers.iter().flat_map(Marker::entries).collect();
+    let width = entries.iter().map(|(example, _)| example.chars().count()).max().unwrap_or(0);
+
+    let mut rows = vec![i18n::translate("Legend:").to_string()];
+    for (example, meaning) in entries {
+        rows.push(format!("  {:<width$}  {}", example, meaning, width = width));
+    }
+    rows.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn active_markers_follow_render_options() {
+        let options = RenderOptions { size: Some(SizeFormat::Human), ..Default::default() };
+
+        assert_eq!(Marker::active(&options), vec![Marker::Dir("/".to_string()), Marker::Size(SizeFormat::Human)]);
+    }
+
+    #[test]
+    fn to_legend_str_aligns_meanings() {
+        let result = to_legend_str(&[Marker::Dir("/".to_string()), Marker::Size(SizeFormat::Bytes)]);
+
+        assert_eq!(result, "Legend:\n  name/   directory\n  12,345  file size in bytes");
+    }
+}
@@ -1,7 +1,27 @@
+pub(crate) mod badges;
+pub(crate) mod bfs;
+pub(crate) mod fzf;
+pub(crate) mod json;
+pub(crate) mod legend;
+pub(crate) mod parse;
+pub(crate) mod stream;
+
 use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::env;
 use std::fmt::{Display, Formatter};
+use std::io::{self, Write};
 use std::ops::Deref;
+use std::path::{Path, PathBuf};
 use std::rc::{Rc, Weak};
+use std::time::SystemTime;
+use clap::ValueEnum;
+use crate::fs_utils;
+use crate::i18n;
+use crate::size_utils::SizeFormat;
+use crate::time_utils::{DirTimes, TimeKind, TimeStyle};
+use crate::tree::badges::Badges;
 
 
 const LVL_SUFFIX: &str = "├──";
@@ -9,14 +29,249 @@ const LVL_SUFFIX_LAST: &str = "└──";
 const PARENT_IS_NOT_LAST: &str = "│  ";
 const PARENT_IS_LAST: &str = "   ";
 
+/// ANSI color codes of `--depth-colors` on dark backgrounds, cycled through by nesting level:
+/// yellow, magenta, blue, cyan, green and red.
+const DEPTH_COLORS: [&str; 6] = ["33", "35", "34", "36", "32", "31"];
+
+/// ANSI color codes of `--depth-colors` on light backgrounds, where yellow and cyan are hard to
+/// read: blue, magenta, red, green, gray and black.
+const DEPTH_COLORS_LIGHT: [&str; 6] = ["34", "35", "31", "32", "90", "30"];
+
+/// Colors for the background of the terminal, of `--theme`.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum Theme {
+    Dark,
+    Light,
+}
+
+impl Theme {
+    /// Returns the theme for the background the terminal reports in `COLORFGBG`, or `Dark` if
+    /// it doesn't.
+    pub(crate) fn detect() -> Theme {
+        env::var("COLORFGBG").ok().and_then(|value| Theme::from_colorfgbg(&value)).unwrap_or(Theme::Dark)
+    }
+
+    /// Reads the background of `COLORFGBG`, e.g. `15;0` or `0;default;15`: the last field is
+    /// the ANSI color index of the background, where 7 (white) and 9 to 15 (bright colors)
+    /// are light.
+    fn from_colorfgbg(value: &str) -> Option<Theme> {
+        let background: u8 = value.rsplit(';').next()?.parse().ok()?;
+        Some(if background == 7 || (9..=15).contains(&background) { Theme::Light } else { Theme::Dark })
+    }
+
+    fn depth_colors(self) -> &'static [&'static str] {
+        match self {
+            Theme::Dark => &DEPTH_COLORS,
+            Theme::Light => &DEPTH_COLORS_LIGHT,
+        }
+    }
+}
+
+/// Returns whether ANSI colors may be written, see `colors_allowed`.
+pub(crate) fn colors_supported() -> bool {
+    colors_allowed(&|name| env::var(name).ok(), cfg!(windows))
+}
+
+///
+/// Returns whether ANSI colors may be written with the environment variables that `var` reads:
+/// not if `NO_COLOR` is set to anything but an empty string (see no-color.org). On Windows, only
+/// in terminals known to interpret them, as the legacy console prints them as text: Windows
+/// Terminal (`WT_SESSION`), ConEmu (`ConEmuANSI=ON`) and terminals that set `TERM`, like mintty.
+///
+fn colors_allowed(var: &dyn Fn(&str) -> Option<String>, windows: bool) -> bool {
+    if var("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        return false;
+    }
+    !windows || var("WT_SESSION").is_some() || var("ConEmuANSI").is_some_and(|value| value == "ON") || var("TERM").is_some()
+}
+
+/// Invisible characters that can make a name look like another one, e.g. `U+202E` reverses the
+/// rest of the name: the format characters of Unicode (category Cf, as of Unicode 15), like
+/// bidirectional formatting characters, zero-width characters, the soft hyphen and the BOM.
+const INVISIBLE_CHARS: [(char, char); 21] = [
+    ('\u{00AD}', '\u{00AD}'),
+    ('\u{0600}', '\u{0605}'),
+    ('\u{061C}', '\u{061C}'),
+    ('\u{06DD}', '\u{06DD}'),
+    ('\u{070F}', '\u{070F}'),
+    ('\u{0890}', '\u{0891}'),
+    ('\u{08E2}', '\u{08E2}'),
+    ('\u{180E}', '\u{180E}'),
+    ('\u{200B}', '\u{200F}'),
+    ('\u{202A}', '\u{202E}'),
+    ('\u{2060}', '\u{2064}'),
+    ('\u{2066}', '\u{206F}'),
+    ('\u{FEFF}', '\u{FEFF}'),
+    ('\u{FFF9}', '\u{FFFB}'),
+    ('\u{110BD}', '\u{110BD}'),
+    ('\u{110CD}', '\u{110CD}'),
+    ('\u{13430}', '\u{1343F}'),
+    ('\u{1BCA0}', '\u{1BCA3}'),
+    ('\u{1D173}', '\u{1D17A}'),
+    ('\u{E0001}', '\u{E0001}'),
+    ('\u{E0020}', '\u{E007F}'),
+];
+
+/// Files smaller than this aren't flagged as sparse, as small files may be stored inline or
+/// with their metadata and then have no blocks of their own.
+const SPARSE_MIN_SIZE: u64 = 64 * 1024;
+
 pub(crate) type TreeItemRefCell = RefCell<TreeItem>;
 
+/// File system metadata of an item, as far as it's needed for filtering and rendering.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct Meta {
+    /// Size in bytes for files, 0 for directories.
+    pub(crate) size: u64,
+    /// Bytes allocated on disk, if the platform reports it. Much less than `size` for sparse files.
+    pub(crate) allocated: Option<u64>,
+    pub(crate) modified: Option<SystemTime>,
+    /// Birth time, if the platform and file system record it.
+    pub(crate) created: Option<SystemTime>,
+    /// Time of the last access. Many systems update it lazily or not at all (`noatime`).
+    pub(crate) accessed: Option<SystemTime>,
+    /// Whether the entry itself is a symbolic link.
+    pub(crate) is_symlink: bool,
+    /// Whether the entry is a symbolic link to a directory. Such links aren't followed, so the
+    /// item is a file without children, see `SymdirPolicy` for how it's shown.
+    pub(crate) is_symdir: bool,
+    /// Whether the entry is a file that anyone may execute. Always false on platforms without
+    /// Unix permissions.
+    pub(crate) is_executable: bool,
+    /// The kind of the entry if it's neither a regular file, a directory nor a symbolic link.
+    pub(crate) special: Option<SpecialFile>,
+}
+
+/// Kinds of special files, only found on platforms with Unix file types.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SpecialFile {
+    Socket,
+    Fifo,
+    BlockDevice,
+    CharDevice,
+}
+
+/// Returns the `--type-column` code of an item, e.g. `DIR` or `LNK`.
+fn type_code(is_dir: bool, meta: &Meta) -> &'static str {
+    match meta.special {
+        _ if is_dir => "DIR",
+        _ if meta.is_symlink => "LNK",
+        Some(SpecialFile::Socket) => "SOC",
+        Some(SpecialFile::Fifo) => "FIF",
+        Some(SpecialFile::BlockDevice) => "BLK",
+        Some(SpecialFile::CharDevice) => "CHR",
+        None => "FIL",
+    }
+}
+
+/// How symbolic links to directories are shown and counted. They're never followed either way.
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum SymdirPolicy {
+    /// Like directories: with the directory suffix, and as directories in stats
+    AsDir,
+    /// Like other symbolic links: with the symlink indicator, and as files in stats
+    #[default]
+    AsLink,
+}
+
+/// Options that control what is shown for each item, independent of which items are visited.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct RenderOptions {
+    /// Show this timestamp before each name, like `tree -D`.
+    pub(crate) time: Option<TimeKind>,
+    /// How the timestamps are formatted.
+    pub(crate) time_style: TimeStyle,
+    /// Which time is shown for directories, see `roll_up_times`.
+    pub(crate) dir_times: DirTimes,
+    /// Show the size of files in a right-aligned column at the start of each row.
+    pub(crate) size: Option<SizeFormat>,
+    /// Minimum width of the right-aligned columns.
+    pub(crate) column_width: usize,
+    /// Shown after the names of directories instead of `/`. Empty to show nothing.
+    pub(crate) dir_suffix: Option<String>,
+    /// Shown after the names of symbolic links, e.g. `@`.
+    pub(crate) symlink_indicator: Option<String>,
+    /// Shown after the names of executable files, e.g. `*`.
+    pub(crate) exec_indicator: Option<String>,
+    /// How symbolic links to directories are shown.
+    pub(crate) symdirs: SymdirPolicy,
+    /// Prefixes of full paths and their replacements, e.g. from `--strip-prefix` (replaced by
+    /// nothing) and `--map-prefix`. The first matching prefix is replaced.
+    pub(crate) path_prefixes: Vec<(String, String)>,
+    /// Tag well-known files with a badge, e.g. `Dockerfile [docker]`.
+    pub(crate) badges: Option<Badges>,
+    /// Show names as they are, without escaping invisible characters, see `escape_invisible`.
+    pub(crate) raw_names: bool,
+    /// Show full paths relative to this directory (from `fs_utils::absolute_path`) instead of
+    /// as given, before `path_prefixes` are replaced.
+    pub(crate) relative_to: Option<PathBuf>,
+    /// Color the branch symbols and names by nesting level for this theme, see `TreeItem::write_rows`.
+    pub(crate) depth_colors: Option<Theme>,
+    /// Show a type code like `DIR` or `LNK` in a leading column, see `type_code`.
+    pub(crate) type_column: bool,
+    /// Annotate sparse files with the share of their size that's allocated, see `sparse_share`.
+    pub(crate) sparse: bool,
+}
+
+impl RenderOptions {
+    /// Returns how many cells `decorate` adds to `TreeItem::leading`.
+    pub(crate) fn leading_column_count(&self) -> usize {
+        usize::from(self.type_column) + usize::from(self.size.is_some())
+    }
+
+    /// Returns `path` relative to `relative_to`, if set, with the first matching prefix of
+    /// `path_prefixes` replaced. A trailing `/` is kept.
+    pub(crate) fn rewrite_path(&self, path: &str) -> String {
+        let relative;
+        let path = match &self.relative_to {
+            Some(base) => {
+                let (trimmed, slash) = match path.strip_suffix('/') {
+                    Some(trimmed) if !trimmed.is_empty() => (trimmed, "/"),
+                    _ => (path, ""),
+                };
+                let absolute = fs_utils::absolute_path(Path::new(trimmed));
+                relative = format!("{}{}", fs_utils::relative_to(&absolute, base).to_string_lossy(), slash);
+                relative.as_str()
+            }
+            None => path,
+        };
+        for (prefix, replacement) in &self.path_prefixes {
+            if let Some(rest) = path.strip_prefix(prefix.as_str()) {
+                return format!("{}{}", replacement, rest);
+            }
+        }
+        path.to_string()
+    }
+}
+
+/// Parses a `--map-prefix` value like `/ci/workspace=.` into the prefix and its replacement.
+pub(crate) fn parse_prefix_map(text: &str) -> Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((prefix, replacement)) if !prefix.is_empty() => Ok((prefix.to_string(), replacement.to_string())),
+        _ => Err(format!("expected OLD=NEW, got '{}'", text)),
+    }
+}
+
 pub(crate) struct TreeItem {
     pub(crate) text: String,
     pub(crate) is_dir: bool,
     pub(crate) is_last: bool,
+    pub(crate) meta: Meta,
+    /// Right-aligned cells shown at the start of the row, before the branch symbols, e.g. the
+    /// size. `align_leading_columns` pads them, so they line up regardless of the depth.
+    pub(crate) leading: Vec<String>,
+    /// Extra information shown before the name, e.g. `[2024-03-31 14:05]`.
+    pub(crate) columns: Vec<String>,
+    /// Shown right after the name instead of the default `/` of directories, e.g. `@`.
+    pub(crate) suffix: Option<String>,
+    /// Extra information shown after the name, e.g. `[new]`.
+    pub(crate) annotations: Vec<String>,
     pub(crate) children: Vec<Rc<TreeItemRefCell>>,
     pub(crate) parent: Option<Weak<TreeItemRefCell>>,
+    /// Number of entries hidden by filters, shown in a placeholder row after the children.
+    pub(crate) hidden: usize,
+    /// Show invisible characters of the name as code points, see `escape_invisible`.
+    pub(crate) escape_name: bool,
 }
 
 impl TreeItem {
@@ -25,8 +280,15 @@ impl TreeItem {
             text,
             is_dir,
             is_last: true,
+            meta: Meta::default(),
+            leading: Vec::new(),
+            columns: Vec::new(),
+            suffix: None,
+            annotations: Vec::new(),
             children: Vec::new(),
             parent: None,
+            hidden: 0,
+            escape_name: false,
         }))
     }
     pub(crate) fn new(parent: &Rc<TreeItemRefCell>, text: String, is_dir: bool) -> Rc<TreeItemRefCell> {
@@ -34,8 +296,15 @@ impl TreeItem {
             text,
             is_dir,
             is_last: true,
+            meta: Meta::default(),
+            leading: Vec::new(),
+            columns: Vec::new(),
+            suffix: None,
+            annotations: Vec::new(),
             children: Vec::new(),
             parent: Some(Rc::downgrade(parent)),
+            hidden: 0,
+            escape_name: false,
         };
 
         let r_inst = Rc::new(RefCell::new(inst));
@@ -49,7 +318,8 @@ impl TreeItem {
 
 
     ///
-    /// Builds a string like:
+    /// Writes rows like the following to `out` one by one, each followed by a newline, so the
+    /// output starts before the whole tree is rendered:
     ///
     /// ```
     /// ./
@@ -66,54 +336,503 @@ impl TreeItem {
     /// └── meta.data
     /// ```
     ///
+    /// With `depth_colors`, the branch symbols and names are colored by nesting level with ANSI
+    /// escape codes of the theme, like rainbow brackets: each `│` has the color of the level it
+    /// belongs to.
+    ///
+    pub(crate) fn write_rows(&self, prefix_self: bool, depth_colors: Option<Theme>, out: &mut impl Write) -> io::Result<()> {
+        let leading: String = self.leading.iter().map(|cell| format!("{} ", cell)).collect();
+        if !prefix_self {
+            writeln!(out, "{}{}", leading, &self)?;
+        } else if let Some(theme) = depth_colors {
+            let symbols = self.row_symbols();
+            let colors = theme.depth_colors();
+            let color = colors[(symbols.len() - 1) % colors.len()];
+            writeln!(out, "{}{} {}", leading, paint_levels(&symbols, colors), self.render(Some(color)))?;
+        } else {
+            writeln!(out, "{}{}{}", leading, self.row_prefix(), &self)?;
+        }
+
+        for child in &self.children {
+            child.borrow().write_rows(true, depth_colors, out)?;
+        }
+        if self.hidden > 0 {
+            let blank: String = self.leading.iter().map(|cell| format!("{} ", " ".repeat(cell.chars().count()))).collect();
+            let symbols = self.placeholder_symbols();
+            let prefix = match depth_colors {
+                Some(theme) => paint_levels(&symbols, theme.depth_colors()),
+                None => symbols.join(""),
+            };
+            writeln!(out, "{}{} {}", blank, prefix, hidden_placeholder(self.hidden))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the rows of `write_rows` as a string without the trailing newline.
+    #[cfg(test)]
     pub(crate) fn to_row_str(&self, prefix_self: bool) -> String {
-        let mut mut_symbols: Vec<String> = Vec::new();
+        let mut out = Vec::new();
+        self.write_rows(prefix_self, None, &mut out).unwrap();
+        let mut rows = String::from_utf8(out).unwrap();
+        rows.pop();
+        rows
+    }
 
-        let prefix = if prefix_self {
-            fill_symbols(&mut mut_symbols, self, false);
-            mut_symbols.reverse();
-            let symbols_str = mut_symbols.join("");
-            format!("{} ", symbols_str)
-        } else {
-            String::new()
+    /// Returns the branch symbols of the placeholder row for hidden entries, which comes after
+    /// all children, one per level.
+    fn placeholder_symbols(&self) -> Vec<String> {
+        let mut symbols = vec![format!(" {}", LVL_SUFFIX_LAST)];
+        if self.parent.is_some() {
+            fill_symbols(&mut symbols, self, true);
+        }
+        symbols.reverse();
+        symbols
+    }
+
+    /// Returns the branch symbols that precede this item's name in the tree, e.g. ` │   ├── `.
+    pub(crate) fn row_prefix(&self) -> String {
+        format!("{} ", self.row_symbols().join(""))
+    }
+
+    /// Returns the branch symbols of `row_prefix`, one per level, e.g. ` │  ` and ` ├──`.
+    fn row_symbols(&self) -> Vec<String> {
+        let mut symbols: Vec<String> = Vec::new();
+        fill_symbols(&mut symbols, self, false);
+        symbols.reverse();
+        symbols
+    }
+
+    /// Returns the row text after the branch symbols: the columns, the name and the
+    /// annotations. The name is wrapped in the ANSI color code `name_color`, if given.
+    fn render(&self, name_color: Option<&str>) -> String {
+        let mut text: String = self.columns.iter().map(|column| format!("{} ", column)).collect();
+        let name = match &self.suffix {
+            Some(suffix) => format!("{}{}", self.text.replace('\\', "/"), suffix),
+            None => display_name(&self.text, self.is_dir),
         };
+        let name = if self.escape_name { escape_invisible(&name) } else { name };
+        match name_color {
+            Some(color) => text.push_str(&paint(color, &name)),
+            None => text.push_str(&name),
+        }
+        // Annotations may quote names and file contents, e.g. link targets and --first-line
+        for annotation in &self.annotations {
+            let annotation = if self.escape_name { escape_invisible(annotation) } else { annotation.clone() };
+            text.push_str(&format!(" {}", annotation));
+        }
+        text
+    }
 
-        let mut rows: Vec<String> = Vec::new();
-        rows.push(format!("{}{}", prefix, &self));
+    /// Returns whether this item is shown and counted as a directory under `symdirs`.
+    pub(crate) fn counts_as_dir(&self, symdirs: SymdirPolicy) -> bool {
+        self.is_dir || (self.meta.is_symdir && symdirs == SymdirPolicy::AsDir)
+    }
 
-        for child in &self.children {
-            rows.push(child.borrow().to_row_str(true));
+    /// Returns the file system path of this item by joining the texts of all its ancestors.
+    /// The top level item's text is the path that was passed to `ftree`.
+    pub(crate) fn path(&self) -> PathBuf {
+        match self.parent.as_ref().and_then(|p| p.upgrade()) {
+            Some(parent) => parent.borrow().path().join(&self.text),
+            None => PathBuf::from(&self.text),
         }
-        rows.join("\n")
+    }
+}
+
+/// Fills the columns of `item` according to `options`.
+pub(crate) fn decorate(item: &mut TreeItem, options: &RenderOptions) {
+    let as_dir = item.counts_as_dir(options.symdirs);
+    item.escape_name = !options.raw_names;
+    if options.type_column {
+        item.leading.push(type_code(item.is_dir, &item.meta).to_string());
+    }
+    if let Some(size_format) = options.size {
+        let size = if as_dir { String::new() } else { size_format.format(item.meta.size) };
+        item.leading.push(size);
+    }
+    if let Some(time_kind) = options.time {
+        let style = options.time_style;
+        let time = time_kind.of(&item.meta).map_or_else(|| "?".repeat(style.width()), |time| style.format(time, SystemTime::now()));
+        item.columns.push(format!("{:<width$}", format!("[{}]", time), width = style.width() + 2));
+    }
+    let name = Path::new(&item.text).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let badge = options.badges.as_ref().filter(|_| !as_dir).and_then(|badges| badges.badge_for(&name));
+    if let Some((badge, color)) = badge {
+        let badge = format!("[{}]", badge);
+        item.annotations.push(match options.depth_colors {
+            Some(theme) => paint(theme.depth_colors()[color % theme.depth_colors().len()], &badge),
+            None => badge,
+        });
+    }
+    if options.sparse && !as_dir {
+        item.annotations.extend(sparse_share(&item.meta).map(|share| format!("[sparse {}%]", share)));
+    }
+    item.suffix = if item.is_dir {
+        options.dir_suffix.clone()
+    } else if as_dir {
+        Some(options.dir_suffix.clone().unwrap_or_else(|| "/".to_string()))
+    } else if item.meta.is_symlink {
+        options.symlink_indicator.clone()
+    } else if item.meta.is_executable {
+        options.exec_indicator.clone()
+    } else {
+        None
+    };
+}
+
+/// Returns the share of the size of a sparse file that's allocated on disk, in percent, or
+/// `None` if the file isn't sparse: smaller than `SPARSE_MIN_SIZE` or at least half allocated.
+/// File systems that compress files allocate less than their size, too.
+fn sparse_share(meta: &Meta) -> Option<u64> {
+    let allocated = meta.allocated.filter(|allocated| meta.size >= SPARSE_MIN_SIZE && allocated * 2 < meta.size)?;
+    Some(allocated * 100 / meta.size)
+}
+
+/// Calls `decorate` for all descendants of `root`. A top level directory stands for the
+/// directory that was passed to `ftree`, so it only gets the directory suffix. A top level
+/// file is decorated like any other file.
+pub(crate) fn decorate_tree(root: &Rc<TreeItemRefCell>, options: &RenderOptions) {
+    if !root.borrow().is_dir {
+        decorate(&mut root.borrow_mut(), options);
+        return;
+    }
+    if let (Some(kind), DirTimes::Newest) = (options.time, options.dir_times) {
+        roll_up_times(root, kind);
+    }
+    root.borrow_mut().suffix = options.dir_suffix.clone();
+    root.borrow_mut().escape_name = !options.raw_names;
+    if options.type_column {
+        root.borrow_mut().leading.push(type_code(true, &Meta::default()).to_string());
+    }
+    decorate_descendants(root, options);
+}
+
+/// Sets the `kind` time of `item` and the directories below it to the newest `kind` time of the
+/// files anywhere below them, and returns it. Directories without files keep their own time,
+/// which doesn't count for the directories above.
+fn roll_up_times(item: &Rc<TreeItemRefCell>, kind: TimeKind) -> Option<SystemTime> {
+    let newest = item
+        .borrow()
+        .children
+        .iter()
+        .filter_map(|child| if child.borrow().is_dir { roll_up_times(child, kind) } else { kind.of(&child.borrow().meta) })
+        .max();
+    let newest = newest?;
+    let mut item = item.borrow_mut();
+    match kind {
+        TimeKind::Modified => item.meta.modified = Some(newest),
+        TimeKind::Created => item.meta.created = Some(newest),
+        TimeKind::Accessed => item.meta.accessed = Some(newest),
+    }
+    Some(newest)
+}
+
+fn decorate_descendants(item: &Rc<TreeItemRefCell>, options: &RenderOptions) {
+    for child in &item.borrow().children {
+        decorate(&mut child.borrow_mut(), options);
+        decorate_descendants(child, options);
+    }
+}
+
+/// Removes the files below `item` for which `keep_file` returns false, as well as directories
+/// that end up without any file. `keep_file` may modify the files it keeps, e.g. to annotate them.
+/// Returns whether `item` still has any children.
+pub(crate) fn prune_tree(item: &Rc<TreeItemRefCell>, keep_file: &mut impl FnMut(&mut TreeItem) -> bool) -> bool {
+    let children: Vec<_> = item.borrow().children.iter().map(Rc::clone).collect();
+    let kept: Vec<_> = children
+        .into_iter()
+        .filter(|child| {
+            if child.borrow().is_dir {
+                prune_tree(child, keep_file)
+            } else {
+                keep_file(&mut child.borrow_mut())
+            }
+        })
+        .collect();
+
+    if let Some(last) = kept.last() {
+        last.borrow_mut().is_last = true;
+    }
+    let mut item_ref = item.borrow_mut();
+    item_ref.children = kept;
+    !item_ref.children.is_empty()
+}
+
+/// Attaches the top level item `child` (with its descendants) as the last child of `parent`,
+/// e.g. to show several trees below a common top level item.
+pub(crate) fn graft(parent: &Rc<TreeItemRefCell>, child: Rc<TreeItemRefCell>) {
+    child.borrow_mut().parent = Some(Rc::downgrade(parent));
+    child.borrow_mut().is_last = true;
+    let mut parent_ref = parent.borrow_mut();
+    if let Some(last) = parent_ref.children.last() {
+        last.borrow_mut().is_last = false;
+    }
+    parent_ref.children.push(child);
+}
+
+/// Returns the number of entries below `item`, at any depth.
+pub(crate) fn count_entries(item: &Rc<TreeItemRefCell>) -> usize {
+    item.borrow().children.iter().map(|child| 1 + count_entries(child)).sum()
+}
+
+/// Keeps only the descendants of `item` for which `is_listed` returns true, given their path
+/// relative to `item` (with `/` separators) and whether they're a directory, together with
+/// their ancestors and descendants. Returns whether `item` still has any children.
+pub(crate) fn retain_listed(item: &Rc<TreeItemRefCell>, is_listed: &impl Fn(&str, bool) -> bool) -> bool {
+    retain_listed_below(item, "", is_listed)
+}
+
+fn retain_listed_below(item: &Rc<TreeItemRefCell>, rel_path: &str, is_listed: &impl Fn(&str, bool) -> bool) -> bool {
+    let children: Vec<_> = item.borrow().children.iter().map(Rc::clone).collect();
+    let kept: Vec<_> = children
+        .into_iter()
+        .filter(|child| {
+            let (child_path, is_dir) = {
+                let child_ref = child.borrow();
+                let child_path = if rel_path.is_empty() { child_ref.text.clone() } else { format!("{}/{}", rel_path, child_ref.text) };
+                (child_path, child_ref.is_dir)
+            };
+            is_listed(&child_path, is_dir) || (is_dir && retain_listed_below(child, &child_path, is_listed))
+        })
+        .collect();
+
+    if let Some(last) = kept.last() {
+        last.borrow_mut().is_last = true;
+    }
+    let mut item_ref = item.borrow_mut();
+    item_ref.children = kept;
+    !item_ref.children.is_empty()
+}
+
+/// How `fit_budget` ranks an entry, from the `--keep` and `--drop-first` rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Priority {
+    Keep,
+    Normal,
+    DropFirst,
+}
+
+///
+/// Drops the rows of the least important entries below `root` until the rows of `write_rows`
+/// fit in `budget` characters, e.g. to paste the tree of a project into a size-limited prompt.
+/// Entries are ranked by importance: first by their `priority`, then shallow ones before deep
+/// ones, and among those at the same depth the largest, by the total size of their files. The
+/// least important entries are dropped first, and an entry is only kept with its directory.
+///
+/// `priority` gets the path of an entry relative to `root` (with `/` separators) and whether
+/// it's a directory. Entries it ranks `Normal` inherit the priority of their directory, and the
+/// ancestors of `Keep` entries are kept first too.
+///
+/// Directories with dropped entries get a `… 7 hidden items` placeholder row, which counts
+/// towards the budget. The top level row is always kept, even if it alone exceeds the budget.
+/// The leading columns should be aligned already, as their width counts too, and so do the
+/// color codes of `depth_colors`, as `write_rows` writes them.
+///
+pub(crate) fn fit_budget(root: &Rc<TreeItemRefCell>, budget: usize, depth_colors: Option<Theme>, priority: &impl Fn(&str, bool) -> Priority) {
+    let mut items = Vec::new();
+    collect_items(root, &mut items);
+    let index: HashMap<*const TreeItemRefCell, usize> = items.iter().enumerate().map(|(i, item)| (Rc::as_ptr(item), i)).collect();
+    let parents: Vec<Option<usize>> = items
+        .iter()
+        .map(|item| item.borrow().parent.as_ref().and_then(Weak::upgrade).and_then(|parent| index.get(&Rc::as_ptr(&parent)).copied()))
+        .collect();
+
+    let mut depths = vec![0; items.len()];
+    let mut rel_paths = vec![String::new(); items.len()];
+    let mut priorities = vec![Priority::Normal; items.len()];
+    let mut sizes: Vec<u64> = items.iter().map(|item| if item.borrow().is_dir { 0 } else { item.borrow().meta.size }).collect();
+    for i in 1..items.len() {
+        let Some(parent) = parents[i] else { continue };
+        let item = items[i].borrow();
+        depths[i] = depths[parent] + 1;
+        rel_paths[i] = if parent == 0 { item.text.clone() } else { format!("{}/{}", rel_paths[parent], item.text) };
+        priorities[i] = match priority(&rel_paths[i], item.is_dir) {
+            Priority::Normal => priorities[parent],
+            own => own,
+        };
+    }
+    // Children come after their parent, so going backwards sums up bottom-up
+    for i in (1..items.len()).rev() {
+        if let Some(parent) = parents[i] {
+            sizes[parent] += sizes[i];
+            if priorities[i] == Priority::Keep {
+                priorities[parent] = Priority::Keep;
+            }
+        }
+    }
+
+    // The characters `paint` adds for the color of `level`, and for the branch symbols of `levels` levels
+    let colors = depth_colors.map_or(&[][..], |theme| theme.depth_colors());
+    let level_cost = |level: usize| if colors.is_empty() { 0 } else { paint("", "").chars().count() + colors[level % colors.len()].len() };
+    let paint_cost = |levels: usize| -> usize { (0..levels).map(level_cost).sum() };
+    let row_cost = |i: usize| {
+        let item = items[i].borrow();
+        let leading: usize = item.leading.iter().map(|cell| cell.chars().count() + 1).sum();
+        // The name is painted in the color of its branch symbol
+        let prefix = if i == 0 { 0 } else { 4 * depths[i] + 1 + paint_cost(depths[i]) + level_cost(depths[i] - 1) };
+        leading + prefix + item.render(None).chars().count() + 1
+    };
+    let placeholder_cost = |i: usize, dropped: usize| {
+        let item = items[i].borrow();
+        let count = dropped + item.hidden;
+        if count == 0 {
+            return 0;
+        }
+        let leading: usize = item.leading.iter().map(|cell| cell.chars().count() + 1).sum();
+        leading + 4 * (depths[i] + 1) + paint_cost(depths[i] + 1) + 1 + hidden_placeholder(count).chars().count() + 1
+    };
+    let child_counts: Vec<usize> = items.iter().map(|item| item.borrow().children.len()).collect();
+    let mut kept = vec![true; items.len()];
+    let mut kept_children = child_counts.clone();
+    let mut total: usize = (0..items.len()).map(|i| row_cost(i) + placeholder_cost(i, 0)).sum();
+
+    // Parents come before their children, so dropping from the end only ever drops leaves
+    let mut order: Vec<usize> = (1..items.len()).collect();
+    order.sort_by_key(|&i| (priorities[i], depths[i], Reverse(sizes[i]), i));
+    while total > budget {
+        let Some(i) = order.pop() else {
+            break;
+        };
+        let Some(parent) = parents[i] else {
+            continue;
+        };
+        let dropped = child_counts[parent] - kept_children[parent];
+        let removed = row_cost(i) + placeholder_cost(i, child_counts[i]) + placeholder_cost(parent, dropped);
+        total = total + placeholder_cost(parent, dropped + 1) - removed;
+        kept[i] = false;
+        kept_children[parent] -= 1;
+    }
+    for (i, item) in items.iter().enumerate().filter(|(i, _)| kept[*i] && kept_children[*i] < child_counts[*i]) {
+        let mut item = item.borrow_mut();
+        item.hidden += child_counts[i] - kept_children[i];
+        item.children.retain(|child| kept[index[&Rc::as_ptr(child)]]);
+        if let Some(last) = item.children.last() {
+            last.borrow_mut().is_last = true;
+        }
+    }
+}
+
+/// Right-aligns the leading cells of all items in the tree to the widest cell of their column,
+/// or `min_width` if that's wider. Items with fewer cells, like the top level item, get blank cells.
+pub(crate) fn align_leading_columns(root: &Rc<TreeItemRefCell>, min_width: usize) {
+    let mut items = Vec::new();
+    collect_items(root, &mut items);
+
+    let mut widths: Vec<usize> = Vec::new();
+    for item in &items {
+        for (i, cell) in item.borrow().leading.iter().enumerate() {
+            let width = cell.chars().count().max(min_width);
+            match widths.get_mut(i) {
+                Some(max) => *max = (*max).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+
+    for item in &items {
+        let mut item = item.borrow_mut();
+        item.leading = widths
+            .iter()
+            .enumerate()
+            .map(|(i, &width)| format!("{:>width$}", item.leading.get(i).map_or("", |c| c.as_str()), width = width))
+            .collect();
+    }
+}
+
+/// Prefixes the rows below and including `root` with their 1-based number in the output, in a
+/// leading column before all others. Placeholder rows for hidden entries aren't numbered.
+pub(crate) fn number_rows(root: &Rc<TreeItemRefCell>) {
+    let mut items = Vec::new();
+    collect_items(root, &mut items);
+    for (i, item) in items.iter().enumerate() {
+        item.borrow_mut().leading.insert(0, (i + 1).to_string());
+    }
+}
+
+/// Returns the full paths of the rows with the given numbers of `number_rows`, rewritten by
+/// `options.path_prefixes`, or the first number without a row.
+pub(crate) fn select_rows(root: &Rc<TreeItemRefCell>, numbers: &[usize], options: &RenderOptions) -> Result<Vec<String>, usize> {
+    let mut items = Vec::new();
+    collect_items(root, &mut items);
+    numbers
+        .iter()
+        .map(|&number| {
+            let item = number.checked_sub(1).and_then(|i| items.get(i)).ok_or(number)?;
+            Ok(options.rewrite_path(&item.borrow().path().to_string_lossy()))
+        })
+        .collect()
+}
+
+fn collect_items(item: &Rc<TreeItemRefCell>, items: &mut Vec<Rc<TreeItemRefCell>>) {
+    items.push(Rc::clone(item));
+    for child in &item.borrow().children {
+        collect_items(child, items);
     }
 }
 
 fn fill_symbols(symbols: &mut Vec<String>, curr_item: &TreeItem, sent_from_child: bool) {
+    let parent_strong = curr_item.parent.as_ref().and_then(|parent_weak| parent_weak.upgrade());
+    let parent_ref = parent_strong.as_ref().map(|parent| parent.borrow());
+
+    // The placeholder row for hidden entries comes after the last child
+    let is_last = curr_item.is_last && parent_ref.as_ref().is_none_or(|parent| parent.hidden == 0);
     let symbol = if sent_from_child {
-        format!(" {}", if curr_item.is_last { PARENT_IS_LAST } else { PARENT_IS_NOT_LAST })
+        format!(" {}", if is_last { PARENT_IS_LAST } else { PARENT_IS_NOT_LAST })
     } else {
-        format!(" {}", if curr_item.is_last { LVL_SUFFIX_LAST } else { LVL_SUFFIX })
+        format!(" {}", if is_last { LVL_SUFFIX_LAST } else { LVL_SUFFIX })
     };
     symbols.push(symbol.to_string());
 
-    if let Some(parent_weak) = &curr_item.parent {
-        if let Some(parent_strong) = parent_weak.upgrade() {
-            let parent_ref = parent_strong.borrow();
-            let parent = parent_ref.deref();
-
-            if parent.parent.is_some() {
-                fill_symbols(symbols, parent, true);
-            }
+    if let Some(parent_ref) = parent_ref {
+        let parent = parent_ref.deref();
+        if parent.parent.is_some() {
+            fill_symbols(symbols, parent, true);
         }
     }
 }
 
+/// Returns the text of the placeholder row for `count` hidden entries, e.g. `… 7 hidden items`.
+pub(crate) fn hidden_placeholder(count: usize) -> String {
+    match count {
+        1 => i18n::tr!("… {} hidden item", count),
+        _ => i18n::tr!("… {} hidden items", count),
+    }
+}
+
+/// Returns `text` as it's displayed in the tree: with `/` separators and a trailing `/` for directories.
+pub(crate) fn display_name(text: &str, is_dir: bool) -> String {
+    let name = text.replace("\\", "/");
+    let trail = if is_dir && !name.ends_with("/") { "/" } else { "" };
+    format!("{}{}", name, trail)
+}
+
+/// Returns `text` with the characters of `INVISIBLE_CHARS` shown as code points, e.g.
+/// `invoice<U+202E>fdp.exe` rather than `invoiceexe.pdf`, so a name can't pass for another one
+/// in a shared tree.
+pub(crate) fn escape_invisible(text: &str) -> String {
+    if !text.contains(is_invisible) {
+        return text.to_string();
+    }
+    text.chars().map(|c| if is_invisible(c) { format!("<U+{:04X}>", c as u32) } else { c.to_string() }).collect()
+}
+
+/// Returns whether `c` is one of `INVISIBLE_CHARS`.
+fn is_invisible(c: char) -> bool {
+    INVISIBLE_CHARS.iter().any(|(first, last)| (*first..=*last).contains(&c))
+}
+
+/// Returns `text` wrapped in the ANSI color code `color` and a reset.
+fn paint(color: &str, text: &str) -> String {
+    format!("\x1b[{}m{}\x1b[0m", color, text)
+}
+
+/// Joins branch symbols, one per level, each in the color of its level from `colors`.
+fn paint_levels(symbols: &[String], colors: &[&str]) -> String {
+    symbols.iter().enumerate().map(|(level, symbol)| paint(colors[level % colors.len()], symbol)).collect()
+}
+
 impl Display for TreeItem {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let name = self.text.replace("\\", "/");
-        let trail = if self.is_dir && !name.ends_with("/") { "/" } else { "" };
-        let to_display = format!("{}{}", name, trail);
-        write!(f, "{}", to_display)
+        write!(f, "{}", self.render(None))
     }
 }
 
@@ -179,14 +898,407 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn to_row_str_aligns_leading_columns() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let folder = TreeItem::new(&root, "folder".to_string(), true);
+        let small = TreeItem::new(&folder, "small.txt".to_string(), false);
+        let big = TreeItem::new(&root, "big.bin".to_string(), false);
+        small.borrow_mut().meta.size = 5;
+        big.borrow_mut().meta.size = 12345;
+        let options = RenderOptions { size: Some(SizeFormat::Bytes), ..Default::default() };
+
+        decorate_tree(&root, &options);
+        align_leading_columns(&root, 0);
+
+        let result = root.borrow().to_row_str(false);
+        let expected = "       root/\n        ├── folder/\n     5  │   └── small.txt\n12,345  └── big.bin";
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn number_rows_and_select_them() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let folder = TreeItem::new(&root, "folder".to_string(), true);
+        TreeItem::new(&folder, "file.txt".to_string(), false);
+        TreeItem::new(&root, "readme.md".to_string(), false);
+        let options = RenderOptions::default();
+
+        number_rows(&root);
+        align_leading_columns(&root, 0);
+
+        let expected = "1 root/\n2  ├── folder/\n3  │   └── file.txt\n4  └── readme.md";
+        assert_eq!(root.borrow().to_row_str(false), expected);
+        let selected = select_rows(&root, &[3, 1], &options).unwrap();
+        assert_eq!(selected, vec![PathBuf::from("root").join("folder").join("file.txt").to_string_lossy().to_string(), "root".to_string()]);
+        assert_eq!(select_rows(&root, &[2, 5], &options), Err(5));
+        assert_eq!(select_rows(&root, &[0], &options), Err(0));
+    }
+
+    #[test]
+    fn to_row_str_with_type_column() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let link = TreeItem::new(&root, "link".to_string(), false);
+        let socket = TreeItem::new(&root, "app.sock".to_string(), false);
+        let file = TreeItem::new(&root, "data.bin".to_string(), false);
+        link.borrow_mut().meta.is_symlink = true;
+        socket.borrow_mut().meta.special = Some(SpecialFile::Socket);
+        file.borrow_mut().meta.size = 1234;
+        let options = RenderOptions { type_column: true, size: Some(SizeFormat::Bytes), ..Default::default() };
+
+        decorate_tree(&root, &options);
+        align_leading_columns(&root, 0);
+
+        let result = root.borrow().to_row_str(false);
+        let expected = "DIR       root/\nLNK     0  ├── link\nSOC     0  ├── app.sock\nFIL 1,234  └── data.bin";
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn decorate_flags_sparse_files() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let files = [("disk.img", 1_000_000, Some(20_000)), ("db.sqlite", 1_000_000, Some(1_000_000)), ("small", 4096, Some(0)), ("parsed", 1_000_000, None)];
+        for (name, size, allocated) in files {
+            let file = TreeItem::new(&root, name.to_string(), false);
+            file.borrow_mut().meta = Meta { size, allocated, ..Default::default() };
+        }
+
+        decorate_tree(&root, &RenderOptions { sparse: true, ..Default::default() });
+
+        let result = root.borrow().to_row_str(false);
+        assert_eq!(result, "root/\n ├── disk.img [sparse 2%]\n ├── db.sqlite\n ├── small\n └── parsed");
+    }
+
+    #[test]
+    fn decorate_tree_sets_suffixes() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let folder = TreeItem::new(&root, "folder".to_string(), true);
+        let link = TreeItem::new(&folder, "link".to_string(), false);
+        let script = TreeItem::new(&root, "run.sh".to_string(), false);
+        link.borrow_mut().meta.is_symlink = true;
+        script.borrow_mut().meta.is_executable = true;
+        let options = RenderOptions {
+            dir_suffix: Some(String::new()),
+            symlink_indicator: Some("@".to_string()),
+            exec_indicator: Some("*".to_string()),
+            ..Default::default()
+        };
+
+        decorate_tree(&root, &options);
+
+        let result = root.borrow().to_row_str(false);
+        assert_eq!(result, "root\n ├── folder\n │   └── link@\n └── run.sh*");
+    }
+
+    #[test]
+    fn decorate_tree_escapes_invisible_characters() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        TreeItem::new(&root, "invoice\u{202E}fdp.exe".to_string(), false);
+        TreeItem::new(&root, "pass\u{200B}words".to_string(), true);
+
+        decorate_tree(&root, &RenderOptions::default());
+        assert_eq!(root.borrow().to_row_str(false), "root/\n ├── invoice<U+202E>fdp.exe\n └── pass<U+200B>words/");
+
+        decorate_tree(&root, &RenderOptions { raw_names: true, ..Default::default() });
+        assert_eq!(root.borrow().to_row_str(false), "root/\n ├── invoice\u{202E}fdp.exe\n └── pass\u{200B}words/");
+        assert_eq!(escape_invisible("plain ünïcode"), "plain ünïcode");
+        assert_eq!(escape_invisible("\u{FEFF}a\u{00AD}b\u{2061}c\u{2064}\u{E0041}"), "<U+FEFF>a<U+00AD>b<U+2061>c<U+2064><U+E0041>");
+    }
+
+    #[test]
+    fn decorate_tree_escapes_invisible_characters_in_annotations() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let link = TreeItem::new(&root, "link".to_string(), false);
+        link.borrow_mut().annotations.push("-> evil\u{202E}txt.sh".to_string());
+        let script = TreeItem::new(&root, "run.sh".to_string(), false);
+        script.borrow_mut().annotations.push("\"#!/bin/sh \u{200B}\"".to_string());
+
+        decorate_tree(&root, &RenderOptions::default());
+
+        assert_eq!(root.borrow().to_row_str(false), "root/\n ├── link -> evil<U+202E>txt.sh\n └── run.sh \"#!/bin/sh <U+200B>\"");
+    }
+
+    #[test]
+    fn decorate_tree_rolls_up_newest_times() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let src = TreeItem::new(&root, "src".to_string(), true);
+        let nested = TreeItem::new(&src, "nested".to_string(), true);
+        let empty = TreeItem::new(&root, "empty".to_string(), true);
+        let day = |days: u64| Some(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(days * 86_400));
+        for (item, modified) in [(&root, 1), (&src, 1), (&nested, 1), (&empty, 5)] {
+            item.borrow_mut().meta.modified = day(modified);
+        }
+        TreeItem::new(&src, "old.rs".to_string(), false).borrow_mut().meta.modified = day(2);
+        TreeItem::new(&nested, "new.rs".to_string(), false).borrow_mut().meta.modified = day(3);
+
+        let options = RenderOptions { time: Some(TimeKind::Modified), dir_times: DirTimes::Newest, ..Default::default() };
+        decorate_tree(&root, &options);
+
+        assert_eq!(src.borrow().meta.modified, day(3));
+        assert_eq!(nested.borrow().meta.modified, day(3));
+        assert_eq!(empty.borrow().meta.modified, day(5));
+        assert_eq!(root.borrow().meta.modified, day(3));
+    }
+
+    #[test]
+    fn decorate_tree_adds_badges() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        TreeItem::new(&root, "Dockerfile".to_string(), false);
+        TreeItem::new(&root, "main.rs".to_string(), false);
+        TreeItem::new(&root, "README".to_string(), true);
+        let badges = Badges::new(&[]).unwrap();
+
+        decorate_tree(&root, &RenderOptions { badges: Some(badges.clone()), ..Default::default() });
+        assert_eq!(root.borrow().to_row_str(false), "root/\n ├── Dockerfile [docker]\n ├── main.rs\n └── README/");
+
+        let options = RenderOptions { badges: Some(badges), depth_colors: Some(Theme::Dark), ..Default::default() };
+        let dockerfile = TreeItem::new_top_level("Dockerfile".to_string(), false);
+        decorate_tree(&dockerfile, &options);
+        assert_eq!(dockerfile.borrow().annotations, vec!["\x1b[34m[docker]\x1b[0m"]);
+    }
+
+    #[test]
+    fn decorate_tree_applies_symdir_policy() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let link = TreeItem::new(&root, "docs".to_string(), false);
+        link.borrow_mut().meta.is_symlink = true;
+        link.borrow_mut().meta.is_symdir = true;
+        let mut options = RenderOptions { symlink_indicator: Some("@".to_string()), ..Default::default() };
+
+        decorate_tree(&root, &options);
+        assert_eq!(root.borrow().to_row_str(false), "root/\n └── docs@");
+
+        options.symdirs = SymdirPolicy::AsDir;
+        decorate_tree(&root, &options);
+        assert_eq!(root.borrow().to_row_str(false), "root/\n └── docs/");
+    }
+
+    #[test]
+    fn to_row_str_shows_hidden_placeholder() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let folder = TreeItem::new(&root, "folder".to_string(), true);
+        TreeItem::new(&folder, "file.txt".to_string(), false);
+        folder.borrow_mut().hidden = 1;
+        root.borrow_mut().hidden = 3;
+
+        let result = root.borrow().to_row_str(false);
+
+        let expected = "root/\n ├── folder/\n │   ├── file.txt\n │   └── … 1 hidden item\n └── … 3 hidden items";
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn colors_allowed_by_environment() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string())
+        };
+
+        assert!(colors_allowed(&env(&[]), false));
+        assert!(colors_allowed(&env(&[("NO_COLOR", "")]), false));
+        assert!(!colors_allowed(&env(&[("NO_COLOR", "1")]), false));
+        assert!(!colors_allowed(&env(&[]), true));
+        assert!(colors_allowed(&env(&[("WT_SESSION", "c2f0")]), true));
+        assert!(colors_allowed(&env(&[("TERM", "xterm-256color")]), true));
+        assert!(!colors_allowed(&env(&[("ConEmuANSI", "OFF")]), true));
+        assert!(!colors_allowed(&env(&[("WT_SESSION", "c2f0"), ("NO_COLOR", "1")]), true));
+    }
+
+    #[test]
+    fn write_rows_with_depth_colors() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let folder = TreeItem::new(&root, "folder".to_string(), true);
+        TreeItem::new(&folder, "file.txt".to_string(), false);
+        folder.borrow_mut().hidden = 1;
+
+        let mut out = Vec::new();
+        root.borrow().write_rows(false, Some(Theme::Dark), &mut out).unwrap();
+
+        let expected = [
+            "root/",
+            "\x1b[33m └──\x1b[0m \x1b[33mfolder/\x1b[0m",
+            "\x1b[33m    \x1b[0m\x1b[35m ├──\x1b[0m \x1b[35mfile.txt\x1b[0m",
+            "\x1b[33m    \x1b[0m\x1b[35m └──\x1b[0m … 1 hidden item",
+            "",
+        ];
+        assert_eq!(String::from_utf8(out).unwrap(), expected.join("\n"));
+        let mut out = Vec::new();
+        root.borrow().write_rows(false, Some(Theme::Light), &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("root/\n\x1b[34m └──\x1b[0m \x1b[34mfolder/\x1b[0m\n"));
+    }
+
+    #[test]
+    fn theme_from_colorfgbg() {
+        assert_eq!(Theme::from_colorfgbg("15;0"), Some(Theme::Dark));
+        assert_eq!(Theme::from_colorfgbg("0;15"), Some(Theme::Light));
+        assert_eq!(Theme::from_colorfgbg("0;default;7"), Some(Theme::Light));
+        assert_eq!(Theme::from_colorfgbg("7;8"), Some(Theme::Dark));
+        assert_eq!(Theme::from_colorfgbg("default;default"), None);
+    }
+
+    #[test]
+    fn path_joins_ancestors() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let folder = TreeItem::new(&root, "folder".to_string(), true);
+        let file = TreeItem::new(&folder, "file.txt".to_string(), false);
+
+        assert_eq!(root.borrow().path(), PathBuf::from("root"));
+        assert_eq!(file.borrow().path(), PathBuf::from("root").join("folder").join("file.txt"));
+    }
+
+    #[test]
+    fn prune_tree_removes_empty_directories() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let folder = TreeItem::new(&root, "folder".to_string(), true);
+        TreeItem::new(&folder, "drop.txt".to_string(), false);
+        TreeItem::new(&root, "keep.txt".to_string(), false);
+        TreeItem::new(&root, "drop.txt".to_string(), false);
+
+        let has_children = prune_tree(&root, &mut |item| item.text == "keep.txt");
+
+        assert!(has_children);
+        let result = root.borrow().to_row_str(false);
+        assert_eq!(result, "root/\n └── keep.txt");
+    }
+
+    #[test]
+    fn rewrite_path_replaces_first_matching_prefix() {
+        let options = RenderOptions {
+            path_prefixes: vec![("/ci/ws/".to_string(), String::new()), parse_prefix_map("/ci=ci:").unwrap()],
+            ..Default::default()
+        };
+
+        assert_eq!(options.rewrite_path("/ci/ws/src/main.rs"), "src/main.rs");
+        assert_eq!(options.rewrite_path("/ci/cache"), "ci:/cache");
+        assert_eq!(options.rewrite_path("./src"), "./src");
+        assert!(parse_prefix_map("=x").is_err());
+
+        let options = RenderOptions { relative_to: Some(PathBuf::from("/repo/sub")), path_prefixes: vec![parse_prefix_map("../=up/").unwrap()], ..Default::default() };
+        assert_eq!(options.rewrite_path("/repo/sub/src/"), "src/");
+        assert_eq!(options.rewrite_path("/repo/lib/util.rs"), "up/lib/util.rs");
+        assert!(parse_prefix_map("/a").is_err());
+    }
+
+    #[test]
+    fn graft_attaches_trees() {
+        let merged = TreeItem::new_top_level("all".to_string(), true);
+        for name in ["a", "b"] {
+            let root = TreeItem::new_top_level(format!("pkgs/{}", name), true);
+            TreeItem::new(&root, "lib.rs".to_string(), false);
+            graft(&merged, root);
+        }
+
+        let result = merged.borrow().to_row_str(false);
+
+        assert_eq!(result, "all/\n ├── pkgs/a/\n │   └── lib.rs\n └── pkgs/b/\n     └── lib.rs");
+        let lib = Rc::clone(&merged.borrow().children[1].borrow().children[0]);
+        assert_eq!(lib.borrow().path(), PathBuf::from("all/pkgs/b/lib.rs"));
+    }
+
+    #[test]
+    fn retain_listed_keeps_ancestors_and_descendants() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let team = TreeItem::new(&root, "team".to_string(), true);
+        let owned = TreeItem::new(&team, "owned".to_string(), true);
+        TreeItem::new(&owned, "lib.rs".to_string(), false);
+        TreeItem::new(&team, "other.rs".to_string(), false);
+        TreeItem::new(&root, "README.md".to_string(), false);
+
+        let has_children = retain_listed(&root, &|rel_path, _| rel_path == "team/owned");
+
+        assert!(has_children);
+        let result = root.borrow().to_row_str(false);
+        assert_eq!(result, "root/\n └── team/\n     └── owned/\n         └── lib.rs");
+    }
+
+    #[test]
+    fn fit_budget_keeps_shallow_and_large_entries() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let src = TreeItem::new(&root, "src".to_string(), true);
+        let main = TreeItem::new(&src, "main.rs".to_string(), false);
+        TreeItem::new(&src, "lib.rs".to_string(), false);
+        let big = TreeItem::new(&root, "big.bin".to_string(), false);
+        let small = TreeItem::new(&root, "small.txt".to_string(), false);
+        main.borrow_mut().meta.size = 10;
+        big.borrow_mut().meta.size = 100;
+        small.borrow_mut().meta.size = 50;
+
+        let all = root.borrow().to_row_str(false);
+        fit_budget(&root, all.chars().count() + 1, None, &|_, _| Priority::Normal);
+        assert_eq!(root.borrow().to_row_str(false), all);
+
+        fit_budget(&root, 75, None, &|_, _| Priority::Normal);
+
+        let result = root.borrow().to_row_str(false);
+        assert_eq!(result, "root/\n ├── src/\n │   └── … 2 hidden items\n ├── big.bin\n └── small.txt");
+        assert!(result.chars().count() < 75);
+    }
+
+    #[test]
+    fn fit_budget_counts_color_codes() {
+        let build = || {
+            let root = TreeItem::new_top_level("root".to_string(), true);
+            let src = TreeItem::new(&root, "src".to_string(), true);
+            for name in ["main.rs", "lib.rs", "cli.rs"] {
+                TreeItem::new(&src, name.to_string(), false);
+            }
+            TreeItem::new(&root, "README.md".to_string(), false);
+            root
+        };
+        let colored = |root: &Rc<TreeItemRefCell>| {
+            let mut out = Vec::new();
+            root.borrow().write_rows(false, Some(Theme::Dark), &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let all = colored(&build()).chars().count();
+
+        for budget in [all, all - 1, 120, 90] {
+            let root = build();
+            fit_budget(&root, budget, Some(Theme::Dark), &|_, _| Priority::Normal);
+            let output = colored(&root);
+            assert!(output.chars().count() <= budget, "{} > {}:\n{}", output.chars().count(), budget, output);
+            assert_eq!(output.chars().count() == all, budget >= all);
+        }
+    }
+
+    #[test]
+    fn fit_budget_follows_priority_rules() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let src = TreeItem::new(&root, "src".to_string(), true);
+        let auth = TreeItem::new(&src, "auth".to_string(), true);
+        TreeItem::new(&auth, "login.rs".to_string(), false);
+        TreeItem::new(&src, "main.rs".to_string(), false);
+        let tests = TreeItem::new(&root, "tests".to_string(), true);
+        TreeItem::new(&tests, "it.rs".to_string(), false);
+        let readme = TreeItem::new(&root, "README.md".to_string(), false);
+        readme.borrow_mut().meta.size = 100;
+        let priority = |rel_path: &str, _| match rel_path {
+            "src/auth/login.rs" => Priority::Keep,
+            "tests" | "README.md" => Priority::DropFirst,
+            _ => Priority::Normal,
+        };
+
+        fit_budget(&root, 100, None, &priority);
+
+        let result = root.borrow().to_row_str(false);
+        let expected = "root/\n ├── src/\n │   ├── auth/\n │   │   └── login.rs\n │   └── main.rs\n └── … 2 hidden items";
+        assert_eq!(result, expected);
+    }
+
     #[test]
     fn display() {
         let item = TreeItem {
             text: "test".to_string(),
             is_dir: true,
             is_last: false,
+            meta: Meta::default(),
+            leading: Vec::new(),
+            columns: Vec::new(),
+            suffix: None,
+            annotations: Vec::new(),
             children: Vec::new(),
             parent: None,
+            hidden: 0,
+            escape_name: false,
         };
         assert_eq!(format!("{}", item), "test/");
 
@@ -194,10 +1306,36 @@ mod tests {
             text: "file.txt".to_string(),
             is_dir: false,
             is_last: true,
+            meta: Meta::default(),
+            leading: Vec::new(),
+            columns: Vec::new(),
+            suffix: None,
+            annotations: Vec::new(),
             children: Vec::new(),
             parent: None,
+            hidden: 0,
+            escape_name: false,
         };
         assert_eq!(format!("{}", file_item), "file.txt");
     }
+
+    #[test]
+    fn display_with_columns_and_annotations() {
+        let file_item = TreeItem {
+            text: "file.txt".to_string(),
+            is_dir: false,
+            is_last: true,
+            meta: Meta::default(),
+            leading: Vec::new(),
+            columns: vec!["[2024-03-31 14:05]".to_string()],
+            suffix: None,
+            annotations: vec!["[new]".to_string()],
+            children: Vec::new(),
+            parent: None,
+            hidden: 0,
+            escape_name: false,
+        };
+        assert_eq!(format!("{}", file_item), "[2024-03-31 14:05] file.txt [new]");
+    }
 }
 
@@ -0,0 +1,108 @@
+use crate::tree::{TreeItem, TreeItemRefCell};
+use std::rc::Rc;
+
+/// Connectors that precede an entry's name, in box-drawing and ASCII charsets of `tree` and `ftree`.
+const CONNECTORS: [&str; 8] = ["├──", "└──", "├─", "└─", "|--", "`--", "+--", "\\--"];
+
+/// Width of one level of indentation, e.g. `│   `.
+const LEVEL_WIDTH: usize = 4;
+
+///
+/// Parses textual tree output of `tree` or `ftree` back into a tree, e.g.:
+///
+/// ```text
+/// ./                           .
+/// ├── src/                     |-- src
+/// │   └── main.rs              |   `-- main.rs
+/// └── README.md                `-- README.md
+/// ```
+///
+/// The first line is the top level item. The depth of every other line follows from the
+/// position of its connector, so the parser tolerates both charsets and the leading space of
+/// `ftree` output. Lines without a connector, like the summary of `tree`, are skipped.
+/// Entries with children or a trailing `/` are directories.
+///
+/// Returns `None` if `text` has no non-empty line.
+///
+pub(crate) fn parse_tree(text: &str) -> Option<Rc<TreeItemRefCell>> {
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let (root_text, root_is_dir) = split_dir_suffix(lines.next()?.trim());
+    let root = TreeItem::new_top_level(root_text, root_is_dir);
+
+    // The last item seen on each depth, where the root has depth 0
+    let mut stack = vec![Rc::clone(&root)];
+    for line in lines {
+        let Some((connector_pos, connector)) = find_connector(line) else {
+            continue;
+        };
+        let depth = (line[..connector_pos].chars().count() / LEVEL_WIDTH + 1).min(stack.len());
+        let name = line[connector_pos + connector.len()..].trim();
+        let (text, is_dir) = split_dir_suffix(name);
+
+        stack.truncate(depth);
+        let parent = &stack[depth - 1];
+        parent.borrow_mut().is_dir = true;
+        let item = TreeItem::new(parent, text, is_dir);
+        stack.push(item);
+    }
+    Some(root)
+}
+
+/// Returns the byte position and the connector of the first connector in `line`.
+fn find_connector(line: &str) -> Option<(usize, &'static str)> {
+    CONNECTORS
+        .iter()
+        .filter_map(|connector| line.find(connector).map(|pos| (pos, *connector)))
+        .min_by_key(|(pos, connector)| (*pos, usize::MAX - connector.len()))
+}
+
+fn split_dir_suffix(name: &str) -> (String, bool) {
+    match name.strip_suffix('/') {
+        Some(stripped) if !stripped.is_empty() => (stripped.to_string(), true),
+        _ => (name.to_string(), false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_tree_round_trips_ftree_output() {
+        let root = TreeItem::new_top_level("root".to_string(), true);
+        let folder = TreeItem::new(&root, "folder".to_string(), true);
+        TreeItem::new(&folder, "file_in_folder.txt".to_string(), false);
+        TreeItem::new(&folder, "empty".to_string(), true);
+        TreeItem::new(&root, "file_in_root.txt".to_string(), false);
+        let rendered = root.borrow().to_row_str(false);
+
+        let parsed = parse_tree(&rendered).unwrap();
+
+        assert_eq!(parsed.borrow().to_row_str(false), rendered);
+    }
+
+    #[test]
+    fn parse_tree_reads_ascii_tree_output() {
+        let text = ".\n|-- src\n|   `-- main.rs\n`-- README.md\n\n1 directory, 2 files\n";
+
+        let parsed = parse_tree(text).unwrap();
+
+        let expected = "./\n ├── src/\n │   └── main.rs\n └── README.md";
+        assert_eq!(parsed.borrow().to_row_str(false), expected);
+    }
+
+    #[test]
+    fn parse_tree_reads_tree_output_with_box_drawing() {
+        let text = ".\n├── a\n│   ├── b\n│   │   └── c\n│   └── d\n└── e\n";
+
+        let parsed = parse_tree(text).unwrap();
+
+        let expected = "./\n ├── a/\n │   ├── b/\n │   │   └── c\n │   └── d\n └── e";
+        assert_eq!(parsed.borrow().to_row_str(false), expected);
+    }
+
+    #[test]
+    fn parse_tree_empty_input() {
+        assert!(parse_tree("\n  \n").is_none());
+    }
+}
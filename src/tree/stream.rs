@@ -0,0 +1,127 @@
+use crate::fs_utils::{descends_into, is_collapsed, top_level_item, visible_entries, TraverseOptions, COLLAPSED_ANNOTATION};
+use crate::tree::{decorate, hidden_placeholder, RenderOptions, TreeItem, LVL_SUFFIX, LVL_SUFFIX_LAST, PARENT_IS_LAST, PARENT_IS_NOT_LAST};
+use std::io::{self, Write};
+
+/// Minimum width of the right-aligned leading columns, since they can't be measured in advance.
+/// Fits sizes up to `1023.9 KiB` or `9,999,999` bytes.
+const STREAM_COLUMN_WIDTH: usize = 9;
+
+///
+/// Writes the same output as `TreeItem::to_row_str`, but without building the tree first.
+/// Each row is written as soon as its directory has been read, and the entries of a directory
+/// are dropped as soon as its subtree is written.
+///
+/// Memory use is bounded by the entries of the directories along the current path, i.e. at most
+/// depth × the largest directory, instead of growing with the total number of entries.
+///
+/// Leading columns like the size are padded to a fixed width, so they stay aligned unless a
+/// value is wider than `STREAM_COLUMN_WIDTH` or `render_options.column_width`.
+///
+pub(crate) fn stream_tree(path: &str, options: &TraverseOptions, render_options: &RenderOptions, out: &mut impl Write) -> io::Result<()> {
+    let blank = vec![String::new(); render_options.leading_column_count()];
+    let root = top_level_item(path);
+    if !root.borrow().is_dir {
+        decorate(&mut root.borrow_mut(), render_options);
+        return writeln!(out, "{}{}", leading_str(&root.borrow().leading, render_options), root.borrow());
+    }
+    root.borrow_mut().suffix = render_options.dir_suffix.clone();
+    root.borrow_mut().escape_name = !render_options.raw_names;
+    let mut leading = blank;
+    if render_options.type_column {
+        leading[0] = "DIR".to_string();
+    }
+    writeln!(out, "{}{}", leading_str(&leading, render_options), root.borrow())?;
+    let mut prefix = String::new();
+    stream_dir(path, options, render_options, 1, &mut prefix, out)
+}
+
+fn stream_dir(
+    path: &str,
+    options: &TraverseOptions,
+    render_options: &RenderOptions,
+    depth: usize,
+    prefix: &mut String,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let (entries, hidden) = visible_entries(path, options, None);
+    let hidden = if options.count_hidden { hidden } else { 0 };
+
+    for (i, entry) in entries.iter().enumerate() {
+        // The placeholder row for hidden entries comes after the last entry
+        let is_last = i == entries.len() - 1 && hidden == 0;
+        let symbol = if is_last { LVL_SUFFIX_LAST } else { LVL_SUFFIX };
+        let item = TreeItem::new_top_level(entry.name.clone(), entry.is_dir);
+        item.borrow_mut().meta = entry.meta.clone();
+        if is_collapsed(entry, options) {
+            item.borrow_mut().annotations.push(COLLAPSED_ANNOTATION.to_string());
+        }
+        decorate(&mut item.borrow_mut(), render_options);
+        writeln!(out, "{}{} {} {}", leading_str(&item.borrow().leading, render_options), prefix, symbol, item.borrow())?;
+
+        if descends_into(path, entry, options, depth) {
+            let parent_symbol = if is_last { PARENT_IS_LAST } else { PARENT_IS_NOT_LAST };
+            let prefix_len = prefix.len();
+            prefix.push(' ');
+            prefix.push_str(parent_symbol);
+
+            let new_path = format!("{}/{}", path, entry.name);
+            stream_dir(&new_path, options, render_options, depth + 1, prefix, out)?;
+            prefix.truncate(prefix_len);
+        }
+    }
+    if hidden > 0 {
+        let blank = vec![String::new(); render_options.leading_column_count()];
+        writeln!(out, "{}{} {} {}", leading_str(&blank, render_options), prefix, LVL_SUFFIX_LAST, hidden_placeholder(hidden))?;
+    }
+    Ok(())
+}
+
+fn leading_str(cells: &[String], render_options: &RenderOptions) -> String {
+    let width = render_options.column_width.max(STREAM_COLUMN_WIDTH);
+    // The codes of the type column have a fixed width already
+    let fixed = usize::from(render_options.type_column);
+    cells
+        .iter()
+        .enumerate()
+        .map(|(i, cell)| if i < fixed { format!("{:<3} ", cell) } else { format!("{:>width$} ", cell, width = width) })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_utils::traverse_fs;
+    use crate::time_utils::TimeKind;
+    use crate::size_utils::SizeFormat;
+    use crate::tree::{align_leading_columns, decorate_tree};
+    use std::fs::{self, File};
+    use tempfile::TempDir;
+
+    #[test]
+    fn stream_tree_matches_to_row_str() {
+        // Prepare
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        fs::create_dir_all(temp_path.join("dir1/nested")).unwrap();
+        fs::create_dir(temp_path.join("dir2")).unwrap();
+        File::create(temp_path.join("dir1/nested/file1.txt")).unwrap();
+        File::create(temp_path.join("dir1/file2.txt")).unwrap();
+        File::create(temp_path.join("file3.txt")).unwrap();
+        fs::write(temp_path.join("dir1/nested/image.png"), b"\0").unwrap();
+        let path = temp_path.to_str().unwrap();
+        let options = TraverseOptions { text_only: true, count_hidden: true, ..Default::default() };
+        let render_options = RenderOptions { time: Some(TimeKind::Modified), size: Some(SizeFormat::Human), ..Default::default() };
+
+        // Call
+        let mut out = Vec::new();
+        stream_tree(path, &options, &render_options, &mut out).unwrap();
+
+        // Verify
+        let root = TreeItem::new_top_level(path.to_string(), true);
+        traverse_fs(path, &root, &options);
+        decorate_tree(&root, &render_options);
+        align_leading_columns(&root, STREAM_COLUMN_WIDTH);
+        let expected = format!("{}\n", root.borrow().to_row_str(false));
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+}
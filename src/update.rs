@@ -0,0 +1,273 @@
+use crate::hash::{sha256, to_hex};
+use std::cmp::Ordering;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/eum2o/ftree/releases/latest";
+
+/// Target triple of the release binary for this platform, as built by `.github/workflows/release.yml`.
+const TARGET: Option<&str> = if cfg!(all(target_os = "linux", target_arch = "x86_64")) {
+    Some("x86_64-unknown-linux-musl")
+} else if cfg!(all(target_os = "windows", target_arch = "x86_64")) {
+    Some("x86_64-pc-windows-gnu")
+} else if cfg!(all(target_os = "macos", target_arch = "x86_64")) {
+    Some("x86_64-apple-darwin")
+} else {
+    None
+};
+
+const BINARY_NAME: &str = if cfg!(windows) { "ftree.exe" } else { "ftree" };
+
+///
+/// Replaces the running executable with the binary of the latest GitHub release, if it's newer,
+/// or older and `allow_downgrade`:
+///
+/// 1. Looks up the latest release and its archive for this platform.
+/// 2. Downloads the archive and verifies it against the published SHA-256 checksum.
+/// 3. Extracts the binary next to the current executable and renames it over it, so the
+///    executable is never left half-written. If that fails, the current executable is kept,
+///    see `replace_exe`.
+///
+/// Downloads use `curl` and extraction uses `tar`, which ship with all supported platforms.
+///
+pub(crate) fn self_update(allow_downgrade: bool) -> io::Result<String> {
+    let target = TARGET.ok_or_else(|| other_error("there is no release binary for this platform, use `cargo install e2o-ftree`"))?;
+
+    let release = String::from_utf8_lossy(&download(LATEST_RELEASE_URL)?).to_string();
+    let tag = json_string_values(&release, "tag_name").into_iter().next().ok_or_else(|| other_error("release without a tag"))?;
+    let current = env!("CARGO_PKG_VERSION");
+    let latest = Version::parse(&tag).ok_or_else(|| other_error(&format!("release tag {} isn't a version", tag)))?;
+    match latest.cmp(&Version::parse(current).ok_or_else(|| other_error("invalid package version"))?) {
+        Ordering::Equal => return Ok(format!("ftree {} is up to date", current)),
+        Ordering::Less if !allow_downgrade => {
+            return Ok(format!("ftree {} is newer than the latest release {}, use --allow-downgrade to install it anyway", current, tag));
+        }
+        _ => {}
+    }
+    remove_old_executable();
+
+    let asset_urls = json_string_values(&release, "browser_download_url");
+    let archive_url = select_archive(&asset_urls, target).ok_or_else(|| other_error(&format!("release {} has no archive for {}", tag, target)))?;
+    let checksum_url = format!("{}.sha256sum", archive_url);
+
+    let archive = download(archive_url)?;
+    let checksum = String::from_utf8_lossy(&download(&checksum_url)?).to_string();
+    let expected = checksum.split_whitespace().next().unwrap_or_default().to_lowercase();
+    if to_hex(&sha256(&archive)) != expected {
+        return Err(other_error(&format!("checksum of {} doesn't match", archive_url)));
+    }
+
+    let exe = env::current_exe()?;
+    let work_dir = exe.with_extension("update");
+    fs::create_dir_all(&work_dir)?;
+    let result = install(&archive, archive_url, &work_dir, &exe);
+    let _ = fs::remove_dir_all(&work_dir);
+    result?;
+    Ok(format!("Updated ftree {} to {}", current, tag))
+}
+
+/// Removes the executable that `install` renamed out of the way on Windows, which can only be
+/// deleted once it doesn't run anymore, i.e. on the next start.
+pub(crate) fn remove_old_executable() {
+    if let Ok(exe) = env::current_exe() {
+        let _ = fs::remove_file(exe.with_extension("old"));
+    }
+}
+
+/// A semantic version like `1.2.0` or `v1.3.0-rc.1`, ordered by the rules of semver.org.
+#[derive(Debug, PartialEq, Eq)]
+struct Version {
+    numbers: [u64; 3],
+    /// Dot-separated pre-release identifiers, e.g. `rc` and `1`, empty for releases
+    pre: Vec<String>,
+}
+
+impl Version {
+    /// Parses `text` with an optional `v` prefix. Build metadata after `+` is ignored.
+    fn parse(text: &str) -> Option<Version> {
+        let text = text.trim_start_matches('v');
+        let text = text.split_once('+').map_or(text, |(version, _)| version);
+        let (core, pre) = text.split_once('-').map_or((text, None), |(core, pre)| (core, Some(pre)));
+        let numbers: Vec<u64> = core.split('.').map(|number| number.parse().ok()).collect::<Option<_>>()?;
+        let numbers = numbers.try_into().ok()?;
+        let pre = pre.map_or(Vec::new(), |pre| pre.split('.').map(str::to_string).collect());
+        Some(Version { numbers, pre })
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // A pre-release comes before its release, numeric identifiers before and below alphanumeric ones
+        let identifier = |id: &String| (id.parse::<u64>().map_or(1, |_| 0), id.parse::<u64>().unwrap_or(0), id.clone());
+        self.numbers.cmp(&other.numbers).then_with(|| match (self.pre.is_empty(), other.pre.is_empty()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => self.pre.iter().map(identifier).cmp(other.pre.iter().map(identifier)),
+        })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn install(archive: &[u8], archive_url: &str, work_dir: &Path, exe: &Path) -> io::Result<()> {
+    let archive_file = work_dir.join(archive_url.rsplit('/').next().unwrap_or("archive"));
+    fs::write(&archive_file, archive)?;
+    run(Command::new("tar").arg("-xf").arg(&archive_file).arg("-C").arg(work_dir))?;
+    let binary = find_file(work_dir, BINARY_NAME)?.ok_or_else(|| other_error(&format!("the archive doesn't contain {}", BINARY_NAME)))?;
+
+    // Windows doesn't allow replacing a running executable, but allows renaming it
+    replace_exe(&binary, exe, cfg!(windows))
+}
+
+/// Replaces `exe` by a copy of `binary`, after renaming it to `.old` if `move_aside`. On failure,
+/// `exe` is restored and the copy removed, so there's always a working executable.
+fn replace_exe(binary: &Path, exe: &Path, move_aside: bool) -> io::Result<()> {
+    // Copy next to the executable first, so the rename stays on the same file system
+    let new_exe = exe.with_extension("new");
+    let result = copy_executable(binary, &new_exe).and_then(|_| {
+        if !move_aside {
+            return fs::rename(&new_exe, exe);
+        }
+        let old_exe = exe.with_extension("old");
+        let _ = fs::remove_file(&old_exe);
+        fs::rename(exe, &old_exe)?;
+        fs::rename(&new_exe, exe).inspect_err(|_| {
+            let _ = fs::rename(&old_exe, exe);
+        })
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&new_exe);
+    }
+    result
+}
+
+fn copy_executable(binary: &Path, target: &Path) -> io::Result<()> {
+    fs::copy(binary, target)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(target, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+fn download(url: &str) -> io::Result<Vec<u8>> {
+    run(Command::new("curl").args(["-fsSL", "-H", "User-Agent: ftree", url]))
+}
+
+fn run(command: &mut Command) -> io::Result<Vec<u8>> {
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(other_error(&format!("{:?} failed: {}", command, String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    Ok(output.stdout)
+}
+
+fn find_file(dir: &Path, name: &str) -> io::Result<Option<PathBuf>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if let Some(found) = find_file(&path, name)? {
+                return Ok(Some(found));
+            }
+        } else if path.file_name().is_some_and(|file_name| file_name == name) {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+fn other_error(message: &str) -> io::Error {
+    io::Error::other(message.to_string())
+}
+
+/// Returns the archive for `target` among the asset URLs of a release, preferring `.tar.gz`.
+fn select_archive<'a>(asset_urls: &'a [String], target: &str) -> Option<&'a str> {
+    let archives = asset_urls.iter().filter(|url| url.contains(target) && !url.ends_with(".sha256sum"));
+    let mut archives: Vec<&String> = archives.collect();
+    archives.sort_by_key(|url| !url.ends_with(".tar.gz"));
+    archives.first().map(|url| url.as_str())
+}
+
+/// Returns the string values of all `"key": "value"` pairs in `json`. Enough for the flat
+/// fields of the GitHub API, without a JSON parser.
+fn json_string_values(json: &str, key: &str) -> Vec<String> {
+    let needle = format!("\"{}\"", key);
+    let mut values = Vec::new();
+    let mut rest = json;
+    while let Some(pos) = rest.find(&needle) {
+        rest = rest[pos + needle.len()..].trim_start();
+        let Some(value) = rest.strip_prefix(':').map(str::trim_start).and_then(|v| v.strip_prefix('"')) else {
+            continue;
+        };
+        if let Some(end) = value.find('"') {
+            values.push(value[..end].to_string());
+        }
+        rest = value;
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_exe_keeps_the_executable_on_failure() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let exe = temp_dir.path().join("ftree");
+        let binary = temp_dir.path().join("release/ftree");
+        fs::write(&exe, "old").unwrap();
+
+        assert!(replace_exe(&binary, &exe, true).is_err());
+        assert_eq!(fs::read_to_string(&exe).unwrap(), "old");
+        assert!(!exe.with_extension("new").exists());
+
+        fs::create_dir(temp_dir.path().join("release")).unwrap();
+        fs::write(&binary, "new").unwrap();
+        replace_exe(&binary, &exe, true).unwrap();
+        assert_eq!(fs::read_to_string(&exe).unwrap(), "new");
+        assert_eq!(fs::read_to_string(exe.with_extension("old")).unwrap(), "old");
+        assert!(!exe.with_extension("new").exists());
+    }
+
+    #[test]
+    fn versions_are_ordered_by_semver() {
+        let version = |text| Version::parse(text).unwrap();
+
+        assert!(version("v1.10.0") > version("1.9.3"));
+        assert!(version("1.2.0") > version("1.2.0-rc.2"));
+        assert!(version("1.2.0-rc.10") > version("1.2.0-rc.2"));
+        assert!(version("1.2.0-rc.1") > version("1.2.0-beta"));
+        assert!(version("1.2.0-beta") > version("1.2.0-1"));
+        assert_eq!(version("1.2.0+build.5"), version("v1.2.0"));
+        assert_eq!(Version::parse("1.2"), None);
+        assert_eq!(Version::parse("latest"), None);
+    }
+
+    #[test]
+    fn json_string_values_and_select_archive() {
+        let release = r#"{"tag_name": "v1.2.0", "assets": [
+            {"browser_download_url":"https://x/ftree_v1.2.0_x86_64-apple-darwin.zip"},
+            {"browser_download_url":"https://x/ftree_v1.2.0_x86_64-unknown-linux-musl.zip"},
+            {"browser_download_url":"https://x/ftree_v1.2.0_x86_64-unknown-linux-musl.tar.gz"},
+            {"browser_download_url":"https://x/ftree_v1.2.0_x86_64-unknown-linux-musl.tar.gz.sha256sum"}]}"#;
+
+        assert_eq!(json_string_values(release, "tag_name"), vec!["v1.2.0"]);
+        let urls = json_string_values(release, "browser_download_url");
+        assert_eq!(urls.len(), 4);
+        assert_eq!(select_archive(&urls, "x86_64-unknown-linux-musl"), Some("https://x/ftree_v1.2.0_x86_64-unknown-linux-musl.tar.gz"));
+        assert_eq!(select_archive(&urls, "x86_64-apple-darwin"), Some("https://x/ftree_v1.2.0_x86_64-apple-darwin.zip"));
+        assert_eq!(select_archive(&urls, "aarch64-apple-darwin"), None);
+    }
+}